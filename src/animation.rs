@@ -0,0 +1,912 @@
+//! Animated GIF, APNG and WebP inputs. The CLI only reads still images, so
+//! an animation is split into one PNG per frame, each frame is upscaled on
+//! its own, and the upscaled frames are put back together afterwards with
+//! their original timing.
+//!
+//! Frames are composited onto the full canvas before they are upscaled, as
+//! the partial frames an animation is stored as would not line up again
+//! once resampled. The reassembled animation is therefore made of full
+//! frames, which are drawn over each other without blending.
+
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use image::{
+    imageops, DynamicImage, GrayAlphaImage, GrayImage, ImageFormat, RgbImage, Rgba, RgbaImage,
+};
+use img_parts::riff::{RiffChunk, RiffContent};
+use img_parts::webp::WebP;
+use img_parts::Bytes;
+
+use crate::encode::{Encoding, PngCompression};
+use crate::metadata;
+
+const WEBP_VP8X: [u8; 4] = *b"VP8X";
+const WEBP_VP8: [u8; 4] = *b"VP8 ";
+const WEBP_VP8L: [u8; 4] = *b"VP8L";
+const WEBP_ALPH: [u8; 4] = *b"ALPH";
+const WEBP_ANIM: [u8; 4] = *b"ANIM";
+const WEBP_ANMF: [u8; 4] = *b"ANMF";
+
+/// What happens to the area of a frame once its delay is over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Disposal {
+    Keep,
+    Background,
+    Previous,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Blend {
+    Over,
+    Source,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// How long the frame is shown, as a fraction of a second.
+    pub delay: (u32, u32),
+    pub disposal: Disposal,
+}
+
+/// Everything about an animation but its pixels, which are kept in files
+/// while the frames are upscaled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation {
+    pub format: ImageFormat,
+    /// How many times the animation plays, as stored by its container, or
+    /// `None` if it loops forever.
+    pub loops: Option<u32>,
+    /// The WebP background colour, in RGBA.
+    pub background: [u8; 4],
+    pub frames: Vec<FrameInfo>,
+}
+
+/// A frame as stored, covering part of the canvas.
+struct RawFrame {
+    image: RgbaImage,
+    left: u32,
+    top: u32,
+    blend: Blend,
+    info: FrameInfo,
+}
+
+/// The name of every frame, as ffmpeg is given it.
+pub const FRAME_PATTERN: &str = "frame-%05d.png";
+
+/// The folder the frames of an animation are kept in at step `step`: as
+/// they are split at step 0, and as realesrgan pass `step - 1` writes them
+/// after that, as the CLI is run over a whole folder at a time.
+pub fn step_dir(dir: &Path, step: u32) -> PathBuf {
+    dir.join(format!("step-{}", step))
+}
+
+pub fn frame_file_name(frame: u32) -> String {
+    format!("frame-{:05}.png", frame)
+}
+
+/// Where frame `frame` of an animation is written to before upscaling.
+pub fn frame_path(dir: &Path, frame: u32) -> PathBuf {
+    step_dir(dir, 0).join(frame_file_name(frame))
+}
+
+/// Counts the frames of `path`, which is 1 for still images. Only the
+/// headers of the frames are read.
+pub fn frame_count(path: &Path, format: ImageFormat) -> Result<u32, String> {
+    match format {
+        ImageFormat::Gif => {
+            let mut options = gif::DecodeOptions::new();
+            options.skip_frame_decoding(true);
+
+            let file = File::open(path).map_err(|e| e.to_string())?;
+            let mut decoder = options
+                .read_info(BufReader::new(file))
+                .map_err(|e| e.to_string())?;
+
+            let mut frames = 0;
+
+            while decoder
+                .read_next_frame()
+                .map_err(|e| e.to_string())?
+                .is_some()
+            {
+                frames += 1;
+            }
+
+            Ok(frames)
+        }
+        ImageFormat::Png => {
+            let file = File::open(path).map_err(|e| e.to_string())?;
+            let reader = png::Decoder::new(BufReader::new(file))
+                .read_info()
+                .map_err(|e| e.to_string())?;
+
+            Ok(reader
+                .info()
+                .animation_control
+                .map_or(1, |control| control.num_frames))
+        }
+        ImageFormat::WebP => {
+            let webp = read_webp(path)?;
+
+            Ok(match webp.chunks_by_id(WEBP_ANMF).count() {
+                0 => 1,
+                frames => frames as u32,
+            })
+        }
+        _ => Ok(1),
+    }
+}
+
+/// Composites every frame of `input` onto the full canvas and writes it to
+/// `dir`, as named by `frame_path`.
+pub fn split(input: &Path, dir: &Path) -> Result<Animation, String> {
+    // The extension may be wrong, so the format is guessed again here.
+    let format = image::io::Reader::open(input)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| e.to_string())?
+        .format()
+        .ok_or_else(|| String::from("not an image in a supported format"))?;
+
+    fs::create_dir_all(step_dir(dir, 0)).map_err(|e| e.to_string())?;
+
+    let mut frames = Vec::new();
+    let mut write_frame = |canvas: &mut Canvas, frame: RawFrame| {
+        let image = canvas.draw(&frame);
+
+        image
+            .save_with_format(frame_path(dir, frames.len() as u32), ImageFormat::Png)
+            .map_err(|e| e.to_string())?;
+
+        frames.push(frame.info);
+        Ok::<(), String>(())
+    };
+
+    let (loops, background) = match format {
+        ImageFormat::Gif => (split_gif(input, &mut write_frame)?, [0; 4]),
+        ImageFormat::Png => (split_apng(input, &mut write_frame)?, [0; 4]),
+        ImageFormat::WebP => split_webp(input, &mut write_frame)?,
+        _ => return Err(String::from("not an animation")),
+    };
+
+    if frames.is_empty() {
+        return Err(String::from("the animation has no frames"));
+    }
+
+    Ok(Animation {
+        format,
+        loops,
+        background,
+        frames,
+    })
+}
+
+fn split_gif(
+    input: &Path,
+    write_frame: &mut impl FnMut(&mut Canvas, RawFrame) -> Result<(), String>,
+) -> Result<Option<u32>, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+
+    let file = File::open(input).map_err(|e| e.to_string())?;
+    let mut decoder = options
+        .read_info(BufReader::new(file))
+        .map_err(|e| e.to_string())?;
+
+    let mut canvas = Canvas::new(decoder.width().into(), decoder.height().into());
+
+    while let Some(frame) = decoder.read_next_frame().map_err(|e| e.to_string())? {
+        let image = RgbaImage::from_raw(
+            frame.width.into(),
+            frame.height.into(),
+            frame.buffer.to_vec(),
+        )
+        .ok_or_else(|| String::from("a frame is truncated"))?;
+
+        let disposal = match frame.dispose {
+            gif::DisposalMethod::Any | gif::DisposalMethod::Keep => Disposal::Keep,
+            gif::DisposalMethod::Background => Disposal::Background,
+            gif::DisposalMethod::Previous => Disposal::Previous,
+        };
+
+        write_frame(
+            &mut canvas,
+            RawFrame {
+                image,
+                left: frame.left.into(),
+                top: frame.top.into(),
+                blend: Blend::Over,
+                info: FrameInfo {
+                    delay: (frame.delay.into(), 100),
+                    disposal,
+                },
+            },
+        )?;
+    }
+
+    // The loop count may come after the first frame, so it is only known
+    // once all of them have been read. A GIF without one plays once.
+    Ok(match decoder.repeat() {
+        gif::Repeat::Infinite => None,
+        gif::Repeat::Finite(loops) => Some(loops.into()),
+    })
+}
+
+fn split_apng(
+    input: &Path,
+    write_frame: &mut impl FnMut(&mut Canvas, RawFrame) -> Result<(), String>,
+) -> Result<Option<u32>, String> {
+    let file = File::open(input).map_err(|e| e.to_string())?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let (width, height) = reader.info().size();
+    let control = reader
+        .info()
+        .animation_control
+        .ok_or_else(|| String::from("not an animation"))?;
+
+    let mut canvas = Canvas::new(width, height);
+    let mut buffer = vec![0; reader.output_buffer_size()];
+
+    // A default image without a frame control is not part of the animation.
+    if reader.info().frame_control.is_none() {
+        reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+    }
+
+    for _ in 0..control.num_frames {
+        let output = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+        let control = reader
+            .info()
+            .frame_control
+            .ok_or_else(|| String::from("a frame has no frame control"))?;
+
+        let image = png_to_rgba(&buffer[..output.buffer_size()], &output)?;
+
+        let disposal = match control.dispose_op {
+            png::DisposeOp::None => Disposal::Keep,
+            png::DisposeOp::Background => Disposal::Background,
+            png::DisposeOp::Previous => Disposal::Previous,
+        };
+
+        let blend = match control.blend_op {
+            png::BlendOp::Source => Blend::Source,
+            png::BlendOp::Over => Blend::Over,
+        };
+
+        // A denominator of 0 stands for hundredths of a second.
+        let delay_den = match control.delay_den {
+            0 => 100,
+            den => den,
+        };
+
+        write_frame(
+            &mut canvas,
+            RawFrame {
+                image,
+                left: control.x_offset,
+                top: control.y_offset,
+                blend,
+                info: FrameInfo {
+                    delay: (control.delay_num.into(), delay_den.into()),
+                    disposal,
+                },
+            },
+        )?;
+    }
+
+    Ok(match control.num_plays {
+        0 => None,
+        plays => Some(plays),
+    })
+}
+
+fn png_to_rgba(data: &[u8], output: &png::OutputInfo) -> Result<RgbaImage, String> {
+    let (width, height) = (output.width, output.height);
+    let data = data.to_vec();
+
+    let image = match output.color_type {
+        png::ColorType::Rgba => RgbaImage::from_raw(width, height, data).map(DynamicImage::from),
+        png::ColorType::Rgb => RgbImage::from_raw(width, height, data).map(DynamicImage::from),
+        png::ColorType::GrayscaleAlpha => {
+            GrayAlphaImage::from_raw(width, height, data).map(DynamicImage::from)
+        }
+        png::ColorType::Grayscale => {
+            GrayImage::from_raw(width, height, data).map(DynamicImage::from)
+        }
+        png::ColorType::Indexed => None,
+    };
+
+    image
+        .map(|image| image.to_rgba8())
+        .ok_or_else(|| String::from("a frame could not be decoded"))
+}
+
+fn split_webp(
+    input: &Path,
+    write_frame: &mut impl FnMut(&mut Canvas, RawFrame) -> Result<(), String>,
+) -> Result<(Option<u32>, [u8; 4]), String> {
+    let webp = read_webp(input)?;
+    let (width, height) = metadata::webp_dimensions(&webp)
+        .ok_or_else(|| String::from("unable to read the dimensions of the animation"))?;
+
+    let anim = webp
+        .chunk_by_id(WEBP_ANIM)
+        .and_then(|chunk| chunk.content().data())
+        .filter(|data| data.len() >= 6)
+        .ok_or_else(|| String::from("not an animation"))?;
+
+    // The background colour is stored as BGRA.
+    let background = [anim[2], anim[1], anim[0], anim[3]];
+    let loops = match u16::from_le_bytes([anim[4], anim[5]]) {
+        0 => None,
+        loops => Some(loops.into()),
+    };
+
+    let mut canvas = Canvas::new(width, height);
+
+    for chunk in webp.chunks_by_id(WEBP_ANMF) {
+        let data = chunk
+            .content()
+            .data()
+            .filter(|data| data.len() >= 16)
+            .ok_or_else(|| String::from("a frame is truncated"))?;
+
+        let u24 = |at: usize| u32::from_le_bytes([data[at], data[at + 1], data[at + 2], 0]);
+        let flags = data[15];
+
+        let image = decode_webp_frame(&data[16..], u24(6) + 1, u24(9) + 1)?;
+
+        write_frame(
+            &mut canvas,
+            RawFrame {
+                image,
+                left: u24(0) * 2,
+                top: u24(3) * 2,
+                blend: if flags & 0x02 != 0 {
+                    Blend::Source
+                } else {
+                    Blend::Over
+                },
+                info: FrameInfo {
+                    delay: (u24(12), 1000),
+                    disposal: if flags & 0x01 != 0 {
+                        Disposal::Background
+                    } else {
+                        Disposal::Keep
+                    },
+                },
+            },
+        )?;
+    }
+
+    Ok((loops, background))
+}
+
+fn read_webp(path: &Path) -> Result<WebP, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    WebP::from_bytes(Bytes::from(bytes)).map_err(|e| e.to_string())
+}
+
+/// Decodes the image chunks of an ANMF chunk, by wrapping them in a WebP of
+/// their own.
+fn decode_webp_frame(data: &[u8], width: u32, height: u32) -> Result<RgbaImage, String> {
+    let mut chunks = Vec::new();
+    let mut rest = data;
+
+    while rest.len() >= 8 {
+        let id = [rest[0], rest[1], rest[2], rest[3]];
+        let size = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+        let contents = rest
+            .get(8..8 + size)
+            .ok_or_else(|| String::from("a frame is truncated"))?;
+
+        chunks.push(RiffChunk::new(
+            id,
+            RiffContent::Data(Bytes::copy_from_slice(contents)),
+        ));
+
+        // Chunks are padded to an even size.
+        rest = rest.get(8 + size + size % 2..).unwrap_or_default();
+    }
+
+    // An alpha chunk is only read behind an extended header.
+    if chunks.iter().any(|chunk| chunk.id() == WEBP_ALPH) {
+        chunks.insert(0, vp8x_chunk(0x10, width, height));
+    }
+
+    let bytes = webp_bytes(chunks)?;
+
+    image::load_from_memory_with_format(&bytes, ImageFormat::WebP)
+        .map(|image| image.to_rgba8())
+        .map_err(|e| e.to_string())
+}
+
+fn vp8x_chunk(flags: u8, width: u32, height: u32) -> RiffChunk {
+    let mut vp8x = vec![flags, 0, 0, 0];
+    vp8x.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+    vp8x.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+
+    RiffChunk::new(WEBP_VP8X, RiffContent::Data(Bytes::from(vp8x)))
+}
+
+fn webp_bytes(chunks: Vec<RiffChunk>) -> Result<Vec<u8>, String> {
+    let riff = RiffChunk::new(
+        *b"RIFF",
+        RiffContent::List {
+            kind: Some(*b"WEBP"),
+            subchunks: chunks,
+        },
+    );
+
+    let webp = WebP::new(riff).map_err(|e| e.to_string())?;
+    Ok(webp.encoder().bytes().to_vec())
+}
+
+/// The canvas frames are drawn onto, as a viewer would.
+struct Canvas {
+    image: RgbaImage,
+    /// How the last frame drawn is to be disposed of before the next one.
+    pending: Option<PendingDisposal>,
+}
+
+struct PendingDisposal {
+    disposal: Disposal,
+    /// The area of the frame, as left, top, width and height.
+    area: (u32, u32, u32, u32),
+    /// The canvas as it was before the frame, if it is to be restored.
+    previous: Option<RgbaImage>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            image: RgbaImage::new(width, height),
+            pending: None,
+        }
+    }
+
+    /// Draws `frame` and returns the full canvas as it is then shown.
+    fn draw(&mut self, frame: &RawFrame) -> RgbaImage {
+        if let Some(pending) = self.pending.take() {
+            match (pending.disposal, pending.previous) {
+                (Disposal::Background, _) => {
+                    let (left, top, width, height) = pending.area;
+                    let clear = RgbaImage::from_pixel(width, height, Rgba([0; 4]));
+                    imageops::replace(&mut self.image, &clear, left.into(), top.into());
+                }
+                (Disposal::Previous, Some(previous)) => self.image = previous,
+                _ => (),
+            }
+        }
+
+        let previous = (frame.info.disposal == Disposal::Previous).then(|| self.image.clone());
+        let (left, top) = (i64::from(frame.left), i64::from(frame.top));
+
+        match frame.blend {
+            Blend::Over => imageops::overlay(&mut self.image, &frame.image, left, top),
+            Blend::Source => imageops::replace(&mut self.image, &frame.image, left, top),
+        }
+
+        self.pending = Some(PendingDisposal {
+            disposal: frame.info.disposal,
+            area: (
+                frame.left,
+                frame.top,
+                frame.image.width(),
+                frame.image.height(),
+            ),
+            previous,
+        });
+
+        self.image.clone()
+    }
+}
+
+impl Animation {
+    /// Puts `frames`, the upscaled full frames, back together in the
+    /// container of the input. WebP frames are encoded with the settings of
+    /// `encoding` if it is a WebP one, and losslessly otherwise.
+    pub fn encode(
+        &self,
+        frames: impl Iterator<Item = Result<RgbaImage, String>>,
+        encoding: Encoding,
+    ) -> Result<Vec<u8>, String> {
+        let mut frames = frames.zip(&self.frames).peekable();
+
+        let (width, height) = match frames.peek() {
+            Some((Ok(frame), _)) => frame.dimensions(),
+            Some((Err(e), _)) => return Err(e.clone()),
+            None => return Err(String::from("the animation has no frames")),
+        };
+
+        match self.format {
+            ImageFormat::Gif => self.encode_gif(frames, width, height),
+            ImageFormat::Png => self.encode_apng(frames, width, height, encoding),
+            ImageFormat::WebP => self.encode_webp(frames, width, height, encoding),
+            _ => Err(String::from("not an animation")),
+        }
+    }
+
+    fn encode_gif<'a>(
+        &self,
+        frames: impl Iterator<Item = (Result<RgbaImage, String>, &'a FrameInfo)>,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>, String> {
+        let (width, height) = match (u16::try_from(width), u16::try_from(height)) {
+            (Ok(w), Ok(h)) => (w, h),
+            _ => return Err(String::from("The animation is too large for a GIF.")),
+        };
+
+        let mut encoder =
+            gif::Encoder::new(Vec::new(), width, height, &[]).map_err(|e| e.to_string())?;
+
+        // Without a loop count a GIF plays once, while a count of 0 stands
+        // for looping forever, so one is only written if there was one.
+        match self.loops {
+            None => encoder.set_repeat(gif::Repeat::Infinite),
+            Some(0) => Ok(()),
+            Some(loops) => {
+                encoder.set_repeat(gif::Repeat::Finite(loops.min(u16::MAX.into()) as u16))
+            }
+        }
+        .map_err(|e| e.to_string())?;
+
+        for (frame, info) in frames {
+            let mut pixels = frame?.into_raw();
+            let mut frame = gif::Frame::from_rgba_speed(width, height, &mut pixels, 10);
+
+            frame.delay = delay_in(info.delay, 100).min(u16::MAX.into()) as u16;
+            frame.dispose = match info.disposal {
+                Disposal::Keep => gif::DisposalMethod::Keep,
+                Disposal::Background => gif::DisposalMethod::Background,
+                Disposal::Previous => gif::DisposalMethod::Previous,
+            };
+
+            encoder.write_frame(&frame).map_err(|e| e.to_string())?;
+        }
+
+        encoder.into_inner().map_err(|e| e.to_string())
+    }
+
+    fn encode_apng<'a>(
+        &self,
+        frames: impl Iterator<Item = (Result<RgbaImage, String>, &'a FrameInfo)>,
+        width: u32,
+        height: u32,
+        encoding: Encoding,
+    ) -> Result<Vec<u8>, String> {
+        let mut buffer = Vec::new();
+        let mut encoder = png::Encoder::new(&mut buffer, width, height);
+
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(match encoding {
+            Encoding::Png {
+                compression: PngCompression::Fast,
+            } => png::Compression::Fast,
+            Encoding::Png {
+                compression: PngCompression::Best,
+            } => png::Compression::Best,
+            _ => png::Compression::Default,
+        });
+        encoder
+            .set_animated(self.frames.len() as u32, self.loops.unwrap_or(0))
+            .map_err(|e| e.to_string())?;
+
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+
+        for (frame, info) in frames {
+            let frame = frame?;
+
+            let (num, den) = match (u16::try_from(info.delay.0), u16::try_from(info.delay.1)) {
+                (Ok(num), Ok(den)) => (num, den),
+                _ => (delay_in(info.delay, 1000).min(u16::MAX.into()) as u16, 1000),
+            };
+
+            writer
+                .set_frame_delay(num, den)
+                .map_err(|e| e.to_string())?;
+            writer
+                .set_dispose_op(match info.disposal {
+                    Disposal::Keep => png::DisposeOp::None,
+                    Disposal::Background => png::DisposeOp::Background,
+                    Disposal::Previous => png::DisposeOp::Previous,
+                })
+                .map_err(|e| e.to_string())?;
+            writer
+                .set_blend_op(png::BlendOp::Source)
+                .map_err(|e| e.to_string())?;
+            writer
+                .write_image_data(frame.as_raw())
+                .map_err(|e| e.to_string())?;
+        }
+
+        writer.finish().map_err(|e| e.to_string())?;
+
+        Ok(buffer)
+    }
+
+    fn encode_webp<'a>(
+        &self,
+        frames: impl Iterator<Item = (Result<RgbaImage, String>, &'a FrameInfo)>,
+        width: u32,
+        height: u32,
+        encoding: Encoding,
+    ) -> Result<Vec<u8>, String> {
+        let (quality, lossless) = match encoding {
+            Encoding::Webp { quality, lossless } => (quality.clamp(0.0, 100.0), lossless),
+            _ => (100.0, true),
+        };
+
+        let [r, g, b, a] = self.background;
+        let mut anim = vec![b, g, r, a];
+        anim.extend_from_slice(
+            &(self.loops.unwrap_or(0).min(u16::MAX.into()) as u16).to_le_bytes(),
+        );
+
+        let mut chunks = vec![
+            vp8x_chunk(0x12, width, height),
+            RiffChunk::new(WEBP_ANIM, RiffContent::Data(Bytes::from(anim))),
+        ];
+
+        for (frame, info) in frames {
+            let frame = frame?;
+
+            let memory = webp::Encoder::from_rgba(frame.as_raw(), frame.width(), frame.height())
+                .encode_simple(lossless, quality)
+                .map_err(|e| format!("WebP encoding failed: {:?}", e))?;
+            let encoded =
+                WebP::from_bytes(Bytes::copy_from_slice(&memory)).map_err(|e| e.to_string())?;
+
+            // Every frame covers the whole canvas and replaces the last one.
+            let mut anmf = vec![0; 6];
+            anmf.extend_from_slice(&(frame.width() - 1).to_le_bytes()[..3]);
+            anmf.extend_from_slice(&(frame.height() - 1).to_le_bytes()[..3]);
+            anmf.extend_from_slice(&delay_in(info.delay, 1000).min(0xFF_FFFF).to_le_bytes()[..3]);
+            anmf.push(match info.disposal {
+                Disposal::Background => 0x03,
+                Disposal::Keep | Disposal::Previous => 0x02,
+            });
+
+            for chunk in encoded.chunks() {
+                if [WEBP_ALPH, WEBP_VP8, WEBP_VP8L].contains(&chunk.id()) {
+                    anmf.extend_from_slice(&chunk.clone().encoder().bytes());
+                }
+            }
+
+            chunks.push(RiffChunk::new(
+                WEBP_ANMF,
+                RiffContent::Data(Bytes::from(anmf)),
+            ));
+        }
+
+        webp_bytes(chunks)
+    }
+}
+
+/// Converts a delay to a whole number of `1 / unit` seconds.
+fn delay_in((num, den): (u32, u32), unit: u32) -> u32 {
+    let den = u64::from(den.max(1));
+    ((u64::from(num) * u64::from(unit) + den / 2) / den).min(u32::MAX.into()) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const CLEAR: [u8; 4] = [0; 4];
+
+    fn raw(colour: [u8; 4], size: (u32, u32), at: (u32, u32), disposal: Disposal) -> RawFrame {
+        RawFrame {
+            image: RgbaImage::from_pixel(size.0, size.1, Rgba(colour)),
+            left: at.0,
+            top: at.1,
+            blend: Blend::Over,
+            info: FrameInfo {
+                delay: (1, 10),
+                disposal,
+            },
+        }
+    }
+
+    #[test]
+    fn converts_delays_to_whole_units() {
+        assert_eq!(delay_in((10, 100), 1000), 100);
+        assert_eq!(delay_in((1, 3), 100), 33);
+        assert_eq!(delay_in((2, 3), 100), 67);
+        assert_eq!(delay_in((5, 0), 1000), 5000);
+        assert_eq!(delay_in((u32::MAX, 1), 1000), u32::MAX);
+    }
+
+    #[test]
+    fn keeps_frames_drawn() {
+        let mut canvas = Canvas::new(4, 4);
+        canvas.draw(&raw(RED, (4, 4), (0, 0), Disposal::Keep));
+        canvas.draw(&raw(GREEN, (2, 2), (0, 0), Disposal::Keep));
+        let shown = canvas.draw(&raw(BLUE, (1, 1), (3, 3), Disposal::Keep));
+
+        assert_eq!(shown[(0, 0)].0, GREEN);
+        assert_eq!(shown[(2, 2)].0, RED);
+        assert_eq!(shown[(3, 3)].0, BLUE);
+    }
+
+    #[test]
+    fn clears_the_area_of_frames_disposed_to_the_background() {
+        let mut canvas = Canvas::new(4, 4);
+        canvas.draw(&raw(RED, (4, 4), (0, 0), Disposal::Keep));
+        let shown = canvas.draw(&raw(GREEN, (2, 2), (0, 0), Disposal::Background));
+        assert_eq!(shown[(1, 1)].0, GREEN);
+
+        let shown = canvas.draw(&raw(BLUE, (1, 1), (3, 3), Disposal::Keep));
+        assert_eq!(shown[(0, 0)].0, CLEAR);
+        assert_eq!(shown[(1, 1)].0, CLEAR);
+        assert_eq!(shown[(2, 2)].0, RED);
+        assert_eq!(shown[(3, 3)].0, BLUE);
+    }
+
+    #[test]
+    fn restores_the_canvas_under_frames_disposed_to_the_previous_one() {
+        let mut canvas = Canvas::new(4, 4);
+        canvas.draw(&raw(RED, (4, 4), (0, 0), Disposal::Keep));
+        let shown = canvas.draw(&raw(GREEN, (2, 2), (2, 2), Disposal::Previous));
+        assert_eq!(shown[(3, 3)].0, GREEN);
+
+        let shown = canvas.draw(&raw(BLUE, (1, 1), (0, 0), Disposal::Keep));
+        assert_eq!(shown[(0, 0)].0, BLUE);
+        assert_eq!(shown[(2, 2)].0, RED);
+        assert_eq!(shown[(3, 3)].0, RED);
+    }
+
+    #[test]
+    fn blends_frames_over_the_canvas_or_replaces_it() {
+        let mut over = Canvas::new(2, 2);
+        over.draw(&raw(RED, (2, 2), (0, 0), Disposal::Keep));
+        let shown = over.draw(&raw(CLEAR, (1, 1), (0, 0), Disposal::Keep));
+        assert_eq!(shown[(0, 0)].0, RED);
+
+        let mut source = Canvas::new(2, 2);
+        source.draw(&raw(RED, (2, 2), (0, 0), Disposal::Keep));
+        let shown = source.draw(&RawFrame {
+            blend: Blend::Source,
+            ..raw(CLEAR, (1, 1), (0, 0), Disposal::Keep)
+        });
+        assert_eq!(shown[(0, 0)].0, CLEAR);
+        assert_eq!(shown[(1, 1)].0, RED);
+    }
+
+    /// Frames with a few solid colours, which even a GIF keeps as they are.
+    fn images(count: usize) -> Vec<RgbaImage> {
+        (0..count)
+            .map(|frame| {
+                RgbaImage::from_fn(6, 4, |x, _| {
+                    Rgba(match (x < 3, frame % 3) {
+                        (true, 0) | (false, 2) => RED,
+                        (true, 1) | (false, 0) => GREEN,
+                        _ => BLUE,
+                    })
+                })
+            })
+            .collect()
+    }
+
+    /// Writes `animation` as the input, then splits it, encodes the frames
+    /// split from it and splits the result again, as a job does.
+    fn round_trip(animation: &Animation) -> (Animation, Vec<RgbaImage>, Animation) {
+        let extension = animation.format.extensions_str()[0];
+        let dir = std::env::temp_dir().join(format!(
+            "upscaler-animation-{}-{}",
+            extension,
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+
+        let (input, output) = (dir.join("in"), dir.join("out"));
+        let frames = images(animation.frames.len()).into_iter().map(Ok);
+        fs::write(
+            &input,
+            animation.encode(frames, Encoding::default()).unwrap(),
+        )
+        .unwrap();
+
+        let split_input = split(&input, &dir.join("input-frames")).unwrap();
+        let read = (0..split_input.frames.len() as u32)
+            .map(|frame| {
+                image::open(frame_path(&dir.join("input-frames"), frame))
+                    .unwrap()
+                    .to_rgba8()
+            })
+            .collect::<Vec<_>>();
+
+        let frames = read.iter().cloned().map(Ok);
+        fs::write(
+            &output,
+            split_input.encode(frames, Encoding::default()).unwrap(),
+        )
+        .unwrap();
+        let split_output = split(&output, &dir.join("output-frames")).unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+        (split_input, read, split_output)
+    }
+
+    fn frame_infos(delays: [(u32, u32); 3], disposals: [Disposal; 3]) -> Vec<FrameInfo> {
+        delays
+            .into_iter()
+            .zip(disposals)
+            .map(|(delay, disposal)| FrameInfo { delay, disposal })
+            .collect()
+    }
+
+    #[test]
+    fn gif_keeps_its_timing_and_loops() {
+        let animation = Animation {
+            format: ImageFormat::Gif,
+            loops: Some(3),
+            background: [0; 4],
+            frames: frame_infos(
+                [(5, 100), (10, 100), (250, 100)],
+                [Disposal::Keep, Disposal::Background, Disposal::Previous],
+            ),
+        };
+
+        let (split_input, read, split_output) = round_trip(&animation);
+
+        assert_eq!(split_input, animation);
+        assert_eq!(split_output, animation);
+
+        // Colours go through a palette, so they only have to come close.
+        for (read, written) in read.iter().zip(images(3)) {
+            let close = read
+                .pixels()
+                .zip(written.pixels())
+                .all(|(a, b)| a.0.iter().zip(b.0).all(|(a, b)| a.abs_diff(b) <= 8));
+            assert!(close);
+        }
+    }
+
+    #[test]
+    fn apng_keeps_its_timing_and_loops() {
+        let animation = Animation {
+            format: ImageFormat::Png,
+            loops: None,
+            background: [0; 4],
+            frames: frame_infos(
+                [(1, 3), (1, 30), (250, 1000)],
+                [Disposal::Keep, Disposal::Background, Disposal::Previous],
+            ),
+        };
+
+        let (split_input, read, split_output) = round_trip(&animation);
+
+        assert_eq!(split_input, animation);
+        assert_eq!(split_output, animation);
+        assert_eq!(read, images(3));
+    }
+
+    #[test]
+    fn webp_keeps_its_timing_loops_and_background() {
+        // WebP has no disposal to the previous frame.
+        let animation = Animation {
+            format: ImageFormat::WebP,
+            loops: Some(2),
+            background: [10, 20, 30, 255],
+            frames: frame_infos(
+                [(40, 1000), (100, 1000), (1500, 1000)],
+                [Disposal::Keep, Disposal::Background, Disposal::Keep],
+            ),
+        };
+
+        let (split_input, read, split_output) = round_trip(&animation);
+
+        assert_eq!(split_input, animation);
+        assert_eq!(split_output, animation);
+        assert_eq!(read, images(3));
+    }
+}
@@ -0,0 +1,496 @@
+//! A small HTTP API on localhost, for other tools to queue inputs and follow
+//! their jobs. It needs so little of HTTP that requests are parsed here.
+//!
+//! Every request carries the token, as `Authorization: Bearer <token>` or
+//! as a `token` query parameter:
+//!
+//! - `POST /jobs?path=<input>` queues a file already on disk, and
+//!   `POST /jobs?name=<file name>` one uploaded as the body
+//! - `GET /jobs` lists the queue, `GET /jobs/<id>` shows one job
+//! - `POST /jobs/<id>/cancel` cancels a job that is not done
+//! - `GET /jobs/<id>/output` downloads the output of a finished job
+//!
+//! Submissions may override `scale`, `model`, `tta`, `format`, `output_dir`
+//! and `output_name`, which are otherwise those in the window.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use async_std::fs::{self, File};
+use async_std::io::{prelude::BufReadExt, BufReader, ReadExt, WriteExt};
+use async_std::net::{TcpListener, TcpStream};
+use iced::futures::channel::mpsc;
+use iced::futures::{SinkExt, StreamExt};
+use iced::Subscription;
+use serde_json::{json, Value};
+
+use crate::job::{Job, JobId, JobStatus, Overrides};
+use crate::{Format, Message};
+
+/// The most the request line and headers may take up.
+const HEAD_LIMIT: u64 = 16 * 1024;
+/// Uploads are kept here until the application is closed, as they are the
+/// inputs of their jobs.
+const UPLOAD_DIR: &str = "realesrgan-ncnn-vulkan-gui-uploads";
+
+#[derive(Debug, Clone)]
+pub enum Request {
+    Submit { path: PathBuf, overrides: Overrides },
+    List,
+    Status(JobId),
+    Cancel(JobId),
+    Download(JobId),
+}
+
+#[derive(Debug)]
+pub enum Response {
+    Json(u16, Value),
+    /// Sends the file at the path.
+    File(PathBuf),
+}
+
+impl Response {
+    pub fn error(status: u16, message: &str) -> Self {
+        Response::Json(status, json!({ "error": message }))
+    }
+}
+
+/// A request for the application to answer, through `reply`.
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub request: Request,
+    reply: mpsc::Sender<Response>,
+}
+
+impl Call {
+    pub fn reply(mut self, response: Response) {
+        // The client may have hung up in the meantime.
+        let _ = self.reply.try_send(response);
+    }
+}
+
+/// A random token to start with, in hexadecimal.
+pub fn generate_token() -> String {
+    let mut bytes = [0; 16];
+
+    // Without randomness, the user has to come up with a token themselves.
+    if getrandom::getrandom(&mut bytes).is_err() {
+        return String::new();
+    }
+
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Serves the API on `port` of localhost, until it is unsubscribed from.
+pub fn serve(port: u16, token: String) -> Subscription<Message> {
+    struct Serve;
+
+    iced::subscription::channel(
+        (std::any::TypeId::of::<Serve>(), port, token.clone()),
+        100,
+        move |mut output| {
+            let token = token.clone();
+
+            async move {
+                let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        let _ = output.send(Message::ApiFailed(e.to_string())).await;
+
+                        // Nothing else to do until it is unsubscribed from.
+                        loop {
+                            iced::futures::future::pending::<()>().await;
+                        }
+                    }
+                };
+
+                loop {
+                    let Ok((stream, _)) = listener.accept().await else {
+                        continue;
+                    };
+
+                    let (token, output) = (token.clone(), output.clone());
+
+                    async_std::task::spawn(async move {
+                        // Errors here only mean the client went away.
+                        let _ = handle(stream, &token, output).await;
+                    });
+                }
+            }
+        },
+    )
+}
+
+async fn handle(
+    stream: TcpStream,
+    token: &str,
+    mut output: mpsc::Sender<Message>,
+) -> std::io::Result<()> {
+    let mut writer = stream.clone();
+    let mut reader = BufReader::new(stream);
+
+    let (method, target, headers) = {
+        let mut head = (&mut reader).take(HEAD_LIMIT);
+        let mut line = String::new();
+        head.read_line(&mut line).await?;
+
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_owned();
+        let target = parts.next().unwrap_or_default().to_owned();
+
+        let mut headers = HashMap::new();
+
+        loop {
+            line.clear();
+
+            if head.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+                break;
+            }
+
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+            }
+        }
+
+        (method, target, headers)
+    };
+
+    let (path, mut query) = parse_target(&target);
+    let authorized = headers
+        .get("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or(query.get("token").map(String::as_str))
+        == Some(token);
+
+    query.remove("token");
+
+    let request = if !authorized {
+        Err(Response::error(401, "The token is missing or wrong."))
+    } else {
+        let body_len = headers
+            .get("content-length")
+            .and_then(|len| len.parse().ok());
+
+        // curl, for one, waits to be told to go on before sending uploads.
+        if headers
+            .get("expect")
+            .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"))
+        {
+            writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await?;
+        }
+
+        route(&method, &path, query, body_len, &mut reader).await
+    };
+
+    let response = match request {
+        Ok(request) => {
+            let (reply, mut response) = mpsc::channel(1);
+            let call = Call { request, reply };
+
+            match output.send(Message::ApiCalled(call)).await {
+                Ok(()) => response.next().await,
+                Err(_) => None,
+            }
+            .unwrap_or_else(|| Response::error(503, "The application is closing."))
+        }
+        Err(response) => response,
+    };
+
+    respond(&mut writer, response).await
+}
+
+/// Works out what is asked for, saving an upload on the way.
+async fn route(
+    method: &str,
+    path: &str,
+    mut query: HashMap<String, String>,
+    body_len: Option<u64>,
+    body: &mut BufReader<TcpStream>,
+) -> Result<Request, Response> {
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    let job_id = |id: &str| {
+        id.parse::<JobId>()
+            .map_err(|_| Response::error(404, "There is no such job."))
+    };
+
+    match (method, segments.as_slice()) {
+        ("GET", ["jobs"]) => Ok(Request::List),
+        ("GET", ["jobs", id]) => Ok(Request::Status(job_id(id)?)),
+        ("GET", ["jobs", id, "output"]) => Ok(Request::Download(job_id(id)?)),
+        ("POST", ["jobs", id, "cancel"]) => Ok(Request::Cancel(job_id(id)?)),
+        ("POST", ["jobs"]) => {
+            let (path, name) = (query.remove("path"), query.remove("name"));
+            let overrides = parse_overrides(query).map_err(|e| Response::error(400, &e))?;
+
+            let path = match (path, name) {
+                (Some(path), None) => PathBuf::from(path),
+                (None, Some(name)) => {
+                    let Some(len) = body_len else {
+                        return Err(Response::error(411, "The upload needs a Content-Length."));
+                    };
+
+                    save_upload(&name, len, body)
+                        .await
+                        .map_err(|e| Response::error(500, &format!("Unable to save it: {}", e)))?
+                }
+                _ => return Err(Response::error(400, "Give either a path or a name.")),
+            };
+
+            Ok(Request::Submit { path, overrides })
+        }
+        (_, ["jobs", ..]) => Err(Response::error(405, "The method is not allowed.")),
+        _ => Err(Response::error(404, "There is no such endpoint.")),
+    }
+}
+
+/// Splits a request target into its path and its decoded query parameters.
+fn parse_target(target: &str) -> (String, HashMap<String, String>) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(name), decode(value))
+        })
+        .collect();
+
+    (decode(path), query)
+}
+
+/// Undoes the percent-encoding of URLs, and of `+` for spaces in forms.
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn parse_overrides(query: HashMap<String, String>) -> Result<Overrides, String> {
+    let mut overrides = Overrides::default();
+
+    for (name, value) in query {
+        match name.as_str() {
+            "scale" => overrides.scale = Some(value),
+            "model" => overrides.model = Some(value),
+            "tta" => {
+                overrides.tta = Some(match value.as_str() {
+                    "1" | "true" => true,
+                    "0" | "false" => false,
+                    _ => {
+                        return Err(format!(
+                            "\"{}\" is not a valid tta, use true or false.",
+                            value
+                        ))
+                    }
+                })
+            }
+            "format" => overrides.format = Some(Format::parse(&value)?),
+            "output_dir" => overrides.output_dir = Some(PathBuf::from(value)),
+            "output_name" => overrides.output_name = Some(value),
+            _ => return Err(format!("Unknown parameter \"{}\".", name)),
+        }
+    }
+
+    Ok(overrides)
+}
+
+/// Writes `len` bytes of the body to a folder of its own, under the name it
+/// was given so that its output is named after it.
+async fn save_upload(
+    name: &str,
+    len: u64,
+    body: &mut BufReader<TcpStream>,
+) -> std::io::Result<PathBuf> {
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+
+    let name = Path::new(name)
+        .file_name()
+        .ok_or_else(|| invalid("the name is not a file name"))?;
+
+    let dir = std::env::temp_dir().join(UPLOAD_DIR).join(generate_token());
+    fs::create_dir_all(&dir).await?;
+
+    let path = dir.join(name);
+    let mut file = File::create(&path).await?;
+    let written = async_std::io::copy(&mut body.take(len), &mut file).await?;
+    file.flush().await?;
+
+    if written < len {
+        return Err(invalid("the upload was cut short"));
+    }
+
+    Ok(path)
+}
+
+/// What the API tells of a job.
+pub fn job_json(job: &Job, default_model: &str) -> Value {
+    let error = match &job.status {
+        JobStatus::Failed(reason) | JobStatus::TimedOut(reason) => Some(reason.clone()),
+        _ => None,
+    };
+
+    json!({
+        "id": job.id,
+        "input": Path::new(&job.input_path).to_string_lossy(),
+        "output": Path::new(&job.output_path).to_string_lossy(),
+        "status": job.status.to_string(),
+        "error": error,
+        "progress": job.total_progress(),
+        "model": if job.settings.model_name.is_empty() {
+            default_model
+        } else {
+            &job.settings.model_name
+        },
+        "tta": job.settings.tta_mode,
+        "seconds": job.elapsed().map(|elapsed| elapsed.as_secs_f64()),
+    })
+}
+
+/// Whether `path` is an upload, which is gone once the application closes.
+pub fn is_upload(path: &Path) -> bool {
+    path.starts_with(std::env::temp_dir().join(UPLOAD_DIR))
+}
+
+/// Removes the uploads, once nothing needs them anymore.
+pub fn remove_uploads() {
+    let _ = std::fs::remove_dir_all(std::env::temp_dir().join(UPLOAD_DIR));
+}
+
+async fn respond(writer: &mut TcpStream, response: Response) -> std::io::Result<()> {
+    let (status, value) = match response {
+        Response::Json(status, value) => (status, value),
+        Response::File(path) => match File::open(&path).await {
+            Ok(file) => return send_file(writer, &path, file).await,
+            Err(e) => (
+                500,
+                json!({ "error": format!("Unable to read the output: {}", e) }),
+            ),
+        },
+    };
+
+    let reason = match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        411 => "Length Required",
+        500 => "Internal Server Error",
+        _ => "Service Unavailable",
+    };
+
+    let body = value.to_string();
+    let head = format!(
+        concat!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\n",
+            "Content-Length: {}\r\nConnection: close\r\n\r\n"
+        ),
+        status,
+        reason,
+        body.len()
+    );
+
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(body.as_bytes()).await?;
+    writer.flush().await
+}
+
+async fn send_file(writer: &mut TcpStream, path: &Path, mut file: File) -> std::io::Result<()> {
+    let len = file.metadata().await?.len();
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let head = format!(
+        concat!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\n",
+            "Content-Length: {}\r\nContent-Disposition: attachment; filename=\"{}\"\r\n",
+            "Connection: close\r\n\r\n"
+        ),
+        len,
+        name.replace('"', "")
+    );
+
+    writer.write_all(head.as_bytes()).await?;
+    async_std::io::copy(&mut file, &mut *writer).await?;
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_percent_escapes_and_plus_signs() {
+        assert_eq!(decode("a%20b+c"), "a b c");
+        assert_eq!(decode("caf%C3%A9%2Fx"), "café/x");
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz%4"), "%zz%4");
+        assert_eq!(decode("%FF"), "\u{FFFD}");
+    }
+
+    #[test]
+    fn splits_targets_into_path_and_query() {
+        let (path, query) = parse_target("/jobs%2Fx?scale=2x&output_name=%7Bname%7D+up&&tta");
+
+        assert_eq!(path, "/jobs/x");
+        assert_eq!(query.len(), 3);
+        assert_eq!(query["scale"], "2x");
+        assert_eq!(query["output_name"], "{name} up");
+        assert_eq!(query["tta"], "");
+
+        let (path, query) = parse_target("/jobs");
+        assert_eq!(path, "/jobs");
+        assert!(query.is_empty());
+    }
+
+    #[test]
+    fn parses_overrides_from_the_query() {
+        let (_, query) = parse_target("/jobs?scale=1080h&tta=1&format=WEBP&output_dir=%2Fout");
+        let overrides = parse_overrides(query).unwrap();
+
+        assert_eq!(overrides.scale.as_deref(), Some("1080h"));
+        assert_eq!(overrides.tta, Some(true));
+        assert_eq!(overrides.format, Some(Format::Webp));
+        assert_eq!(overrides.output_dir, Some(PathBuf::from("/out")));
+
+        for target in ["/jobs?tta=yes", "/jobs?format=gif", "/jobs?scael=2"] {
+            assert!(parse_overrides(parse_target(target).1).is_err());
+        }
+    }
+
+    #[test]
+    fn tells_uploads_apart() {
+        assert!(is_upload(
+            &std::env::temp_dir()
+                .join(UPLOAD_DIR)
+                .join("a")
+                .join("b.png")
+        ));
+        assert!(!is_upload(&std::env::temp_dir().join("b.png")));
+    }
+}
@@ -0,0 +1,363 @@
+//! Comic archives (CBZ/ZIP). The pages of an archive are extracted to PNGs,
+//! upscaled one by one like the frames of an animation, and packed into a
+//! new archive in place of the originals. Everything else in the archive,
+//! such as a ComicInfo.xml, is copied over as is.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Write};
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+use image::DynamicImage;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::animation;
+use crate::input::{self, InputFormat, InputInfo};
+use crate::postprocess::PostProcess;
+use crate::scale;
+
+pub const EXTENSIONS: &[&str] = &["cbz", "zip"];
+
+/// The pages of an archive, in reading order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Archive {
+    pub pages: Vec<Page>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page {
+    /// The path of the page within the archive.
+    pub name: String,
+    pub dimensions: (u32, u32),
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Compares file names the way people read them, with runs of digits
+/// compared by value, so that "page2" comes before "page10".
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+
+    loop {
+        let ordering = match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (take_number(&mut a), take_number(&mut b));
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                a.next();
+                b.next();
+                ordering
+            }
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        number.push(c);
+    }
+
+    number
+}
+
+fn open(path: &Path) -> Result<ZipArchive<File>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    ZipArchive::new(file).map_err(|e| e.to_string())
+}
+
+/// The entries of `archive` that are pages, in reading order. Pages are
+/// recognised by their extension, leaving out the resource forks macOS
+/// leaves behind.
+fn page_names(archive: &ZipArchive<File>) -> Vec<String> {
+    let extensions = input::supported_extensions();
+
+    let mut pages = archive
+        .file_names()
+        .filter(|name| !name.ends_with('/'))
+        .filter(|name| !name.starts_with("__MACOSX/"))
+        .filter(|name| {
+            let path = Path::new(name);
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            let image = path.extension().is_some_and(|ext| {
+                extensions.contains(&ext.to_string_lossy().to_lowercase().as_str())
+            });
+
+            image && !hidden
+        })
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    pages.sort_by(|a, b| natural_cmp(a, b).then_with(|| a.cmp(b)));
+    pages
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<Vec<u8>, String> {
+    let mut entry = archive.by_name(name).map_err(|e| e.to_string())?;
+    let mut bytes = Vec::with_capacity(entry.size() as usize);
+
+    entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// Describes the first page of `archive`, failing if it has none or the
+/// page cannot be decoded.
+fn first_page(archive: &mut ZipArchive<File>) -> Result<(InputInfo, u32), String> {
+    let pages = page_names(archive);
+    let first = pages
+        .first()
+        .ok_or_else(|| String::from("the archive has no pages"))?;
+
+    let bytes = read_entry(archive, first)?;
+    let format = image::guess_format(&bytes)
+        .ok()
+        .filter(|format| input::is_supported_format(*format))
+        .ok_or_else(|| format!("{} is not an image in a supported format", first))?;
+    let (dimensions, color) =
+        input::read_header(format, Cursor::new(&bytes)).map_err(|e| format!("{}: {}", first, e))?;
+
+    let info = InputInfo {
+        format: InputFormat::Archive,
+        dimensions,
+        bit_depth: color.bits_per_pixel() / color.channel_count() as u16,
+        has_alpha: color.has_alpha(),
+        extension_mismatch: false,
+        frames: pages.len() as u32,
+    };
+
+    Ok((info, pages.len() as u32))
+}
+
+/// Reads the list of pages of `path`, describing the archive by its first
+/// page.
+pub fn probe(path: &Path) -> Result<InputInfo, String> {
+    first_page(&mut open(path)?).map(|(info, _)| info)
+}
+
+/// Reads the size of the first page of the archive written to `path`, to
+/// check that it is one.
+pub fn verify(path: &Path) -> Result<(u32, u32), String> {
+    first_page(&mut open(path)?).map(|(info, _)| info.dimensions)
+}
+
+/// Decodes the first page of `path`, to show as its thumbnail.
+pub fn cover(path: &Path) -> Result<DynamicImage, String> {
+    let mut archive = open(path)?;
+    let first = page_names(&archive)
+        .into_iter()
+        .next()
+        .ok_or_else(|| String::from("the archive has no pages"))?;
+
+    let bytes = read_entry(&mut archive, &first)?;
+    image::load_from_memory(&bytes).map_err(|e| format!("{}: {}", first, e))
+}
+
+/// Extracts every page of `input` to `dir` as a PNG, as named by
+/// `animation::frame_path`, in reading order.
+pub fn split(input: &Path, dir: &Path) -> Result<Archive, String> {
+    let mut archive = open(input)?;
+
+    fs::create_dir_all(animation::step_dir(dir, 0)).map_err(|e| e.to_string())?;
+
+    let mut pages = Vec::new();
+
+    for (index, name) in page_names(&archive).into_iter().enumerate() {
+        let bytes = read_entry(&mut archive, &name)?;
+        let image = image::load_from_memory(&bytes).map_err(|e| format!("{}: {}", name, e))?;
+        let dimensions = (image.width(), image.height());
+
+        input::narrow_for_png(image)
+            .save_with_format(
+                animation::frame_path(dir, index as u32),
+                image::ImageFormat::Png,
+            )
+            .map_err(|e| e.to_string())?;
+
+        pages.push(Page { name, dimensions });
+    }
+
+    if pages.is_empty() {
+        return Err(String::from("the archive has no pages"));
+    }
+
+    Ok(Archive { pages })
+}
+
+/// The names the pages of `archive` are written under, with `extension`.
+/// Pages that would then share a name with another entry, as "1.jpg" and
+/// "1.png" do, keep their old extension before the new one, which keeps
+/// them in the same order.
+fn renamed_pages(archive: &Archive, others: &[String], extension: &str) -> Vec<String> {
+    // Some readers extract archives to folders that ignore case.
+    let mut taken = others
+        .iter()
+        .map(|name| name.to_lowercase())
+        .collect::<HashSet<_>>();
+
+    archive
+        .pages
+        .iter()
+        .map(|page| {
+            let renamed = Path::new(&page.name)
+                .with_extension(extension)
+                .to_string_lossy()
+                .replace('\\', "/");
+
+            let name = [renamed, format!("{}.{}", page.name, extension)]
+                .into_iter()
+                .chain((2..).map(|n| format!("{}-{}.{}", page.name, n, extension)))
+                .find(|name| !taken.contains(&name.to_lowercase()))
+                .unwrap_or_default();
+
+            taken.insert(name.to_lowercase());
+            name
+        })
+        .collect()
+}
+
+/// Writes a copy of `input` to `output` with its pages replaced by the
+/// upscaled ones in `upscaled`, in the order of `archive`.
+///
+/// Pages are all scaled by `factor`, which is worked out from the first
+/// page when a size is asked for, and encoded as set on the Output page.
+pub fn repack(
+    input: &Path,
+    archive: &Archive,
+    upscaled: &[PathBuf],
+    post_process: &PostProcess,
+    factor: f64,
+    output: &Path,
+) -> Result<(), String> {
+    let mut source = open(input)?;
+    let file = File::create(output).map_err(|e| e.to_string())?;
+    let mut writer = ZipWriter::new(file);
+
+    let others = source
+        .file_names()
+        .filter(|name| archive.pages.iter().all(|page| page.name != *name))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    let names = renamed_pages(archive, &others, post_process.encoding.extension());
+
+    let pages = archive
+        .pages
+        .iter()
+        .zip(upscaled)
+        .zip(names)
+        .map(|((page, upscaled), name)| (page.name.as_str(), (page, upscaled, name)))
+        .collect::<HashMap<_, _>>();
+
+    // Images are compressed already, so pages are only stored.
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+    // Entries are written in their original order, with pages replaced in
+    // place, so that readers going by either order see the same thing.
+    for index in 0..source.len() {
+        let entry = source.by_index(index).map_err(|e| e.to_string())?;
+
+        let Some((page, upscaled, name)) = pages.get(entry.name()) else {
+            writer.raw_copy_file(entry).map_err(|e| e.to_string())?;
+            continue;
+        };
+
+        let mut page_process = post_process.clone();
+
+        if post_process.output_size.is_some() {
+            let (width, height) = page.dimensions;
+            page_process.output_size =
+                Some((scale::scaled(width, factor), scale::scaled(height, factor)));
+        }
+
+        let bytes = page_process
+            .render(upscaled)
+            .map_err(|e| format!("{}: {}", page.name, e))?;
+
+        writer
+            .start_file(name, options)
+            .map_err(|e| e.to_string())?;
+        writer.write_all(&bytes).map_err(|e| e.to_string())?;
+    }
+
+    writer.finish().map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorts_names_as_people_read_them() {
+        assert_eq!(natural_cmp("page2.png", "page10.png"), Ordering::Less);
+        assert_eq!(natural_cmp("page10.png", "page9.png"), Ordering::Greater);
+        assert_eq!(natural_cmp("page", "page1"), Ordering::Less);
+
+        // Leading zeros do not count, so only the tie-break tells these apart.
+        assert_eq!(natural_cmp("page007.png", "page7.png"), Ordering::Equal);
+        assert_eq!(natural_cmp("page01.png", "page2.png"), Ordering::Less);
+        assert_eq!(natural_cmp("0.png", "00.png"), Ordering::Equal);
+
+        assert_eq!(natural_cmp("Page3.png", "page10.png"), Ordering::Less);
+        assert_eq!(natural_cmp("a.png", "B.png"), Ordering::Less);
+        assert_eq!(natural_cmp("CH1/p2.png", "ch1/p1.png"), Ordering::Greater);
+
+        let mut names = ["p10.png", "P9.png", "p009b.png", "p1.png", "p01.png"];
+        names.sort_by(|a, b| natural_cmp(a, b).then_with(|| a.cmp(b)));
+        assert_eq!(
+            names,
+            ["p01.png", "p1.png", "P9.png", "p009b.png", "p10.png"]
+        );
+    }
+
+    #[test]
+    fn keeps_renamed_pages_apart() {
+        let archive = Archive {
+            pages: ["p1.jpg", "p1.png", "P1.webp", "p2.webp", "cover.jpg"]
+                .into_iter()
+                .map(|name| Page {
+                    name: String::from(name),
+                    dimensions: (1, 1),
+                })
+                .collect(),
+        };
+        let others = [String::from("cover.png"), String::from("ComicInfo.xml")];
+
+        assert_eq!(
+            renamed_pages(&archive, &others, "png"),
+            [
+                "p1.png",
+                "p1.png.png",
+                "P1.webp.png",
+                "p2.png",
+                "cover.jpg.png"
+            ]
+        );
+        assert_eq!(
+            renamed_pages(&archive, &others, "jpg"),
+            ["p1.jpg", "p1.png.jpg", "P1.webp.jpg", "p2.jpg", "cover.jpg"]
+        );
+    }
+}
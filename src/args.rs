@@ -0,0 +1,282 @@
+//! The command line, which opens inputs and fills in settings as if they had
+//! been picked in the window, for "Open with" and "Send to" integration.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::manifest;
+use crate::scale::{self, ScaleTarget};
+use crate::Format;
+
+pub const USAGE: &str = "\
+Usage: realesrgan-ncnn-vulkan-gui [OPTIONS] [FILES OR FOLDERS]...
+
+Options:
+  -s, --scale <SCALE>     A factor such as 2x, or a size such as 1920w, 1080h
+                          or 2048px for the longest edge
+  -m, --model <MODEL>     The realesrgan model to upscale with
+  -f, --format <FORMAT>   png, jpg or webp
+  -o, --output <FOLDER>   Where the outputs are written
+  -p, --preset <FILE>     A JSON or TOML file of the options above, for those
+                          not given here
+      --start             Starts upscaling the inputs right away
+  -h, --help              Shows this message";
+
+#[derive(Clone, Debug, Default)]
+pub struct Args {
+    pub inputs: Vec<PathBuf>,
+    pub scale: Option<ScaleTarget>,
+    pub model: Option<String>,
+    pub format: Option<Format>,
+    pub output_dir: Option<PathBuf>,
+    pub start: bool,
+    pub help: bool,
+}
+
+impl Args {
+    /// Arguments that parse into these again, with absolute paths for an
+    /// instance that may run in another directory. `--start` is left out.
+    pub fn to_absolute(&self) -> Vec<OsString> {
+        let absolute = |path: &PathBuf| std::path::absolute(path).unwrap_or_else(|_| path.clone());
+        let mut args = Vec::new();
+
+        if let Some(scale) = self.scale {
+            args.push(OsString::from(format!("--scale={}", scale)));
+        }
+
+        if let Some(model) = &self.model {
+            args.push(OsString::from(format!("--model={}", model)));
+        }
+
+        if let Some(format) = self.format {
+            args.push(OsString::from(match format {
+                Format::Png => "--format=png",
+                Format::Jpg => "--format=jpg",
+                Format::Webp => "--format=webp",
+            }));
+        }
+
+        if let Some(dir) = &self.output_dir {
+            let mut arg = OsString::from("--output=");
+            arg.push(absolute(dir));
+            args.push(arg);
+        }
+
+        args.push(OsString::from("--"));
+        args.extend(
+            self.inputs
+                .iter()
+                .map(|input| absolute(input).into_os_string()),
+        );
+
+        args
+    }
+}
+
+/// Settings the command line starts from, written like the settings of an
+/// input in a manifest.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Preset {
+    #[serde(default)]
+    scale: Option<String>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    format: Option<String>,
+    #[serde(default)]
+    output_dir: Option<PathBuf>,
+}
+
+impl Preset {
+    /// A relative `output_dir` is taken to be relative to the preset's folder.
+    fn load(path: &Path) -> Result<Self, String> {
+        let mut preset: Self = manifest::read(path)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+
+        preset.output_dir = preset.output_dir.map(|dir| base.join(dir));
+
+        Ok(preset)
+    }
+}
+
+/// Parses the arguments, without the name of the program.
+pub fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    let mut options_ended = false;
+    let mut preset = None;
+
+    while let Some(arg) = args.next() {
+        let text = arg.to_string_lossy();
+
+        if options_ended || !text.starts_with('-') || text == "-" {
+            parsed.inputs.push(PathBuf::from(arg));
+            continue;
+        }
+
+        // Values may be given as "--scale=2x" as well as "--scale 2x".
+        let (name, inline_value) = match text.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name.to_owned(), Some(value)),
+            _ => (text.to_string(), None),
+        };
+
+        let mut value = || match inline_value {
+            Some(value) => Ok(OsString::from(value)),
+            None => args
+                .next()
+                .ok_or_else(|| format!("{} needs a value.", name)),
+        };
+
+        match name.as_str() {
+            "-s" | "--scale" => {
+                parsed.scale = Some(scale::parse_target(&value()?.to_string_lossy())?)
+            }
+            "-m" | "--model" => parsed.model = Some(value()?.to_string_lossy().to_string()),
+            "-f" | "--format" => parsed.format = Some(Format::parse(&value()?.to_string_lossy())?),
+            "-o" | "--output" => parsed.output_dir = Some(PathBuf::from(value()?)),
+            "-p" | "--preset" => preset = Some(PathBuf::from(value()?)),
+            "--start" => parsed.start = true,
+            "-h" | "--help" => parsed.help = true,
+            "--" => options_ended = true,
+            _ => return Err(format!("Unknown option \"{}\".", text)),
+        }
+    }
+
+    if let Some(path) = preset {
+        let preset =
+            Preset::load(&path).map_err(|e| format!("{}: {}", path.to_string_lossy(), e))?;
+
+        // The preset is read last, so it only fills in what the options left.
+        if parsed.scale.is_none() {
+            parsed.scale = preset
+                .scale
+                .as_deref()
+                .map(scale::parse_target)
+                .transpose()?;
+        }
+
+        if parsed.format.is_none() {
+            parsed.format = preset.format.as_deref().map(Format::parse).transpose()?;
+        }
+
+        parsed.model = parsed.model.or(preset.model);
+        parsed.output_dir = parsed.output_dir.or(preset.output_dir);
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse(args.iter().map(OsString::from))
+    }
+
+    #[test]
+    fn parses_options_in_either_form() {
+        let parsed = args(&[
+            "-s",
+            "2x",
+            "--model=anime",
+            "-f",
+            "JPEG",
+            "--output",
+            "out",
+            "a.png",
+        ]);
+        let parsed = parsed.unwrap();
+
+        assert_eq!(parsed.scale, Some(ScaleTarget::Factor(2.0)));
+        assert_eq!(parsed.model.as_deref(), Some("anime"));
+        assert_eq!(parsed.format, Some(Format::Jpg));
+        assert_eq!(parsed.output_dir, Some(PathBuf::from("out")));
+        assert_eq!(parsed.inputs, [PathBuf::from("a.png")]);
+        assert!(!parsed.start && !parsed.help);
+
+        assert!(args(&["--start", "-h"]).map(|a| a.start && a.help).unwrap());
+    }
+
+    #[test]
+    fn takes_anything_after_the_separator_as_an_input() {
+        let parsed = args(&["-", "--", "--start", "-s"]).unwrap();
+
+        assert_eq!(
+            parsed.inputs,
+            [PathBuf::from("-"), "--start".into(), "-s".into()]
+        );
+        assert!(!parsed.start);
+        assert_eq!(parsed.scale, None);
+    }
+
+    #[test]
+    fn rejects_unknown_options_and_bad_values() {
+        assert!(args(&["--frobnicate"]).is_err());
+        assert!(args(&["-s"]).is_err());
+        assert!(args(&["--scale=0"]).is_err());
+        assert!(args(&["-f", "gif"]).is_err());
+    }
+
+    #[test]
+    fn absolute_arguments_parse_back() {
+        let parsed = args(&[
+            "-s", "1080h", "-m", "anime", "-f", "webp", "-o", "out", "--start",
+        ])
+        .map(|mut parsed| {
+            parsed.inputs = vec![PathBuf::from("-a.png"), PathBuf::from("b")];
+            parsed
+        })
+        .unwrap();
+
+        let forwarded = parse(parsed.to_absolute()).unwrap();
+        let absolute = |path: &str| std::path::absolute(path).unwrap();
+
+        assert_eq!(forwarded.scale, parsed.scale);
+        assert_eq!(forwarded.model, parsed.model);
+        assert_eq!(forwarded.format, parsed.format);
+        assert_eq!(forwarded.output_dir, Some(absolute("out")));
+        assert_eq!(forwarded.inputs, [absolute("-a.png"), absolute("b")]);
+        assert!(!forwarded.start);
+    }
+
+    #[test]
+    fn fills_in_options_from_a_preset() {
+        let dir = std::env::temp_dir().join(format!("upscaler-args-preset-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let preset = dir.join("photos.toml");
+        let misspelt = dir.join("misspelt.json");
+        std::fs::write(
+            &preset,
+            "scale = \"2x\"\nmodel = \"anime\"\nformat = \"webp\"\noutput_dir = \"out\"\n",
+        )
+        .unwrap();
+        std::fs::write(&misspelt, r#"{"sacle": "2x"}"#).unwrap();
+
+        let path = preset.to_string_lossy().to_string();
+        let from_preset = args(&["--preset", &path, "a.png"]);
+        let overridden = args(&["-s", "1080h", "-f", "jpg", &format!("--preset={}", path)]);
+        let misspelt = args(&["-p", &misspelt.to_string_lossy()]);
+        let missing = args(&["-p", &dir.join("missing.json").to_string_lossy()]);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let from_preset = from_preset.unwrap();
+        assert_eq!(from_preset.scale, Some(ScaleTarget::Factor(2.0)));
+        assert_eq!(from_preset.model.as_deref(), Some("anime"));
+        assert_eq!(from_preset.format, Some(Format::Webp));
+        assert_eq!(from_preset.output_dir, Some(dir.join("out")));
+        assert_eq!(from_preset.inputs, [PathBuf::from("a.png")]);
+
+        let overridden = overridden.unwrap();
+        assert_eq!(overridden.scale, Some(ScaleTarget::Height(1080)));
+        assert_eq!(overridden.format, Some(Format::Jpg));
+        assert_eq!(overridden.model.as_deref(), Some("anime"));
+
+        assert!(misspelt.is_err());
+        assert!(missing.is_err());
+    }
+}
@@ -1,42 +1,158 @@
 use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::process::ExitStatus;
+use std::time::{Duration, Instant};
 
-use async_std::io::{prelude::BufReadExt, BufReader};
-use async_std::process::{Child, Command};
+use async_std::io::{prelude::BufReadExt, BufReader, Lines};
+use async_std::process::{Child, ChildStderr, Command};
+use async_std::stream::StreamExt as _;
+use async_std::task::JoinHandle;
 
-use iced::futures::{channel::mpsc, SinkExt};
+use iced::futures::{channel::mpsc, FutureExt, SinkExt};
 use iced::Subscription;
 
+use crate::animation::{self, Animation};
+use crate::archive::{self, Archive};
+use crate::crop::{self, Region};
+use crate::input;
+use crate::job::JobId;
+use crate::partial;
+use crate::postprocess::PostProcess;
+use crate::video::{self, Ffmpeg, Video};
 use crate::Message;
 
 pub struct ChildrenStatusChecker;
 
+/// How long a poll waits for a child to print something before moving on.
+const STDERR_READ_TIMEOUT: Duration = Duration::from_millis(10);
+
 #[derive(Debug)]
 enum CheckerStatus {
     Starting,
-    Ready(Vec<Child>, mpsc::Receiver<CheckerTask>),
+    Ready(Vec<RunningChild>, mpsc::Receiver<CheckerTask>),
+}
+
+/// What a job runs and where it writes, which stays the same across its
+/// realesrgan passes and frames.
+#[derive(Debug)]
+struct ChildSpec {
+    job_id: JobId,
+    input_path: PathBuf,
+    /// Where the input is converted or cropped to, if the CLI is not given
+    /// it as is.
+    converted_input_path: Option<PathBuf>,
+    crop: Option<Region>,
+    /// Where the frames or pages of an input are split to and upscaled in.
+    frames_dir: Option<PathBuf>,
+    split: Option<Split>,
+    output_path: PathBuf,
+    partial_path: PathBuf,
+    upscale_ratio: u32,
+    passes: u32,
+    gpu_id: String,
+    model_path: String,
+    model_name: String,
+    tta_mode: bool,
+    post_process: PostProcess,
+}
+
+#[derive(Debug)]
+enum Stage {
+    Converting(JoinHandle<Result<(), String>>),
+    Splitting(JoinHandle<Result<Frames, String>>),
+    /// ffmpeg extracting the frames of a video.
+    Extracting(Ffmpeg),
+    /// A realesrgan pass, over the input or over the whole folder of frames.
+    Upscaling {
+        pass: u32,
+        child: Child,
+        stderr: Option<Lines<BufReader<ChildStderr>>>,
+        /// How many frames the pass has written so far.
+        written: u32,
+    },
+    PostProcessing(JoinHandle<Result<(), String>>, ExitStatus),
+    /// ffmpeg encoding the upscaled frames of a video.
+    Encoding(Ffmpeg, ExitStatus),
+}
+
+/// What an input has been split into.
+#[derive(Debug)]
+enum Frames {
+    Animation(Animation),
+    /// The number of frames extracted from a video.
+    Video(Video, u32),
+    Archive(Archive, f64),
+}
+
+/// How an input is split into frames, each upscaled on its own.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Split {
+    Animation,
+    /// Videos are put back together by ffmpeg.
+    Video(Video),
+    /// The pages of a comic archive, all scaled by `factor` if resized.
+    Archive {
+        factor: f64,
+    },
+}
+
+#[derive(Debug)]
+struct RunningChild {
+    spec: ChildSpec,
+    stage: Stage,
+    /// The frames being upscaled, once the input has been split into them.
+    frames: Option<Frames>,
+    started_at: Instant,
+    last_output_at: Instant,
+    timeout: Option<Duration>,
+    stall_timeout: Option<Duration>,
 }
 
 #[derive(Clone, Debug)]
 pub enum CheckerResult {
     Ended,
-    ChildLog(u32, String),        // (pid, message)
-    ChildExited(u32, ExitStatus), // (pid, error_code)
-    ChildErrored(u32, String),    // (pid, error)
-    SpawnError(String),
+    ChildSpawned(JobId, u32), // (job, pid)
+    ChildLog(JobId, String),  // (job, message)
+    ChildConverting(JobId),
+    ChildSplitting(JobId),
+    ChildProgress(JobId, f32), // (job, percentage of the frames written)
+    ChildPostProcessing(JobId),
+    ChildEncoding(JobId),
+    ChildExited(JobId, ExitStatus), // (job, error_code)
+    ChildErrored(JobId, String),    // (job, error)
+    ChildTimedOut(JobId, String),   // (job, reason)
+    ChildCancelled(JobId),
+    SpawnError(JobId, String), // (job, error)
 }
 
 pub enum CheckerTask {
     NewChild {
+        job_id: JobId,
         input_path: OsString,
+        /// Whether the input has to be converted to PNG for the CLI.
+        convert_input: bool,
+        /// The region of the input to upscale, which is written to a PNG
+        /// in place of the converted input.
+        crop: Option<Box<Region>>,
+        split: Option<Box<Split>>,
         output_path: OsString,
         upscale_ratio: u32,
+        passes: u32,
         gpu_id: String,
         model_path: String,
         model_name: String,
         tta_mode: bool,
+        timeout: Option<Duration>,
+        stall_timeout: Option<Duration>,
+        post_process: Box<PostProcess>,
     },
     Poll,
+    /// Kills the child of one job and deletes whatever it has written so far.
+    Cancel(JobId),
+    /// Kills every running child and deletes whatever it has written so far.
+    CancelAll,
 }
 
 impl ChildrenStatusChecker {
@@ -65,56 +181,104 @@ impl ChildrenStatusChecker {
 
                             match input {
                                 CheckerTask::NewChild {
+                                    job_id,
                                     input_path,
+                                    convert_input,
+                                    crop,
+                                    split,
                                     output_path,
                                     upscale_ratio,
+                                    passes,
                                     gpu_id,
                                     model_path,
                                     model_name,
                                     tta_mode,
+                                    timeout,
+                                    stall_timeout,
+                                    post_process,
                                 } => {
-                                    let mut child = Command::new("./realesrgan-ncnn-vulkan-cli");
-                                    let mut child = child
-                                        .stderr(std::process::Stdio::piped())
-                                        .arg("-i")
-                                        .arg(input_path)
-                                        .arg("-o")
-                                        .arg(output_path)
-                                        .arg("-s")
-                                        .arg(upscale_ratio.to_string());
-
-                                    if !gpu_id.is_empty() {
-                                        child = child.arg("-g").arg(gpu_id);
-                                    }
+                                    let input_path = PathBuf::from(input_path);
+                                    let output_path = PathBuf::from(output_path);
 
-                                    if !model_path.is_empty() {
-                                        child = child.arg("-m").arg(&model_path);
-                                    }
+                                    let spec = ChildSpec {
+                                        job_id,
+                                        converted_input_path: (convert_input || crop.is_some())
+                                            .then(|| partial::converted_input_path(&output_path)),
+                                        crop: crop.map(|crop| *crop),
+                                        frames_dir: split
+                                            .is_some()
+                                            .then(|| partial::frames_dir(&output_path)),
+                                        split: split.map(|split| *split),
+                                        input_path,
+                                        partial_path: partial::partial_path(&output_path),
+                                        output_path,
+                                        upscale_ratio,
+                                        passes: passes.max(1),
+                                        gpu_id,
+                                        model_path,
+                                        model_name,
+                                        tta_mode,
+                                        post_process: *post_process,
+                                    };
 
-                                    if !model_name.is_empty() {
-                                        child = child.arg("-n").arg(&model_name);
+                                    // Failing to record them only means a leftover
+                                    // would not be cleaned up after a crash.
+                                    for path in spec.temporary_paths() {
+                                        let _ = partial::record(&path);
                                     }
 
-                                    if tta_mode {
-                                        child = child.arg("-x");
-                                    }
+                                    let result = match spec.start() {
+                                        Ok(stage) => {
+                                            let now = Instant::now();
+                                            let result = match stage.pid() {
+                                                Some(pid) => {
+                                                    CheckerResult::ChildSpawned(job_id, pid)
+                                                }
+                                                None if spec.frames_dir.is_some() => {
+                                                    CheckerResult::ChildSplitting(job_id)
+                                                }
+                                                None => CheckerResult::ChildConverting(job_id),
+                                            };
+
+                                            children.push(RunningChild {
+                                                spec,
+                                                stage,
+                                                frames: None,
+                                                started_at: now,
+                                                last_output_at: now,
+                                                timeout,
+                                                stall_timeout,
+                                            });
 
-                                    match child.spawn() {
-                                        Ok(c) => children.push(c),
+                                            result
+                                        }
                                         Err(e) => {
-                                            // TODO: is unwrap() good here?
-                                            output
-                                                .send(Message::ChildUpdate(
-                                                    CheckerResult::SpawnError(e.to_string()),
-                                                ))
-                                                .await
-                                                .unwrap();
+                                            spec.discard();
+                                            CheckerResult::SpawnError(job_id, e.to_string())
                                         }
                                     };
+
+                                    // TODO: is unwrap() good here?
+                                    output.send(Message::ChildUpdate(result)).await.unwrap();
                                 }
                                 CheckerTask::Poll => {
                                     Self::check_children_status(children, &mut output).await;
                                 }
+                                CheckerTask::Cancel(job_id) => {
+                                    let Some(i) =
+                                        children.iter().position(|c| c.spec.job_id == job_id)
+                                    else {
+                                        continue;
+                                    };
+
+                                    let result = Self::cancel_child(children.remove(i)).await;
+
+                                    // TODO: is unwrap() good here?
+                                    output.send(Message::ChildUpdate(result)).await.unwrap();
+                                }
+                                CheckerTask::CancelAll => {
+                                    Self::cancel_children(children, &mut output).await;
+                                }
                             }
                         }
                     }
@@ -123,7 +287,10 @@ impl ChildrenStatusChecker {
         )
     }
 
-    async fn check_children_status(children: &mut Vec<Child>, output: &mut mpsc::Sender<Message>) {
+    async fn check_children_status(
+        children: &mut Vec<RunningChild>,
+        output: &mut mpsc::Sender<Message>,
+    ) {
         if children.is_empty() {
             return;
         }
@@ -132,53 +299,153 @@ impl ChildrenStatusChecker {
 
         while i < children.len() {
             let c = &mut children[i];
+            let job_id = c.spec.job_id;
 
-            let should_remove = match c.try_status() {
-                Ok(None) => {
-                    let pid = c.id();
+            let result = match &mut c.stage {
+                Stage::Converting(handle) => match handle.now_or_never() {
+                    Some(Ok(())) => Some(c.spawn_step(0)),
+                    Some(Err(e)) => {
+                        c.spec.discard();
+
+                        let action = if c.spec.crop.is_some() {
+                            "crop"
+                        } else {
+                            "convert"
+                        };
+
+                        Some(CheckerResult::ChildErrored(
+                            job_id,
+                            format!("unable to {} the input: {}", action, e),
+                        ))
+                    }
+                    None => None,
+                },
+
+                Stage::Splitting(handle) => match handle.now_or_never() {
+                    Some(Ok(frames)) => {
+                        c.frames = Some(frames);
+                        Some(c.spawn_step(0))
+                    }
+                    Some(Err(e)) => Some(c.split_failed(e)),
+                    None => None,
+                },
+
+                Stage::Extracting(ffmpeg) => match ffmpeg.try_finish().await {
+                    Some(Ok(())) => Some(c.extracted()),
+                    Some(Err(e)) => Some(c.split_failed(e)),
+                    None => None,
+                },
+
+                Stage::Upscaling {
+                    pass,
+                    child,
+                    stderr,
+                    written,
+                } => {
+                    let pass = *pass;
+
+                    // Drain whatever the child has printed since the last poll.
+                    // The read is bounded so that a silent (possibly hung)
+                    // child cannot block the checker.
+                    while let Some(lines) = stderr.as_mut() {
+                        match async_std::future::timeout(STDERR_READ_TIMEOUT, lines.next()).await {
+                            Ok(Some(Ok(log))) => {
+                                c.last_output_at = Instant::now();
 
-                    if let Some(stderr) = c.stderr.as_mut().take() {
-                        let mut reader = BufReader::new(stderr);
-                        let mut log = String::new();
-                        match reader.read_line(&mut log).await {
-                            Ok(_) => {
                                 // TODO: is unwrap() good here?
                                 output
-                                    .send(Message::ChildUpdate(CheckerResult::ChildLog(pid, log)))
+                                    .send(Message::ChildUpdate(CheckerResult::ChildLog(
+                                        job_id, log,
+                                    )))
                                     .await
                                     .unwrap();
                             }
-                            Err(_) => (),
+                            Ok(Some(Err(_))) | Ok(None) => *stderr = None,
+                            Err(_) => break,
+                        }
+                    }
+
+                    match child.try_status() {
+                        // The CLI only reports the progress of each frame, so
+                        // that over all of them is told by the files written.
+                        Ok(None) => match (&c.frames, &c.spec.frames_dir) {
+                            (Some(frames), Some(frames_dir)) => {
+                                let count = fs::read_dir(animation::step_dir(frames_dir, pass + 1))
+                                    .map_or(0, |entries| entries.count() as u32);
+
+                                (count != *written).then(|| {
+                                    *written = count;
+                                    c.last_output_at = Instant::now();
+
+                                    let progress = count.min(frames.len()) as f32 * 100.0
+                                        / frames.len() as f32;
+                                    CheckerResult::ChildProgress(job_id, progress)
+                                })
+                            }
+                            _ => None,
+                        },
+
+                        Ok(Some(status)) => Some(c.advance(pass, status)),
+
+                        Err(e) => {
+                            c.spec.discard();
+                            Some(CheckerResult::ChildErrored(job_id, e.to_string()))
                         }
                     }
-                    false
                 }
 
-                Ok(Some(status)) => {
-                    // TODO: is unwrap() good here?
-                    output
-                        .send(Message::ChildUpdate(CheckerResult::ChildExited(
-                            c.id(),
-                            status,
-                        )))
-                        .await
-                        .unwrap();
-                    true
+                Stage::PostProcessing(handle, status) => {
+                    let status = *status;
+
+                    match handle.now_or_never() {
+                        Some(Ok(())) => Some(c.post_processed(status)),
+                        Some(Err(e)) => {
+                            c.spec.discard();
+                            Some(CheckerResult::ChildErrored(job_id, e))
+                        }
+                        None => None,
+                    }
                 }
 
-                Err(e) => {
-                    // TODO: is unwrap() good here?
-                    output
-                        .send(Message::ChildUpdate(CheckerResult::ChildErrored(
-                            c.id(),
-                            e.to_string(),
-                        )))
-                        .await
-                        .unwrap();
-                    true
+                Stage::Encoding(ffmpeg, status) => {
+                    let status = *status;
+
+                    match ffmpeg.try_finish().await {
+                        Some(Ok(())) => Some(c.spec.finish(status)),
+                        Some(Err(e)) => {
+                            c.spec.discard();
+                            Some(CheckerResult::ChildErrored(
+                                job_id,
+                                format!("unable to encode the video: {}", e),
+                            ))
+                        }
+                        None => None,
+                    }
                 }
             };
 
+            // Whatever is still running is stopped once it takes too long.
+            let result = match result {
+                Some(result) => Some(result),
+                None => c.time_out().await,
+            };
+
+            // The job goes on if it has only made progress, or moved to its
+            // next pass, to post-processing or to encoding.
+            let should_remove = match result {
+                Some(CheckerResult::ChildSpawned(..))
+                | Some(CheckerResult::ChildProgress(..))
+                | Some(CheckerResult::ChildPostProcessing(_))
+                | Some(CheckerResult::ChildEncoding(_))
+                | None => false,
+                Some(_) => true,
+            };
+
+            if let Some(result) = result {
+                // TODO: is unwrap() good here?
+                output.send(Message::ChildUpdate(result)).await.unwrap();
+            }
+
             if should_remove {
                 children.remove(i);
             } else {
@@ -194,4 +461,603 @@ impl ChildrenStatusChecker {
                 .unwrap();
         }
     }
+
+    async fn cancel_children(children: &mut Vec<RunningChild>, output: &mut mpsc::Sender<Message>) {
+        for c in children.drain(..) {
+            let result = Self::cancel_child(c).await;
+
+            // TODO: is unwrap() good here?
+            output.send(Message::ChildUpdate(result)).await.unwrap();
+        }
+
+        // TODO: is unwrap good here?
+        output
+            .send(Message::ChildUpdate(CheckerResult::Ended))
+            .await
+            .unwrap();
+    }
+
+    async fn cancel_child(mut c: RunningChild) -> CheckerResult {
+        let job_id = c.spec.job_id;
+
+        match &mut c.stage {
+            // Neither converting nor post-processing can be interrupted,
+            // but they do not take long either. ffmpeg is killed instead.
+            Stage::Converting(handle) => {
+                let _ = handle.await;
+                c.spec.discard();
+                CheckerResult::ChildCancelled(job_id)
+            }
+
+            Stage::Splitting(handle) => {
+                let _ = handle.await;
+                c.spec.discard();
+                CheckerResult::ChildCancelled(job_id)
+            }
+
+            Stage::Extracting(ffmpeg) => {
+                let _ = ffmpeg.kill().await;
+                c.spec.discard();
+                CheckerResult::ChildCancelled(job_id)
+            }
+
+            Stage::PostProcessing(handle, status) => {
+                let status = *status;
+
+                match handle.await {
+                    // The frames of a video are yet to be encoded.
+                    Ok(()) if c.frames.is_some() => {
+                        c.spec.discard();
+                        CheckerResult::ChildCancelled(job_id)
+                    }
+                    Ok(()) => c.spec.finish(status),
+                    Err(e) => {
+                        c.spec.discard();
+                        CheckerResult::ChildErrored(job_id, e)
+                    }
+                }
+            }
+
+            Stage::Upscaling { pass, child, .. } => {
+                // The last pass may have finished since the last poll, in
+                // which case its output is complete and should be kept.
+                let finished = match child.try_status() {
+                    Ok(Some(status)) if status.success() && c.spec.is_last_step(*pass) => {
+                        Some(status)
+                    }
+                    _ => None,
+                };
+
+                match finished {
+                    Some(status) => c.spec.finish(status),
+                    None => {
+                        let _ = c.kill().await;
+                        c.spec.discard();
+                        CheckerResult::ChildCancelled(job_id)
+                    }
+                }
+            }
+
+            Stage::Encoding(ffmpeg, status) => {
+                let status = *status;
+
+                // Likewise, the video may have been encoded since.
+                match ffmpeg.try_finish().await {
+                    Some(Ok(())) => c.spec.finish(status),
+                    _ => {
+                        let _ = ffmpeg.kill().await;
+                        c.spec.discard();
+                        CheckerResult::ChildCancelled(job_id)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Kills `child` and waits for it to exit. It may have exited on its own
+/// since it was last polled, in which case kill() fails harmlessly.
+pub async fn kill(child: &mut Child) -> io::Result<()> {
+    let killed = child.kill();
+    let _ = child.status().await;
+
+    killed
+}
+
+impl Stage {
+    fn pid(&self) -> Option<u32> {
+        match self {
+            Stage::Upscaling { child, .. } => Some(child.id()),
+            Stage::Converting(_)
+            | Stage::Splitting(_)
+            | Stage::Extracting(_)
+            | Stage::PostProcessing(..)
+            | Stage::Encoding(..) => None,
+        }
+    }
+}
+
+impl Frames {
+    fn len(&self) -> u32 {
+        match self {
+            Frames::Animation(animation) => animation.frames.len() as u32,
+            Frames::Video(_, count) => *count,
+            Frames::Archive(archive, _) => archive.pages.len() as u32,
+        }
+    }
+}
+
+impl ChildSpec {
+    /// Whether `pass` writes the output directly, with nothing left to do
+    /// after it but moving the output into place.
+    fn is_last_step(&self, pass: u32) -> bool {
+        self.frames_dir.is_none() && pass + 1 == self.passes && !self.post_process.is_needed()
+    }
+
+    /// Where realesrgan pass `pass` writes to: a file for a single image, or
+    /// the folder of the step after it for frames.
+    fn pass_output(&self, pass: u32) -> PathBuf {
+        if let Some(frames_dir) = &self.frames_dir {
+            animation::step_dir(frames_dir, pass + 1)
+        } else if self.is_last_step(pass) {
+            self.partial_path.clone()
+        } else {
+            partial::intermediate_path(&self.output_path, pass)
+        }
+    }
+
+    /// Every file the job may write before its output is moved into place.
+    fn temporary_paths(&self) -> Vec<PathBuf> {
+        // The frames of an animation, video or archive are all kept in the
+        // same directory.
+        if let Some(frames_dir) = &self.frames_dir {
+            return vec![frames_dir.clone(), self.partial_path.clone()];
+        }
+
+        (0..self.passes)
+            .map(|pass| self.pass_output(pass))
+            .chain(Some(self.partial_path.clone()))
+            .chain(self.converted_input_path.clone())
+            .collect()
+    }
+
+    /// Starts the job, with splitting or converting its input if needed or
+    /// otherwise with its first pass.
+    fn start(&self) -> io::Result<Stage> {
+        if let Some(frames_dir) = self.frames_dir.clone() {
+            if let Some(Split::Video(video)) = &self.split {
+                return video::extract(&self.input_path, &frames_dir, video).map(Stage::Extracting);
+            }
+
+            let input_path = self.input_path.clone();
+            let split = self.split.clone();
+
+            let handle = async_std::task::spawn_blocking(move || match split {
+                Some(Split::Archive { factor }) => archive::split(&input_path, &frames_dir)
+                    .map(|archive| Frames::Archive(archive, factor)),
+                _ => animation::split(&input_path, &frames_dir).map(Frames::Animation),
+            });
+
+            return Ok(Stage::Splitting(handle));
+        }
+
+        let Some(converted_input_path) = self.converted_input_path.clone() else {
+            return self.spawn_pass(0);
+        };
+
+        let input_path = self.input_path.clone();
+        let crop = self.crop;
+        let handle = async_std::task::spawn_blocking(move || match crop {
+            Some(region) => crop::extract(&input_path, region, &converted_input_path),
+            None => input::convert(&input_path, &converted_input_path),
+        });
+
+        Ok(Stage::Converting(handle))
+    }
+
+    /// Starts realesrgan pass `pass`, which runs over every frame at once if
+    /// the input has been split into them.
+    fn spawn_pass(&self, pass: u32) -> io::Result<Stage> {
+        let input_path = match (&self.frames_dir, pass) {
+            (Some(frames_dir), 0) => animation::step_dir(frames_dir, 0),
+            (None, 0) => self
+                .converted_input_path
+                .clone()
+                .unwrap_or_else(|| self.input_path.clone()),
+            _ => self.pass_output(pass - 1),
+        };
+
+        let output_path = self.pass_output(pass);
+
+        // The CLI only writes to a folder that is already there.
+        if self.frames_dir.is_some() {
+            fs::create_dir_all(&output_path)?;
+        }
+
+        let mut child = self.spawn(&input_path, &output_path)?;
+        let stderr = child
+            .stderr
+            .take()
+            .map(|stderr| BufReader::new(stderr).lines());
+
+        Ok(Stage::Upscaling {
+            pass,
+            child,
+            stderr,
+            written: 0,
+        })
+    }
+
+    fn spawn(&self, input_path: &Path, output_path: &Path) -> io::Result<Child> {
+        let mut child = Command::new("./realesrgan-ncnn-vulkan-cli");
+        let mut child = child
+            .stderr(std::process::Stdio::piped())
+            .arg("-i")
+            .arg(input_path)
+            .arg("-o")
+            .arg(output_path)
+            .arg("-s")
+            .arg(self.upscale_ratio.to_string());
+
+        if !self.gpu_id.is_empty() {
+            child = child.arg("-g").arg(&self.gpu_id);
+        }
+
+        if !self.model_path.is_empty() {
+            child = child.arg("-m").arg(&self.model_path);
+        }
+
+        if !self.model_name.is_empty() {
+            child = child.arg("-n").arg(&self.model_name);
+        }
+
+        if self.tta_mode {
+            child = child.arg("-x");
+        }
+
+        // Frames keep their names, and so their format, when a whole folder
+        // is upscaled.
+        if self.frames_dir.is_some() {
+            child = child.arg("-f").arg("png");
+        }
+
+        child.spawn()
+    }
+
+    /// Moves the verified output into place and removes the intermediates.
+    fn finish(&self, status: ExitStatus) -> CheckerResult {
+        let finished = match &self.split {
+            Some(Split::Video(video)) => partial::finish_verified(
+                &self.partial_path,
+                &self.output_path,
+                video::dimensions(&self.partial_path, &video.settings.ffmpeg_path),
+            ),
+            Some(Split::Archive { .. }) => partial::finish_verified(
+                &self.partial_path,
+                &self.output_path,
+                archive::verify(&self.partial_path),
+            ),
+            _ => partial::finish(&self.partial_path, &self.output_path),
+        };
+
+        let result = match finished {
+            Ok(()) => CheckerResult::ChildExited(self.job_id, status),
+            Err(e) => CheckerResult::ChildErrored(self.job_id, e),
+        };
+
+        self.discard();
+        result
+    }
+
+    /// Removes whatever the job has written but not moved into place.
+    fn discard(&self) {
+        let paths = self.temporary_paths();
+
+        for path in &paths {
+            partial::discard(path);
+        }
+
+        // Failing to only means they are looked for again on the next launch.
+        let _ = partial::forget(&paths);
+    }
+}
+
+impl RunningChild {
+    /// Runs realesrgan pass `pass`.
+    fn spawn_step(&mut self, pass: u32) -> CheckerResult {
+        let job_id = self.spec.job_id;
+
+        match self.spec.spawn_pass(pass) {
+            Ok(stage) => {
+                let pid = stage.pid().unwrap_or_default();
+                self.stage = stage;
+                self.last_output_at = Instant::now();
+                CheckerResult::ChildSpawned(job_id, pid)
+            }
+            Err(e) => {
+                self.spec.discard();
+                CheckerResult::SpawnError(job_id, e.to_string())
+            }
+        }
+    }
+
+    fn split_failed(&mut self, e: String) -> CheckerResult {
+        self.spec.discard();
+        CheckerResult::ChildErrored(
+            self.spec.job_id,
+            format!("unable to split the input into frames: {}", e),
+        )
+    }
+
+    /// Moves on to upscaling the frames ffmpeg has extracted from a video.
+    fn extracted(&mut self) -> CheckerResult {
+        let (Some(Split::Video(video)), Some(frames_dir)) =
+            (&self.spec.split, &self.spec.frames_dir)
+        else {
+            return self.split_failed(String::from("the input is not a video"));
+        };
+
+        match video::count_frames(frames_dir) {
+            Ok(count) => {
+                self.frames = Some(Frames::Video(video.clone(), count));
+                self.spawn_step(0)
+            }
+            Err(e) => self.split_failed(e),
+        }
+    }
+
+    /// Moves on once `pass` has exited: to the next pass, to post-processing,
+    /// or to finishing the job.
+    fn advance(&mut self, pass: u32, status: ExitStatus) -> CheckerResult {
+        let job_id = self.spec.job_id;
+
+        if !status.success() {
+            self.spec.discard();
+            return CheckerResult::ChildExited(job_id, status);
+        }
+
+        if pass + 1 < self.spec.passes {
+            return self.spawn_step(pass + 1);
+        }
+
+        if let Some(frames) = self.frames.take() {
+            let post_process = self.spec.post_process.clone();
+            let input_path = self.spec.input_path.clone();
+            let partial_path = self.spec.partial_path.clone();
+            let upscaled = self.spec.pass_output(pass);
+
+            let handle = match frames {
+                Frames::Video(video, _) if !post_process.resizes() => {
+                    return self.encode(video, &upscaled, status);
+                }
+                Frames::Video(video, count) => {
+                    // Resized into a step of their own, after the last pass.
+                    let resized = self.spec.pass_output(pass + 1);
+                    self.frames = Some(Frames::Video(video, count));
+
+                    async_std::task::spawn_blocking(move || {
+                        video::resize_frames(&upscaled, &resized, count, &post_process)
+                    })
+                }
+                Frames::Archive(archive, factor) => {
+                    let upscaled = (0..archive.pages.len() as u32)
+                        .map(|frame| upscaled.join(animation::frame_file_name(frame)))
+                        .collect::<Vec<_>>();
+
+                    async_std::task::spawn_blocking(move || {
+                        archive::repack(
+                            &input_path,
+                            &archive,
+                            &upscaled,
+                            &post_process,
+                            factor,
+                            &partial_path,
+                        )
+                    })
+                }
+                Frames::Animation(animation) => {
+                    let upscaled = (0..animation.frames.len() as u32)
+                        .map(|frame| upscaled.join(animation::frame_file_name(frame)))
+                        .collect::<Vec<_>>();
+
+                    async_std::task::spawn_blocking(move || {
+                        post_process.run_animation(
+                            &input_path,
+                            &animation,
+                            &upscaled,
+                            &partial_path,
+                        )
+                    })
+                }
+            };
+
+            self.stage = Stage::PostProcessing(handle, status);
+            return CheckerResult::ChildPostProcessing(job_id);
+        }
+
+        if self.spec.post_process.is_needed() {
+            let post_process = self.spec.post_process.clone();
+            let upscaled = self.spec.pass_output(pass);
+            let input_path = self.spec.input_path.clone();
+            let partial_path = self.spec.partial_path.clone();
+
+            let handle = async_std::task::spawn_blocking(move || {
+                post_process.run(&input_path, &upscaled, &partial_path)
+            });
+
+            self.stage = Stage::PostProcessing(handle, status);
+            return CheckerResult::ChildPostProcessing(job_id);
+        }
+
+        self.spec.finish(status)
+    }
+
+    /// Moves on once post-processing is done: to encoding the resized frames
+    /// of a video, or to finishing the job.
+    fn post_processed(&mut self, status: ExitStatus) -> CheckerResult {
+        match self.frames.take() {
+            Some(Frames::Video(video, _)) => {
+                let resized = self.spec.pass_output(self.spec.passes);
+                self.encode(video, &resized, status)
+            }
+            _ => self.spec.finish(status),
+        }
+    }
+
+    /// Starts ffmpeg on the upscaled frames of a video, kept in `frames`.
+    fn encode(&mut self, video: Video, frames: &Path, status: ExitStatus) -> CheckerResult {
+        let job_id = self.spec.job_id;
+
+        match video::encode(
+            &self.spec.input_path,
+            &video,
+            frames,
+            &self.spec.partial_path,
+        ) {
+            Ok(ffmpeg) => {
+                self.stage = Stage::Encoding(ffmpeg, status);
+                CheckerResult::ChildEncoding(job_id)
+            }
+            Err(e) => {
+                self.spec.discard();
+                CheckerResult::ChildErrored(job_id, format!("unable to run ffmpeg: {}", e))
+            }
+        }
+    }
+
+    /// Stops the job if it has taken too long. realesrgan and ffmpeg are
+    /// killed, and the other stages run out first, as they cannot be
+    /// interrupted and would write their files again once removed.
+    async fn time_out(&mut self) -> Option<CheckerResult> {
+        let reason = self.timeout_reason()?;
+        let reason = match self.kill().await {
+            Ok(()) => reason,
+            Err(e) => format!("{} (failed to kill: {})", reason, e),
+        };
+
+        self.spec.discard();
+        Some(CheckerResult::ChildTimedOut(self.spec.job_id, reason))
+    }
+
+    async fn kill(&mut self) -> io::Result<()> {
+        match &mut self.stage {
+            Stage::Upscaling { child, .. } => kill(child).await,
+            Stage::Extracting(ffmpeg) | Stage::Encoding(ffmpeg, _) => ffmpeg.kill().await,
+            Stage::Converting(handle) | Stage::PostProcessing(handle, _) => {
+                let _ = handle.await;
+                Ok(())
+            }
+            Stage::Splitting(handle) => {
+                let _ = handle.await;
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns why the job should be stopped, if it has exceeded either its
+    /// wall-clock timeout or, while realesrgan runs, as it is the only one to
+    /// report its progress, its no-progress timeout.
+    fn timeout_reason(&self) -> Option<String> {
+        let now = Instant::now();
+
+        if let Some(timeout) = self.timeout {
+            if now.duration_since(self.started_at) >= timeout {
+                return Some(format!("exceeded the time limit of {}s", timeout.as_secs()));
+            }
+        }
+
+        let upscaling = matches!(self.stage, Stage::Upscaling { .. });
+
+        if let Some(stall_timeout) = self.stall_timeout.filter(|_| upscaling) {
+            if now.duration_since(self.last_output_at) >= stall_timeout {
+                return Some(format!("no progress for {}s", stall_timeout.as_secs()));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn running(
+        stage: Stage,
+        ago: (u64, u64),
+        timeouts: (Option<u64>, Option<u64>),
+    ) -> RunningChild {
+        let now = Instant::now();
+        let secs = |s: Option<u64>| s.map(Duration::from_secs);
+
+        RunningChild {
+            spec: ChildSpec {
+                job_id: 0,
+                input_path: PathBuf::from("in.png"),
+                converted_input_path: None,
+                crop: None,
+                frames_dir: None,
+                split: None,
+                output_path: PathBuf::from("out.png"),
+                partial_path: PathBuf::from("out.partial.png"),
+                upscale_ratio: 4,
+                passes: 1,
+                gpu_id: String::from("auto"),
+                model_path: String::from("models"),
+                model_name: String::from("realesrgan-x4plus"),
+                tta_mode: false,
+                post_process: PostProcess::default(),
+            },
+            stage,
+            frames: None,
+            started_at: now - Duration::from_secs(ago.0),
+            last_output_at: now - Duration::from_secs(ago.1),
+            timeout: secs(timeouts.0),
+            stall_timeout: secs(timeouts.1),
+        }
+    }
+
+    fn converting() -> Stage {
+        Stage::Converting(async_std::task::spawn(async { Ok(()) }))
+    }
+
+    #[test]
+    fn times_out_past_the_time_limit_in_any_stage() {
+        let job = running(converting(), (10, 10), (Some(5), None));
+        assert_eq!(
+            job.timeout_reason().as_deref(),
+            Some("exceeded the time limit of 5s")
+        );
+
+        let job = running(converting(), (10, 10), (Some(60), None));
+        assert_eq!(job.timeout_reason(), None);
+    }
+
+    #[test]
+    fn times_out_without_progress_only_while_upscaling() {
+        // Stages other than upscaling report no progress to go by.
+        let job = running(converting(), (10, 10), (None, Some(5)));
+        assert_eq!(job.timeout_reason(), None);
+
+        // Any quickly exiting program stands in for realesrgan.
+        let child = Command::new(std::env::current_exe().unwrap())
+            .arg("--list")
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        let upscaling = Stage::Upscaling {
+            pass: 0,
+            child,
+            stderr: None,
+            written: 0,
+        };
+
+        let mut job = running(upscaling, (10, 10), (Some(60), Some(5)));
+        assert_eq!(job.timeout_reason().as_deref(), Some("no progress for 5s"));
+
+        job.last_output_at = Instant::now();
+        assert_eq!(job.timeout_reason(), None);
+
+        async_std::task::block_on(job.kill()).unwrap();
+    }
 }
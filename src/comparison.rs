@@ -0,0 +1,257 @@
+//! Running one input through several models and TTA settings at once, and
+//! putting the results side by side on a labelled contact sheet.
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+
+use crate::crop::Region;
+use crate::input;
+use crate::job::JobId;
+
+/// The longest side of a tile on the contact sheet.
+const TILE_SIZE: u32 = 1024;
+const COLUMNS: u32 = 3;
+const GAP: u32 = 8;
+const LABEL_HEIGHT: u32 = 36;
+const LABEL_SIZE: f32 = 24.0;
+/// How small labels are made to fit narrow tiles, before they are cut off.
+const MIN_LABEL_SIZE: f32 = 10.0;
+
+const BACKGROUND: Rgba<u8> = Rgba([32, 32, 32, 255]);
+const LABEL_COLOR: [u8; 3] = [255, 255, 255];
+
+/// Which TTA settings each model is compared with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TtaVariants {
+    #[default]
+    Off,
+    On,
+    Both,
+}
+
+impl TtaVariants {
+    pub const ALL: [TtaVariants; 3] = [TtaVariants::Off, TtaVariants::On, TtaVariants::Both];
+
+    fn modes(self) -> &'static [bool] {
+        match self {
+            TtaVariants::Off => &[false],
+            TtaVariants::On => &[true],
+            TtaVariants::Both => &[false, true],
+        }
+    }
+}
+
+impl fmt::Display for TtaVariants {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TtaVariants::Off => "Without TTA",
+            TtaVariants::On => "With TTA",
+            TtaVariants::Both => "With and without TTA",
+        })
+    }
+}
+
+/// A model and TTA setting an input is upscaled with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    /// Empty for the CLI's default model.
+    pub model: String,
+    pub tta: bool,
+}
+
+impl Variant {
+    pub fn model_name<'a>(&'a self, default_model: &'a str) -> &'a str {
+        if self.model.is_empty() {
+            default_model
+        } else {
+            &self.model
+        }
+    }
+
+    /// What `{model}` stands for in the names of the outputs, so that each
+    /// variant gets its own.
+    pub fn file_tag(&self, default_model: &str) -> String {
+        if self.tta {
+            format!("{}-tta", self.model_name(default_model))
+        } else {
+            self.model_name(default_model).to_owned()
+        }
+    }
+
+    fn label(&self, default_model: &str) -> String {
+        if self.tta {
+            format!("{} (TTA)", self.model_name(default_model))
+        } else {
+            self.model_name(default_model).to_owned()
+        }
+    }
+}
+
+/// Parses the models to compare, separated by commas or spaces, into every
+/// combination with `tta`.
+pub fn parse_variants(models: &str, tta: TtaVariants) -> Result<Vec<Variant>, String> {
+    let mut seen = HashSet::new();
+    let models = models
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|model| !model.is_empty())
+        .filter(|model| seen.insert(*model))
+        .collect::<Vec<_>>();
+
+    if models.is_empty() {
+        return Err(String::from("Enter the models to compare."));
+    }
+
+    if models.len() < 2 && tta != TtaVariants::Both {
+        return Err(String::from("Enter at least two models to compare."));
+    }
+
+    Ok(models
+        .into_iter()
+        .flat_map(|model| {
+            tta.modes().iter().map(move |&tta| Variant {
+                model: model.to_owned(),
+                tta,
+            })
+        })
+        .collect())
+}
+
+/// The jobs started to compare models on one input, which the contact sheet
+/// is made from once they are all done.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub input: OsString,
+    pub crop: Option<Region>,
+    pub jobs: Vec<(JobId, Variant)>,
+    pub sheet_path: PathBuf,
+}
+
+/// Where the contact sheet of `input` is written.
+pub fn sheet_path(output_dir: &Path, input: &Path) -> PathBuf {
+    let mut file_name = OsString::from(input.file_stem().unwrap_or_default());
+    file_name.push("-models.png");
+
+    output_dir.join(file_name)
+}
+
+/// Lays out the input next to the output of each variant, all at the same
+/// size, each with its label underneath, and writes the sheet as a PNG.
+/// There has to be at least one output.
+pub fn contact_sheet(
+    input: &Path,
+    crop: Option<Region>,
+    outputs: &[(Variant, PathBuf)],
+    default_model: &str,
+    sheet_path: &Path,
+) -> Result<(), String> {
+    let open = |path: &Path, crop| {
+        input::decode(path, crop)
+            .map(|image| image.to_rgba8())
+            .map_err(|e| format!("{}: {}", path.to_string_lossy(), e))
+    };
+
+    let mut tiles = vec![(String::from("Input"), open(input, crop)?)];
+
+    for (variant, output) in outputs {
+        tiles.push((variant.label(default_model), open(output, None)?));
+    }
+
+    // Every tile is shown at the size of the first output, shrunk to fit.
+    let (width, height) = tiles[1].1.dimensions();
+    let shrink = (TILE_SIZE as f64 / width.max(height) as f64).min(1.0);
+    let tile_width = ((width as f64 * shrink).round() as u32).max(1);
+    let tile_height = ((height as f64 * shrink).round() as u32).max(1);
+
+    let columns = COLUMNS.min(tiles.len() as u32);
+    let rows = (tiles.len() as u32).div_ceil(columns);
+    let mut sheet = RgbaImage::from_pixel(
+        columns * (tile_width + GAP) + GAP,
+        rows * (tile_height + LABEL_HEIGHT + GAP) + GAP,
+        BACKGROUND,
+    );
+
+    let font = FontRef::try_from_slice(iced_graphics::font::FALLBACK).map_err(|e| e.to_string())?;
+
+    for (index, (label, image)) in tiles.into_iter().enumerate() {
+        // The input is enlarged without smoothing, so its pixels show.
+        let filter = if index == 0 {
+            FilterType::Nearest
+        } else {
+            FilterType::Lanczos3
+        };
+
+        let tile = if image.dimensions() == (tile_width, tile_height) {
+            image
+        } else {
+            imageops::resize(&image, tile_width, tile_height, filter)
+        };
+
+        let x = GAP + (index as u32 % columns) * (tile_width + GAP);
+        let y = GAP + (index as u32 / columns) * (tile_height + LABEL_HEIGHT + GAP);
+
+        imageops::overlay(&mut sheet, &tile, x as i64, y as i64);
+        draw_label(&mut sheet, &font, &label, x, y + tile_height, tile_width);
+    }
+
+    sheet
+        .save_with_format(sheet_path, image::ImageFormat::Png)
+        .map_err(|e| e.to_string())
+}
+
+/// Writes `label` in the band below a tile, smaller if it does not fit in
+/// `width` and otherwise cut off.
+fn draw_label(sheet: &mut RgbaImage, font: &FontRef, label: &str, x: u32, y: u32, width: u32) {
+    let full_width = {
+        let scaled = font.as_scaled(PxScale::from(LABEL_SIZE));
+        label
+            .chars()
+            .map(|c| scaled.h_advance(scaled.glyph_id(c)))
+            .sum::<f32>()
+    };
+    let room = width.saturating_sub(2 * GAP) as f32;
+    let size = (LABEL_SIZE * (room / full_width).min(1.0)).max(MIN_LABEL_SIZE);
+
+    let scaled = font.as_scaled(PxScale::from(size));
+    let baseline = y as f32 + (LABEL_HEIGHT as f32 + scaled.ascent() + scaled.descent()) / 2.0;
+    let mut caret = x as f32 + GAP as f32;
+    let mut previous = None;
+
+    for c in label.chars() {
+        let id = scaled.glyph_id(c);
+
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+
+        previous = Some(id);
+
+        let glyph = id.with_scale_and_position(size, point(caret, baseline));
+        caret += scaled.h_advance(id);
+
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+
+        let bounds = outlined.px_bounds();
+
+        outlined.draw(|gx, gy, coverage| {
+            let px = bounds.min.x as i64 + gx as i64;
+            let py = bounds.min.y as i64 + gy as i64;
+
+            if px < x as i64 || px >= (x + width) as i64 || py < 0 || py >= sheet.height() as i64 {
+                return;
+            }
+
+            let pixel = sheet.get_pixel_mut(px as u32, py as u32);
+            for (channel, color) in pixel.0.iter_mut().zip(LABEL_COLOR) {
+                *channel = (*channel as f32 * (1.0 - coverage) + color as f32 * coverage) as u8;
+            }
+        });
+    }
+}
@@ -0,0 +1,255 @@
+//! Cropping the input to a region of interest before it is upscaled, so
+//! that only the part that is needed goes through the CLI.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::input;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CropMode {
+    #[default]
+    None,
+    Pixels,
+    Percent,
+}
+
+impl CropMode {
+    pub const ALL: [CropMode; 3] = [CropMode::None, CropMode::Pixels, CropMode::Percent];
+
+    /// Parses the values typed next to the mode.
+    pub fn parse(
+        self,
+        x: &str,
+        y: &str,
+        width: &str,
+        height: &str,
+    ) -> Result<Option<Crop>, String> {
+        if self == CropMode::None {
+            return Ok(None);
+        }
+
+        let value = |value: &str, name| {
+            let value = value.trim();
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite() && *value >= 0.0)
+                .ok_or_else(|| format!("\"{}\" is not a valid crop {}.", value, name))
+        };
+
+        let (x, y) = (value(x, "position")?, value(y, "position")?);
+        let (width, height) = (value(width, "size")?, value(height, "size")?);
+
+        if width == 0.0 || height == 0.0 {
+            return Err(String::from("The crop region is empty."));
+        }
+
+        Ok(Some(match self {
+            CropMode::None => unreachable!(),
+            CropMode::Pixels => {
+                let pixels = |value: f64, name| {
+                    (value.fract() == 0.0 && value <= u32::MAX as f64)
+                        .then_some(value as u32)
+                        .ok_or_else(|| {
+                            format!(
+                                "\"{}\" is not a whole number of pixels for the {}.",
+                                value, name
+                            )
+                        })
+                };
+
+                Crop::Pixels(Region {
+                    x: pixels(x, "position")?,
+                    y: pixels(y, "position")?,
+                    width: pixels(width, "size")?,
+                    height: pixels(height, "size")?,
+                })
+            }
+            CropMode::Percent => {
+                if x + width > 100.0 || y + height > 100.0 {
+                    return Err(String::from("The crop region goes past 100%."));
+                }
+
+                Crop::Percent {
+                    x,
+                    y,
+                    width,
+                    height,
+                }
+            }
+        }))
+    }
+}
+
+impl fmt::Display for CropMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CropMode::None => "Whole image",
+            CropMode::Pixels => "Pixels",
+            CropMode::Percent => "Percent of size",
+        })
+    }
+}
+
+/// The crop set when a batch is started, applied to each of its inputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Crop {
+    /// The same region of every input.
+    Pixels(Region),
+    /// The same part of every input, whatever its size, in percent.
+    Percent {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+}
+
+impl Crop {
+    /// Works out the region of an input of size `dimensions`.
+    pub fn region(&self, dimensions: (u32, u32)) -> Result<Region, String> {
+        let (input_width, input_height) = dimensions;
+
+        let region = match *self {
+            Crop::Pixels(region) => region,
+            Crop::Percent {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let scaled =
+                    |percent: f64, length: u32| (percent / 100.0 * length as f64).round() as u32;
+                let x = scaled(x, input_width).min(input_width - 1);
+                let y = scaled(y, input_height).min(input_height - 1);
+
+                Region {
+                    x,
+                    y,
+                    width: scaled(width, input_width).clamp(1, input_width - x),
+                    height: scaled(height, input_height).clamp(1, input_height - y),
+                }
+            }
+        };
+
+        let fits = region.x as u64 + region.width as u64 <= input_width as u64
+            && region.y as u64 + region.height as u64 <= input_height as u64;
+
+        if !fits {
+            return Err(format!(
+                "The crop region {} does not fit in the {}x{} input.",
+                region, input_width, input_height
+            ));
+        }
+
+        Ok(region)
+    }
+}
+
+/// A rectangle of the input, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} at ({}, {})",
+            self.width, self.height, self.x, self.y
+        )
+    }
+}
+
+/// Decodes `input` and writes `region` of it to `cropped` as a PNG.
+pub fn extract(input: &Path, region: Region, cropped: &Path) -> Result<(), String> {
+    input::narrow_for_png(input::decode(input, Some(region))?)
+        .save_with_format(cropped, image::ImageFormat::Png)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(x: u32, y: u32, width: u32, height: u32) -> Region {
+        Region {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn parses_typed_values() {
+        assert_eq!(CropMode::None.parse("x", "", "", ""), Ok(None));
+        assert_eq!(
+            CropMode::Pixels.parse("10", " 20 ", "30", "40"),
+            Ok(Some(Crop::Pixels(region(10, 20, 30, 40))))
+        );
+        assert_eq!(
+            CropMode::Percent.parse("25", "0", "50", "100"),
+            Ok(Some(Crop::Percent {
+                x: 25.0,
+                y: 0.0,
+                width: 50.0,
+                height: 100.0,
+            }))
+        );
+
+        assert!(CropMode::Pixels.parse("1.5", "0", "10", "10").is_err());
+        assert!(CropMode::Pixels.parse("-1", "0", "10", "10").is_err());
+        assert!(CropMode::Pixels.parse("0", "0", "0", "10").is_err());
+        assert!(CropMode::Percent.parse("60", "0", "50", "10").is_err());
+        assert!(CropMode::Percent.parse("NaN", "0", "50", "10").is_err());
+    }
+
+    #[test]
+    fn keeps_pixel_regions_that_fit() {
+        let crop = Crop::Pixels(region(10, 20, 90, 80));
+
+        assert_eq!(crop.region((100, 100)), Ok(region(10, 20, 90, 80)));
+        assert!(crop.region((99, 100)).is_err());
+        assert!(crop.region((100, 99)).is_err());
+        assert!(Crop::Pixels(region(u32::MAX, 0, 1, 1))
+            .region((100, 100))
+            .is_err());
+    }
+
+    #[test]
+    fn works_out_percent_regions_within_the_input() {
+        let percent = |x, y, width, height| Crop::Percent {
+            x,
+            y,
+            width,
+            height,
+        };
+
+        assert_eq!(
+            percent(25.0, 50.0, 50.0, 50.0).region((200, 100)),
+            Ok(region(50, 50, 100, 50))
+        );
+        // Rounding never takes the region past the edge of the input.
+        assert_eq!(
+            percent(50.0, 50.0, 50.0, 50.0).region((101, 101)),
+            Ok(region(51, 51, 50, 50))
+        );
+        // Nor does it leave it empty.
+        assert_eq!(
+            percent(100.0, 0.0, 0.1, 0.1).region((10, 10)),
+            Ok(region(9, 0, 1, 1))
+        );
+    }
+}
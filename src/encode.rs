@@ -0,0 +1,340 @@
+//! Encoding of the final image, so the encoder settings on the Output page
+//! apply instead of whatever defaults the CLI uses.
+
+use std::fmt;
+
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{DynamicImage, ImageEncoder, Rgb, RgbImage};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChromaSubsampling {
+    /// No subsampling, for the sharpest colour edges.
+    Yuv444,
+    Yuv422,
+    #[default]
+    Yuv420,
+}
+
+impl ChromaSubsampling {
+    pub const ALL: [ChromaSubsampling; 3] = [
+        ChromaSubsampling::Yuv444,
+        ChromaSubsampling::Yuv422,
+        ChromaSubsampling::Yuv420,
+    ];
+}
+
+impl fmt::Display for ChromaSubsampling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ChromaSubsampling::Yuv444 => "4:4:4",
+            ChromaSubsampling::Yuv422 => "4:2:2",
+            ChromaSubsampling::Yuv420 => "4:2:0",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
+
+impl PngCompression {
+    pub const ALL: [PngCompression; 3] = [
+        PngCompression::Fast,
+        PngCompression::Default,
+        PngCompression::Best,
+    ];
+}
+
+impl fmt::Display for PngCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PngCompression::Fast => "Fast",
+            PngCompression::Default => "Default",
+            PngCompression::Best => "Best",
+        })
+    }
+}
+
+/// What is done with transparent inputs when the output format has no
+/// alpha channel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Transparency {
+    #[default]
+    Color,
+    Checkerboard,
+    /// Save transparent still images as PNG instead.
+    SwitchToPng,
+    /// Save transparent still images as WebP instead.
+    SwitchToWebp,
+}
+
+impl Transparency {
+    pub const ALL: [Transparency; 4] = [
+        Transparency::Color,
+        Transparency::Checkerboard,
+        Transparency::SwitchToPng,
+        Transparency::SwitchToWebp,
+    ];
+}
+
+impl fmt::Display for Transparency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Transparency::Color => "Flatten onto colour",
+            Transparency::Checkerboard => "Flatten onto checkerboard",
+            Transparency::SwitchToPng => "Switch to PNG",
+            Transparency::SwitchToWebp => "Switch to WebP",
+        })
+    }
+}
+
+/// What transparent pixels are blended onto in formats without alpha.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    Color([u8; 3]),
+    /// Light grey squares, the way image editors show transparency.
+    Checkerboard,
+}
+
+impl Background {
+    const SQUARE: u32 = 16;
+
+    fn at(self, x: u32, y: u32) -> [u8; 3] {
+        match self {
+            Background::Color(color) => color,
+            Background::Checkerboard => {
+                if (x / Self::SQUARE + y / Self::SQUARE).is_multiple_of(2) {
+                    [255; 3]
+                } else {
+                    [204; 3]
+                }
+            }
+        }
+    }
+
+    /// Blends `image` onto the background, dropping its alpha channel.
+    pub fn flatten(self, image: &DynamicImage) -> RgbImage {
+        if !image.color().has_alpha() {
+            return image.to_rgb8();
+        }
+
+        let rgba = image.to_rgba8();
+
+        RgbImage::from_fn(image.width(), image.height(), |x, y| {
+            let [r, g, b, a] = rgba.get_pixel(x, y).0;
+            let background = self.at(x, y);
+            let blend = |fg: u8, bg: u8| {
+                ((fg as u32 * a as u32 + bg as u32 * (255 - a as u32) + 127) / 255) as u8
+            };
+
+            Rgb([
+                blend(r, background[0]),
+                blend(g, background[1]),
+                blend(b, background[2]),
+            ])
+        })
+    }
+}
+
+/// The output format of a job, along with its encoder parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Png {
+        compression: PngCompression,
+    },
+    Jpeg {
+        /// From 1 to 100.
+        quality: u8,
+        subsampling: ChromaSubsampling,
+        /// What transparent pixels are flattened onto.
+        background: Background,
+    },
+    Webp {
+        /// From 0 to 100, ignored if `lossless` is set.
+        quality: f32,
+        lossless: bool,
+    },
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Png {
+            compression: PngCompression::Default,
+        }
+    }
+}
+
+impl Encoding {
+    /// Whether the CLI can write this itself, without the image being
+    /// re-encoded afterwards.
+    pub fn is_native(&self) -> bool {
+        *self == Encoding::default()
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Encoding::Png { .. } => "png",
+            Encoding::Jpeg { .. } => "jpg",
+            Encoding::Webp { .. } => "webp",
+        }
+    }
+
+    pub fn encode(&self, image: &DynamicImage) -> Result<Vec<u8>, String> {
+        let mut buffer = Vec::new();
+
+        match *self {
+            Encoding::Png { compression } => {
+                let compression = match compression {
+                    PngCompression::Fast => CompressionType::Fast,
+                    PngCompression::Default => CompressionType::Default,
+                    PngCompression::Best => CompressionType::Best,
+                };
+
+                PngEncoder::new_with_quality(&mut buffer, compression, FilterType::Adaptive)
+                    .write_image(
+                        image.as_bytes(),
+                        image.width(),
+                        image.height(),
+                        image.color(),
+                    )
+                    .map_err(|e| e.to_string())?;
+            }
+            Encoding::Jpeg {
+                quality,
+                subsampling,
+                background,
+            } => {
+                let (width, height) =
+                    match (u16::try_from(image.width()), u16::try_from(image.height())) {
+                        (Ok(w), Ok(h)) => (w, h),
+                        _ => return Err(String::from("The image is too large for a JPEG.")),
+                    };
+
+                let mut encoder = jpeg_encoder::Encoder::new(&mut buffer, quality.clamp(1, 100));
+                encoder.set_sampling_factor(match subsampling {
+                    ChromaSubsampling::Yuv444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+                    ChromaSubsampling::Yuv422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+                    ChromaSubsampling::Yuv420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+                });
+
+                encoder
+                    .encode(
+                        background.flatten(image).as_raw(),
+                        width,
+                        height,
+                        jpeg_encoder::ColorType::Rgb,
+                    )
+                    .map_err(|e| e.to_string())?;
+            }
+            Encoding::Webp { quality, lossless } => {
+                let rgba = image.to_rgba8();
+                let encoder =
+                    webp::Encoder::from_rgba(rgba.as_raw(), image.width(), image.height());
+
+                let memory = encoder
+                    .encode_simple(lossless, quality.clamp(0.0, 100.0))
+                    .map_err(|e| format!("WebP encoding failed: {:?}", e))?;
+
+                buffer.extend_from_slice(&memory);
+            }
+        }
+
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{ImageFormat, Rgba, RgbaImage};
+
+    /// Half opaque red, half transparent.
+    fn image() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(32, 16, |x, _| {
+            Rgba(if x < 16 { [255, 0, 0, 255] } else { [0; 4] })
+        }))
+    }
+
+    fn decode(bytes: &[u8], format: ImageFormat) -> DynamicImage {
+        assert_eq!(image::guess_format(bytes).unwrap(), format);
+        image::load_from_memory(bytes).unwrap()
+    }
+
+    #[test]
+    fn encodes_png_losslessly() {
+        for compression in PngCompression::ALL {
+            let png = Encoding::Png { compression }.encode(&image()).unwrap();
+            assert_eq!(decode(&png, ImageFormat::Png), image());
+        }
+
+        let deep = DynamicImage::ImageRgb16(image().to_rgb16());
+        let png = Encoding::default().encode(&deep).unwrap();
+        assert_eq!(decode(&png, ImageFormat::Png), deep);
+    }
+
+    #[test]
+    fn encodes_jpeg_onto_the_background() {
+        let jpeg = Encoding::Jpeg {
+            quality: 90,
+            subsampling: ChromaSubsampling::Yuv444,
+            background: Background::Color([0, 0, 255]),
+        }
+        .encode(&image())
+        .unwrap();
+
+        let decoded = decode(&jpeg, ImageFormat::Jpeg).to_rgb8();
+        assert_eq!(decoded.dimensions(), (32, 16));
+
+        let close = |pixel: &Rgb<u8>, color: [u8; 3]| {
+            pixel.0.iter().zip(color).all(|(a, b)| a.abs_diff(b) <= 8)
+        };
+        assert!(close(decoded.get_pixel(4, 8), [255, 0, 0]));
+        assert!(close(decoded.get_pixel(28, 8), [0, 0, 255]));
+
+        let wide = DynamicImage::new_rgb8(u16::MAX as u32 + 1, 1);
+        assert!(Encoding::Jpeg {
+            quality: 90,
+            subsampling: ChromaSubsampling::Yuv420,
+            background: Background::Checkerboard,
+        }
+        .encode(&wide)
+        .is_err());
+    }
+
+    #[test]
+    fn encodes_webp_with_alpha() {
+        let lossless = Encoding::Webp {
+            quality: 0.0,
+            lossless: true,
+        }
+        .encode(&image())
+        .unwrap();
+        assert_eq!(
+            decode(&lossless, ImageFormat::WebP).to_rgba8(),
+            image().to_rgba8()
+        );
+
+        let lossy = Encoding::Webp {
+            quality: 75.0,
+            lossless: false,
+        }
+        .encode(&image())
+        .unwrap();
+        let decoded = decode(&lossy, ImageFormat::WebP).to_rgba8();
+        assert_eq!(decoded.dimensions(), (32, 16));
+        assert_eq!(decoded.get_pixel(28, 8).0[3], 0);
+    }
+
+    #[test]
+    fn flattens_transparency_onto_a_checkerboard() {
+        let flat = Background::Checkerboard.flatten(&DynamicImage::new_rgba8(32, 32));
+        assert_eq!(flat.get_pixel(0, 0).0, [255; 3]);
+        assert_eq!(flat.get_pixel(16, 0).0, [204; 3]);
+        assert_eq!(flat.get_pixel(16, 16).0, [255; 3]);
+    }
+}
@@ -0,0 +1,308 @@
+//! The image formats accepted as inputs. The CLI only reads PNG, JPEG and
+//! WebP, so anything else is converted to a temporary PNG first.
+//!
+//! Inputs are recognised by their content rather than by their extension,
+//! which is only trusted for TGA, as it has no signature to go by.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek};
+use std::path::Path;
+
+use image::codecs;
+use image::{ColorType, DynamicImage, ImageDecoder, ImageFormat, ImageResult};
+
+use crate::animation;
+use crate::archive;
+use crate::crop::Region;
+use crate::video::{self, VideoInfo};
+
+/// The formats offered in the input file dialog, by extension.
+pub const FILTERS: &[(&str, &[&str])] = &[
+    ("PNG images", &["png"]),
+    ("JPEG images", &["jpg", "jpeg"]),
+    ("WebP images", &["webp"]),
+    ("BMP images", &["bmp"]),
+    ("TIFF images", &["tif", "tiff"]),
+    ("TGA images", &["tga"]),
+    ("GIF images", &["gif"]),
+    #[cfg(feature = "avif")]
+    ("AVIF images", &["avif"]),
+    ("QOI images", &["qoi"]),
+];
+
+/// The formats the CLI reads by itself, provided the extension matches.
+const NATIVE_FORMATS: &[ImageFormat] = &[ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::WebP];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputFormat {
+    Image(ImageFormat),
+    /// A video, read through ffmpeg.
+    Video(VideoInfo),
+    /// A comic archive of images, in which the first page is described.
+    Archive,
+}
+
+/// What the content of an input turned out to be.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputInfo {
+    pub format: InputFormat,
+    pub dimensions: (u32, u32),
+    /// Bits per channel.
+    pub bit_depth: u16,
+    pub has_alpha: bool,
+    /// Whether the extension is missing or names another format.
+    pub extension_mismatch: bool,
+    /// 1 for still images.
+    pub frames: u32,
+}
+
+impl InputInfo {
+    /// Reads the header of `path` to find out what it holds, and decodes it
+    /// so that broken files are turned down before they reach the CLI. Fails
+    /// for files that are not in a supported format or cannot be decoded.
+    pub fn probe(path: &Path) -> Result<Self, String> {
+        let format = detect_format(path)
+            .ok_or_else(|| String::from("not an image in a supported format"))?;
+
+        let reader = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+        let (dimensions, color) = read_header(format, reader)?;
+        let frames = animation::frame_count(path, format)?;
+
+        // Of an animation, only the first frame is decoded here.
+        decode(path, None)?;
+
+        Ok(Self {
+            format: InputFormat::Image(format),
+            dimensions,
+            bit_depth: color.bits_per_pixel() / color.channel_count() as u16,
+            has_alpha: color.has_alpha(),
+            extension_mismatch: ImageFormat::from_path(path).ok() != Some(format),
+            frames,
+        })
+    }
+
+    /// Whether the input has to be converted before the CLI can read it.
+    /// The CLI picks its decoder by extension, so mislabelled files are
+    /// converted too. Animations are split into frames instead.
+    pub fn needs_conversion(&self) -> bool {
+        match self.format {
+            InputFormat::Image(format) => {
+                !self.is_animated()
+                    && (!NATIVE_FORMATS.contains(&format) || self.extension_mismatch)
+            }
+            InputFormat::Video(_) | InputFormat::Archive => false,
+        }
+    }
+
+    pub fn is_animated(&self) -> bool {
+        matches!(self.format, InputFormat::Image(_)) && self.frames > 1
+    }
+
+    /// Whether this is a single image, rather than frames or pages.
+    pub fn is_still(&self) -> bool {
+        matches!(self.format, InputFormat::Image(_)) && !self.is_animated()
+    }
+
+    pub fn video(&self) -> Option<&VideoInfo> {
+        match &self.format {
+            InputFormat::Video(info) => Some(info),
+            InputFormat::Image(_) | InputFormat::Archive => None,
+        }
+    }
+
+    /// The extension of an image format, or the codec of a video.
+    pub fn format_name(&self) -> &str {
+        match &self.format {
+            InputFormat::Image(format) => format.extensions_str()[0],
+            InputFormat::Video(info) => &info.codec,
+            InputFormat::Archive => "zip",
+        }
+    }
+}
+
+/// Reads the dimensions and colour type of an image in `format`, failing
+/// for empty images.
+pub fn read_header<R: BufRead + Seek>(
+    format: ImageFormat,
+    reader: R,
+) -> Result<((u32, u32), ColorType), String> {
+    let header = match format {
+        ImageFormat::Png => header(codecs::png::PngDecoder::new(reader)),
+        ImageFormat::Jpeg => header(codecs::jpeg::JpegDecoder::new(reader)),
+        ImageFormat::WebP => header(codecs::webp::WebPDecoder::new(reader)),
+        ImageFormat::Bmp => header(codecs::bmp::BmpDecoder::new(reader)),
+        ImageFormat::Tiff => header(codecs::tiff::TiffDecoder::new(reader)),
+        ImageFormat::Tga => header(codecs::tga::TgaDecoder::new(reader)),
+        ImageFormat::Gif => header(codecs::gif::GifDecoder::new(reader)),
+        ImageFormat::Qoi => header(codecs::qoi::QoiDecoder::new(reader)),
+        #[cfg(feature = "avif")]
+        ImageFormat::Avif => header(codecs::avif::AvifDecoder::new(reader)),
+        _ => return Err(String::from("not an image in a supported format")),
+    };
+
+    let (dimensions, color) = header.map_err(|e| e.to_string())?;
+
+    if dimensions.0 == 0 || dimensions.1 == 0 {
+        return Err(String::from("the image is empty"));
+    }
+
+    Ok((dimensions, color))
+}
+
+fn header<'a, D: ImageDecoder<'a>>(
+    decoder: ImageResult<D>,
+) -> ImageResult<((u32, u32), ColorType)> {
+    let decoder = decoder?;
+    Ok((decoder.dimensions(), decoder.color_type()))
+}
+
+/// Guesses the format of `path` from its first bytes, falling back to the
+/// extension for formats without a signature.
+fn detect_format(path: &Path) -> Option<ImageFormat> {
+    let mut start = [0; 32];
+    let mut file = File::open(path).ok()?;
+    let read = file.read(&mut start).ok()?;
+
+    match image::guess_format(&start[..read]) {
+        Ok(format) => Some(format).filter(|format| is_supported_format(*format)),
+        Err(_) => ImageFormat::from_path(path)
+            .ok()
+            .filter(|&format| format == ImageFormat::Tga),
+    }
+}
+
+pub fn is_supported_format(format: ImageFormat) -> bool {
+    format
+        .extensions_str()
+        .iter()
+        .any(|ext| supported_extensions().contains(ext))
+}
+
+pub fn supported_extensions() -> Vec<&'static str> {
+    FILTERS
+        .iter()
+        .flat_map(|(_, extensions)| extensions.iter().copied())
+        .collect()
+}
+
+/// Whether `path` looks like a supported image, going by its first bytes,
+/// or like a video or a comic archive, going by its extension.
+pub fn is_supported(path: &Path) -> bool {
+    detect_format(path).is_some() || video::is_video(path) || archive::is_archive(path)
+}
+
+/// Decodes the image at `path`, or its first frame, and crops it to `crop`.
+pub fn decode(path: &Path, crop: Option<Region>) -> Result<DynamicImage, String> {
+    // The extension may be wrong, so the format is guessed from the content.
+    let image = image::io::Reader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| e.to_string())?
+        .decode()
+        .map_err(|e| e.to_string())?;
+
+    Ok(match crop {
+        Some(region) => image.crop_imm(region.x, region.y, region.width, region.height),
+        None => image,
+    })
+}
+
+/// Decodes `input` and writes it to `converted` as a PNG.
+pub fn convert(input: &Path, converted: &Path) -> Result<(), String> {
+    narrow_for_png(decode(input, None)?)
+        .save_with_format(converted, image::ImageFormat::Png)
+        .map_err(|e| e.to_string())
+}
+
+/// Whether any pixel of `path` is not fully opaque. Images often carry an
+/// alpha channel they do not use, so this takes decoding the whole image.
+pub fn has_transparency(path: &Path) -> Result<bool, String> {
+    Ok(match decode(path, None)? {
+        DynamicImage::ImageLumaA8(image) => image.pixels().any(|p| p[1] < u8::MAX),
+        DynamicImage::ImageLumaA16(image) => image.pixels().any(|p| p[1] < u16::MAX),
+        DynamicImage::ImageRgba8(image) => image.pixels().any(|p| p[3] < u8::MAX),
+        DynamicImage::ImageRgba16(image) => image.pixels().any(|p| p[3] < u16::MAX),
+        DynamicImage::ImageRgba32F(image) => image.pixels().any(|p| p[3] < 1.0),
+        _ => false,
+    })
+}
+
+/// PNG has no floating point samples, so e.g. HDR TIFFs are narrowed.
+pub fn narrow_for_png(image: DynamicImage) -> DynamicImage {
+    match image {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+            DynamicImage::ImageRgba16(image.to_rgba16())
+        }
+        image => image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::PathBuf;
+
+    use image::{Rgb, RgbImage};
+
+    /// Writes a PNG with enough detail that it does not compress to nothing,
+    /// under each of `names`, in a folder of the test's own.
+    fn write_png(test: &str, names: &[&str]) -> (PathBuf, Vec<PathBuf>) {
+        let dir =
+            std::env::temp_dir().join(format!("upscaler-input-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let image = RgbImage::from_fn(64, 64, |x, y| {
+            Rgb([(x * 7 + y * 13) as u8, (x * y) as u8, (x ^ y) as u8])
+        });
+        let paths = names.iter().map(|name| dir.join(name)).collect::<Vec<_>>();
+
+        for path in &paths {
+            image.save_with_format(path, ImageFormat::Png).unwrap();
+        }
+
+        (dir, paths)
+    }
+
+    #[test]
+    fn detects_formats_by_content() {
+        let (dir, paths) = write_png("detect", &["right.png", "wrong.jpg", "none"]);
+        let probed = paths
+            .iter()
+            .map(|path| InputInfo::probe(path))
+            .collect::<Vec<_>>();
+        let _ = fs::remove_dir_all(&dir);
+
+        for (probed, mislabelled) in probed.into_iter().zip([false, true, true]) {
+            let info = probed.unwrap();
+
+            assert_eq!(info.format, InputFormat::Image(ImageFormat::Png));
+            assert_eq!(info.dimensions, (64, 64));
+            assert_eq!(info.extension_mismatch, mislabelled);
+            // The CLI goes by the extension, so it gets a converted copy.
+            assert_eq!(info.needs_conversion(), mislabelled);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_and_unknown_files() {
+        let (dir, paths) = write_png("reject", &["truncated.png"]);
+        let truncated = &paths[0];
+        let text = dir.join("text.png");
+
+        let png = fs::read(truncated).unwrap();
+        fs::write(truncated, &png[..png.len() / 2]).unwrap();
+        fs::write(&text, "not an image").unwrap();
+
+        let reader = BufReader::new(File::open(truncated).unwrap());
+        let header = header(codecs::png::PngDecoder::new(reader));
+        let probed = [InputInfo::probe(truncated), InputInfo::probe(&text)];
+        let supported = is_supported(&text);
+        let _ = fs::remove_dir_all(&dir);
+
+        // The header alone is intact, so only decoding finds out.
+        assert!(header.is_ok());
+        assert!(probed.iter().all(Result::is_err));
+        assert!(!supported);
+    }
+}
@@ -0,0 +1,270 @@
+//! Keeping to a single instance, so that launching the application again,
+//! from a file manager's "Open with" say, hands the files over to the one
+//! already running instead of starting another that competes for the GPU.
+//!
+//! The first instance listens on a local socket of the user's own, a named
+//! pipe on Windows, and later ones send it their command line there, one
+//! argument per line between a greeting and an empty line.
+
+use std::ffi::OsString;
+use std::io::{self, BufRead, BufReader, Write};
+#[cfg(not(windows))]
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use iced::futures::SinkExt;
+use iced::Subscription;
+use interprocess::local_socket::prelude::*;
+use interprocess::local_socket::{ListenerNonblockingMode, ListenerOptions, Name};
+
+use crate::args::{self, Args};
+use crate::Message;
+
+/// Sent first both ways, so that whatever else may be on the socket is not
+/// mistaken for an instance.
+const GREETING: &str = "realesrgan-ncnn-vulkan-gui 2";
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the first instance looks for later launches.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// The most a launch may send, which is plenty of paths.
+const MESSAGE_LIMIT: u64 = 1024 * 1024;
+
+pub enum Instance {
+    /// This is the first instance, and listens for the later ones.
+    First(LocalSocketListener),
+    /// The command line was handed over to the first instance.
+    Forwarded,
+    /// The socket is unusable, or held by something else, so this runs on
+    /// its own.
+    Alone,
+}
+
+/// Named pipes are shared by all users, so their name includes the user's.
+#[cfg(windows)]
+fn endpoint() -> io::Result<Name<'static>> {
+    use interprocess::local_socket::GenericNamespaced;
+
+    let user = std::env::var("USERNAME").unwrap_or_default();
+    format!("realesrgan-ncnn-vulkan-gui-{}", user).to_ns_name::<GenericNamespaced>()
+}
+
+/// Unix sockets are files, kept in the user's runtime folder, or else in the
+/// shared temporary folder under a name that includes the user's id.
+#[cfg(not(windows))]
+fn endpoint() -> io::Result<Name<'static>> {
+    use interprocess::local_socket::GenericFilePath;
+
+    let path = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("realesrgan-ncnn-vulkan-gui.sock"),
+        None => {
+            // SAFETY: getuid() has no preconditions and cannot fail.
+            let uid = unsafe { libc::getuid() };
+            std::env::temp_dir().join(format!("realesrgan-ncnn-vulkan-gui-{}.sock", uid))
+        }
+    };
+
+    path.to_fs_name::<GenericFilePath>()
+}
+
+/// Hands `args` over to the first instance, or else becomes it.
+pub fn claim(args: &Args) -> Instance {
+    let Ok(name) = endpoint() else {
+        return Instance::Alone;
+    };
+
+    if let Ok(stream) = LocalSocketStream::connect(name.borrow()) {
+        return match forward(stream, args) {
+            Ok(()) => Instance::Forwarded,
+            Err(_) => Instance::Alone,
+        };
+    }
+
+    // Nothing answered, so a socket file left behind by an instance that
+    // did not exit cleanly can be replaced.
+    let listener = ListenerOptions::new()
+        .name(name)
+        .try_overwrite(true)
+        .nonblocking(ListenerNonblockingMode::Accept)
+        .create_sync();
+
+    match listener {
+        Ok(listener) => Instance::First(listener),
+        Err(_) => Instance::Alone,
+    }
+}
+
+fn forward(stream: LocalSocketStream, args: &Args) -> io::Result<()> {
+    stream.set_recv_timeout(Some(TIMEOUT))?;
+    stream.set_send_timeout(Some(TIMEOUT))?;
+
+    let mut message = format!("{}\n", GREETING);
+
+    for arg in args.to_absolute() {
+        message.push_str(&arg.to_string_lossy());
+        message.push('\n');
+    }
+
+    message.push('\n');
+
+    let mut stream = BufReader::new(stream);
+    stream.get_mut().write_all(message.as_bytes())?;
+
+    let mut reply = String::new();
+    stream.read_line(&mut reply)?;
+
+    if reply.trim_end() == GREETING {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an instance",
+        ))
+    }
+}
+
+/// Reports the command line of each later launch, which may have no files.
+pub fn listen(listener: Arc<LocalSocketListener>) -> Subscription<Message> {
+    struct Listen;
+
+    iced::subscription::channel(std::any::TypeId::of::<Listen>(), 16, move |mut output| {
+        let listener = listener.clone();
+
+        async move {
+            loop {
+                // Nothing is waiting, or else the launch went away again.
+                let Ok(stream) = listener.accept() else {
+                    async_std::task::sleep(POLL_INTERVAL).await;
+                    continue;
+                };
+
+                let received = async_std::task::spawn_blocking(move || receive(stream)).await;
+
+                if let Ok(args) = received {
+                    let _ = output.send(Message::LaunchForwarded(args)).await;
+                }
+            }
+        }
+    })
+}
+
+fn receive(stream: LocalSocketStream) -> io::Result<Args> {
+    use std::io::Read;
+
+    stream.set_recv_timeout(Some(TIMEOUT))?;
+    stream.set_send_timeout(Some(TIMEOUT))?;
+
+    let mut stream = BufReader::new(stream);
+    let mut lines = (&mut stream).take(MESSAGE_LIMIT).lines();
+
+    if lines.next().transpose()?.as_deref() != Some(GREETING) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an instance",
+        ));
+    }
+
+    let mut received = Vec::new();
+
+    for line in lines {
+        let line = line?;
+
+        if line.is_empty() {
+            break;
+        }
+
+        received.push(OsString::from(line));
+    }
+
+    let args = args::parse(received).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    stream
+        .get_mut()
+        .write_all(format!("{}\n", GREETING).as_bytes())?;
+
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use crate::scale::ScaleTarget;
+
+    /// A socket of each test's own, apart from the one of a running instance.
+    fn test_endpoint(test: &str) -> Name<'static> {
+        let name = format!("realesrgan-ncnn-vulkan-gui-{}-{}", test, std::process::id());
+
+        #[cfg(windows)]
+        let name = name.to_ns_name::<interprocess::local_socket::GenericNamespaced>();
+        #[cfg(not(windows))]
+        let name = std::env::temp_dir()
+            .join(format!("{}.sock", name))
+            .to_fs_name::<interprocess::local_socket::GenericFilePath>();
+
+        name.unwrap()
+    }
+
+    /// Sends `args` from a launch on another thread, and receives them on
+    /// this one as the first instance.
+    fn hand_over(
+        test: &str,
+        args: Args,
+        greeting: &'static str,
+    ) -> (io::Result<()>, io::Result<Args>) {
+        let name = test_endpoint(test);
+        let listener = ListenerOptions::new()
+            .name(name.borrow())
+            .try_overwrite(true)
+            .create_sync()
+            .unwrap();
+
+        let launch = std::thread::spawn(move || {
+            let stream = LocalSocketStream::connect(name)?;
+            match greeting {
+                GREETING => forward(stream, &args),
+                _ => {
+                    let mut stream = stream;
+                    stream.write_all(format!("{}\n\n", greeting).as_bytes())
+                }
+            }
+        });
+
+        let received = receive(listener.accept().unwrap());
+
+        (launch.join().unwrap(), received)
+    }
+
+    #[test]
+    fn hands_the_command_line_over() {
+        let args = Args {
+            inputs: vec![PathBuf::from("a b.png"), PathBuf::from("dir/é.jpg")],
+            scale: Some(ScaleTarget::Width(1920)),
+            model: Some(String::from("realesrgan-x4plus")),
+            output_dir: Some(PathBuf::from("out")),
+            start: true,
+            ..Default::default()
+        };
+
+        let (forwarded, received) = hand_over("forward", args.clone(), GREETING);
+        forwarded.unwrap();
+        let received = received.unwrap();
+
+        let expected = args::parse(args.to_absolute()).unwrap();
+        assert_eq!(received.inputs, expected.inputs);
+        assert_eq!(received.inputs[0], std::path::absolute("a b.png").unwrap());
+        assert_eq!(received.scale, args.scale);
+        assert_eq!(received.model, args.model);
+        assert_eq!(received.output_dir, expected.output_dir);
+        assert!(!received.start);
+    }
+
+    #[test]
+    fn ignores_what_is_not_an_instance() {
+        let (_, received) = hand_over("stranger", Args::default(), "GET / HTTP/1.1");
+
+        assert!(received.is_err());
+    }
+}
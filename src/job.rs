@@ -0,0 +1,226 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::checker::{CheckerTask, Split};
+use crate::crop::{Crop, Region};
+use crate::encode::Encoding;
+use crate::input::{InputFormat, InputInfo};
+use crate::metadata::MetadataOptions;
+use crate::postprocess::{Fit, PostProcess};
+use crate::quality::Measurement;
+use crate::scale::{ScalePlan, ScaleTarget};
+use crate::video::{Video, VideoSettings};
+use crate::Format;
+
+pub type JobId = u64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Finished,
+    Failed(String),
+    TimedOut(String),
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_done(&self) -> bool {
+        !matches!(self, JobStatus::Pending | JobStatus::Running)
+    }
+}
+
+impl fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JobStatus::Pending => "Pending",
+            JobStatus::Running => "Running",
+            JobStatus::Finished => "Finished",
+            JobStatus::Failed(_) => "Failed",
+            JobStatus::TimedOut(_) => "Timed out",
+            JobStatus::Cancelled => "Cancelled",
+        })
+    }
+}
+
+/// The realesrgan options a job is run with, as they were when it was queued.
+#[derive(Clone, Debug)]
+pub struct JobSettings {
+    /// The scale realesrgan itself is run at, for every pass.
+    pub model_scale: u32,
+    /// The size asked for, which the plan of each job works out.
+    pub scale: ScaleTarget,
+    pub gpu_id: String,
+    pub model_path: String,
+    pub model_name: String,
+    pub tta_mode: bool,
+    pub timeout: Option<Duration>,
+    pub stall_timeout: Option<Duration>,
+    pub fit: Option<Fit>,
+    /// The same for every input of the batch, and worked out into a region
+    /// for each of them.
+    pub crop: Option<Crop>,
+    pub encoding: Encoding,
+    /// The file name format the output was named with.
+    pub filename_format: String,
+    pub metadata: MetadataOptions,
+    pub video: VideoSettings,
+    /// Whether the output is compared with the input once it is written.
+    pub measure_quality: bool,
+}
+
+/// Settings an input is queued with in place of those in the window, when it
+/// is handed over from outside of it.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    /// A factor or a length, as parsed by `scale::parse_target`.
+    pub scale: Option<String>,
+    pub model: Option<String>,
+    pub tta: Option<bool>,
+    pub format: Option<Format>,
+    /// A file name format, as typed on the Output page.
+    pub output_name: Option<String>,
+    pub output_dir: Option<PathBuf>,
+}
+
+/// A single input file to be upscaled by a realesrgan instance.
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub id: JobId,
+    pub input_path: OsString,
+    pub output_path: OsString,
+    pub settings: JobSettings,
+    pub input: InputInfo,
+    /// The part of the input that is upscaled, if not all of it.
+    pub crop: Option<Region>,
+    pub plan: ScalePlan,
+    pub pid: Option<u32>,
+    pub status: JobStatus,
+    /// How many realesrgan passes have been started.
+    pub step: u32,
+    /// The last percentage reported by the CLI for the current pass, or the
+    /// share of the frames it has written.
+    pub progress: f32,
+    pub started_at: Option<Instant>,
+    pub finished_at: Option<Instant>,
+    pub quality: Option<Measurement>,
+}
+
+impl Job {
+    pub fn new(
+        id: JobId,
+        input_path: OsString,
+        output_path: OsString,
+        settings: JobSettings,
+        input: InputInfo,
+        crop: Option<Region>,
+        plan: ScalePlan,
+    ) -> Self {
+        Self {
+            id,
+            input_path,
+            output_path,
+            settings,
+            input,
+            crop,
+            plan,
+            pid: None,
+            status: JobStatus::Pending,
+            step: 0,
+            progress: 0.0,
+            started_at: None,
+            finished_at: None,
+            quality: None,
+        }
+    }
+
+    pub fn task(&self) -> CheckerTask {
+        CheckerTask::NewChild {
+            job_id: self.id,
+            input_path: self.input_path.clone(),
+            convert_input: self.input.needs_conversion(),
+            crop: self.crop.map(Box::new),
+            split: self.split().map(Box::new),
+            output_path: self.output_path.clone(),
+            upscale_ratio: self.settings.model_scale,
+            passes: self.plan.passes,
+            gpu_id: self.settings.gpu_id.clone(),
+            model_path: self.settings.model_path.clone(),
+            model_name: self.settings.model_name.clone(),
+            tta_mode: self.settings.tta_mode,
+            timeout: self.settings.timeout,
+            stall_timeout: self.settings.stall_timeout,
+            post_process: Box::new(self.post_process()),
+        }
+    }
+
+    pub fn post_process(&self) -> PostProcess {
+        PostProcess {
+            output_size: self.plan.output_size,
+            fit: self.settings.fit,
+            encoding: self.settings.encoding,
+            metadata: self.settings.metadata,
+        }
+    }
+
+    /// How the input is split into frames, if it is not upscaled in one go.
+    fn split(&self) -> Option<Split> {
+        match &self.input.format {
+            InputFormat::Image(_) if self.input.is_animated() => Some(Split::Animation),
+            InputFormat::Image(_) => None,
+            InputFormat::Video(info) => Some(Split::Video(Video {
+                info: info.clone(),
+                settings: self.settings.video.clone(),
+            })),
+            InputFormat::Archive => Some(Split::Archive {
+                factor: self.plan.factor,
+            }),
+        }
+    }
+
+    /// How many times realesrgan runs for this job, once per pass over all
+    /// of its frames.
+    pub fn total_steps(&self) -> u32 {
+        self.plan.passes.max(1)
+    }
+
+    /// The progress over all passes, in percent.
+    pub fn total_progress(&self) -> f32 {
+        if self.status == JobStatus::Finished {
+            return 100.0;
+        }
+
+        let done = self.step.saturating_sub(1) as f32;
+
+        (done * 100.0 + self.progress) / self.total_steps() as f32
+    }
+
+    /// How long the job has been running, or how long it took if it is done.
+    pub fn elapsed(&self) -> Option<Duration> {
+        let started_at = self.started_at?;
+        let until = self.finished_at.unwrap_or_else(Instant::now);
+
+        Some(until.duration_since(started_at))
+    }
+
+    /// Whether the quality of the output is yet to be measured.
+    pub fn needs_measuring(&self) -> bool {
+        self.settings.measure_quality
+            && self.status == JobStatus::Finished
+            && self.input.is_still()
+            && self.quality.is_none()
+    }
+
+    pub fn set_status(&mut self, status: JobStatus) {
+        match status {
+            JobStatus::Running => self.started_at = Some(Instant::now()),
+            JobStatus::Finished => self.finished_at = Some(Instant::now()),
+            JobStatus::Pending => (),
+            _ => self.finished_at = Some(Instant::now()),
+        }
+
+        self.status = status;
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+
+use chrono::{DateTime, Local};
+
+use crate::job::JobId;
+
+/// The log file written when logging to disk is enabled. Like the CLI, this
+/// lives in the working directory.
+const LOG_FILE: &str = "realesrgan-ncnn-vulkan-gui.log";
+
+/// The log file is rotated once it grows past this size...
+const LOG_FILE_MAX_SIZE: u64 = 1024 * 1024;
+
+/// ...keeping this many older files around as `.log.1`, `.log.2` and so on.
+const LOG_FILE_BACKUPS: usize = 3;
+
+pub const DEFAULT_CAPACITY: usize = 255;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Raw output of the CLI.
+    Debug,
+    #[default]
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 4] = [
+        Severity::Debug,
+        Severity::Info,
+        Severity::Warning,
+        Severity::Error,
+    ];
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Debug => "DEBUG",
+            Severity::Info => "INFO",
+            Severity::Warning => "WARNING",
+            Severity::Error => "ERROR",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub time: DateTime<Local>,
+    pub severity: Severity,
+    pub job: Option<JobId>,
+    pub input: Option<String>,
+    pub message: String,
+}
+
+impl LogEntry {
+    pub fn new(severity: Severity, message: String) -> Self {
+        Self {
+            time: Local::now(),
+            severity,
+            job: None,
+            input: None,
+            message,
+        }
+    }
+
+    pub fn with_job(mut self, job: JobId, input: String) -> Self {
+        self.job = Some(job);
+        self.input = Some(input);
+        self
+    }
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} [{}]",
+            self.time.format("%Y-%m-%d %H:%M:%S"),
+            self.severity
+        )?;
+
+        match (self.job, &self.input) {
+            (Some(job), Some(input)) => write!(f, " job #{} ({})", job, input)?,
+            (Some(job), None) => write!(f, " job #{}", job)?,
+            _ => (),
+        }
+
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Which entries are shown on the Log page.
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    pub min_severity: Severity,
+    pub job: Option<JobId>,
+    pub search: String,
+}
+
+impl LogFilter {
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        if entry.severity < self.min_severity {
+            return false;
+        }
+
+        if self.job.is_some() && entry.job != self.job {
+            return false;
+        }
+
+        if self.search.is_empty() {
+            return true;
+        }
+
+        let search = self.search.to_lowercase();
+
+        entry.message.to_lowercase().contains(&search)
+            || entry
+                .input
+                .as_ref()
+                .is_some_and(|input| input.to_lowercase().contains(&search))
+    }
+}
+
+/// The in-memory log shown on the Log page, optionally mirrored into a
+/// rotating file on disk.
+#[derive(Debug)]
+pub struct Log {
+    entries: VecDeque<LogEntry>,
+    capacity: usize,
+    file: Option<File>,
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            capacity: DEFAULT_CAPACITY,
+            file: None,
+        }
+    }
+}
+
+impl Log {
+    pub fn push(&mut self, entry: LogEntry) {
+        if self.file.is_some() {
+            if let Err(e) = self.write_to_file(&entry) {
+                // Stop trying rather than failing on every single entry.
+                self.file = None;
+                self.push(LogEntry::new(
+                    Severity::Error,
+                    format!("Unable to write to {}: {}", LOG_FILE, e),
+                ));
+            }
+        }
+
+        self.entries.push_back(entry);
+
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &LogEntry> {
+        self.entries.iter()
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn is_writing_to_file(&self) -> bool {
+        self.file.is_some()
+    }
+
+    pub fn set_write_to_file(&mut self, enabled: bool) -> Result<(), String> {
+        self.file = if enabled {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(LOG_FILE)
+                .map_err(|e| e.to_string())?;
+            Some(file)
+        } else {
+            None
+        };
+
+        Ok(())
+    }
+
+    /// Renders the entries accepted by `filter`, one per line.
+    pub fn export(&self, filter: &LogFilter) -> String {
+        self.entries
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .map(|entry| format!("{}\n", entry))
+            .collect()
+    }
+
+    fn write_to_file(&mut self, entry: &LogEntry) -> io::Result<()> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+
+        writeln!(file, "{}", entry)?;
+
+        if file.metadata()?.len() >= LOG_FILE_MAX_SIZE {
+            self.file = None;
+
+            for i in (1..LOG_FILE_BACKUPS).rev() {
+                let from = format!("{}.{}", LOG_FILE, i);
+                let to = format!("{}.{}", LOG_FILE, i + 1);
+                let _ = fs::rename(from, to);
+            }
+
+            fs::rename(LOG_FILE, format!("{}.1", LOG_FILE))?;
+
+            self.file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(LOG_FILE)?,
+            );
+        }
+
+        Ok(())
+    }
+}
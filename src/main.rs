@@ -1,21 +1,70 @@
 #![cfg_attr(target_os = "windows", windows_subsystem = "windows")]
 
+mod animation;
+mod api;
+mod archive;
+mod args;
 mod checker;
-
-use std::collections::VecDeque;
+mod comparison;
+mod crop;
+mod encode;
+mod input;
+mod instance;
+mod job;
+mod log;
+mod manifest;
+mod metadata;
+mod partial;
+mod postprocess;
+mod preview;
+mod quality;
+mod scale;
+mod selection;
+mod session;
+mod thumbnail;
+mod video;
+mod watch;
+
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsString;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 use std::{fs, io};
 
-use checker::{CheckerTask, CheckerResult, ChildrenStatusChecker};
+use api::{Call, Request, Response};
+use args::Args;
+use checker::{CheckerResult, CheckerTask, ChildrenStatusChecker};
+use comparison::{Comparison, TtaVariants, Variant};
+use crop::{Crop, CropMode};
+use encode::{Background, ChromaSubsampling, Encoding, PngCompression, Transparency};
 use iced::futures::channel::mpsc;
-use iced::widget::{button, checkbox, column, radio, row, scrollable, text, text_input, Space, vertical_space};
-use iced::window::Settings as WindowSettings;
+use iced::widget::{
+    button, checkbox, column, container, image, mouse_area, pick_list, radio, row, scrollable,
+    text, text_input, tooltip, vertical_space, Space,
+};
+use iced::window::{self, Settings as WindowSettings};
 use iced::{
-    executor, theme, Alignment, Application, Color, Command, Element, Length, Settings,
-    Subscription, Theme,
+    executor, theme, Alignment, Application, Color, Command, Element, Event, Length, Rectangle,
+    Settings, Subscription, Theme,
 };
+use input::{InputFormat, InputInfo};
+use instance::Instance;
+use job::{Job, JobId, JobSettings, JobStatus, Overrides};
+use log::{Log, LogEntry, LogFilter, Severity};
+use manifest::{Item, Manifest};
+use metadata::MetadataOptions;
+use postprocess::FitMode;
+use preview::{Images, Interaction, Preview, Viewer};
+use quality::{Measurement, Quality};
+use scale::{ScaleMode, ScalePlan, ScaleTarget};
+use selection::Selection;
+use session::SavedQueue;
+use thumbnail::{Thumbnail, ThumbnailCache};
+use video::VideoSettings;
+
+/// The model the CLI uses when none is given.
+const DEFAULT_MODEL: &str = "realesrgan-x4plus-anime";
 
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub enum Format {
@@ -25,23 +74,76 @@ pub enum Format {
     Webp,
 }
 
+impl Format {
+    /// Parses a format given by its extension, from outside the window.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "png" => Ok(Format::Png),
+            "jpg" | "jpeg" => Ok(Format::Jpg),
+            "webp" => Ok(Format::Webp),
+            _ => Err(format!("\"{}\" is not a supported output format.", value)),
+        }
+    }
+}
+
 pub fn main() -> iced::Result {
+    let args = match args::parse(std::env::args_os().skip(1)) {
+        Ok(args) if !args.help => args,
+        parsed => {
+            // There may well be no console to print to.
+            rfd::MessageDialog::new()
+                .set_title("realesrgan-ncnn-vulkan-gui")
+                .set_level(match parsed {
+                    Ok(_) => rfd::MessageLevel::Info,
+                    Err(_) => rfd::MessageLevel::Error,
+                })
+                .set_description(&match parsed {
+                    Ok(_) => args::USAGE.to_owned(),
+                    Err(e) => format!("{}\n\n{}", e, args::USAGE),
+                })
+                .show();
+
+            return Ok(());
+        }
+    };
+
+    let instance = match instance::claim(&args) {
+        Instance::First(listener) => Some(listener),
+        Instance::Forwarded => return Ok(()),
+        Instance::Alone => None,
+    };
+
     RealEsrgan::run(Settings {
+        flags: Flags { args, instance },
         id: Some("dev.hch12907.realesrgan-ncnn-vulkan-gui".into()),
         window: WindowSettings {
             size: (800, 500),
             ..Default::default()
         },
+        // Closing is handled in Message::CloseRequested, as running jobs
+        // need to be taken care of first.
+        exit_on_close_request: false,
         ..Default::default()
     })
 }
 
+/// What the application is launched with.
+#[derive(Default)]
+struct Flags {
+    args: Args,
+    /// Where later launches hand their files over, if this is the first
+    /// instance.
+    instance: Option<interprocess::local_socket::Listener>,
+}
+
 #[derive(Default)]
 struct RealEsrgan {
     start_button_text: String,
     input: String,
     output: String,
     current_page: Page,
+    scale_mode: ScaleMode,
+    scale_value: String,
     upscale_ratio: UpscaleRatio,
     tta_mode: bool,
     advanced_options: bool,
@@ -49,11 +151,67 @@ struct RealEsrgan {
     model_name: String,
     model_path: String,
     format: Format,
+    jpeg_quality: String,
+    jpeg_subsampling: ChromaSubsampling,
+    jpeg_background: String,
+    transparency: Transparency,
+    webp_quality: String,
+    webp_lossless: bool,
+    png_compression: PngCompression,
+    metadata: MetadataOptions,
+    measure_quality: bool,
     filename_format: String,
+    fit_mode: FitMode,
+    fit_width: String,
+    fit_height: String,
+    fit_color: String,
+    crop_mode: CropMode,
+    crop_x: String,
+    crop_y: String,
+    crop_width: String,
+    crop_height: String,
+    compare_models: String,
+    compare_tta: TtaVariants,
+    ffmpeg_path: String,
+    video_codec: String,
+    video_crf: String,
+    job_timeout: String,
+    stall_timeout: String,
+    max_jobs: String,
 
     checker: Option<mpsc::Sender<CheckerTask>>,
-    log: VecDeque<String>,
+    log: Log,
+    log_filter: LogFilter,
+    log_capacity: String,
     processing: bool,
+    jobs: Vec<Job>,
+    next_job_id: JobId,
+    shutdown: Shutdown,
+    thumbnails: ThumbnailCache,
+    preview: Option<Preview>,
+    /// Shown on the Preview page while there is no comparison to show.
+    preview_status: String,
+    /// The input the crop region is being drawn on, on the Inputs page.
+    crop_input: Option<OsString>,
+    /// A larger thumbnail of it, once made.
+    crop_image: Option<Result<Thumbnail, String>>,
+    /// Model comparisons whose contact sheet is yet to be made.
+    comparisons: Vec<Comparison>,
+    /// How many contact sheets are being made in the background.
+    sheets_in_progress: usize,
+    /// Whether the inputs the Start button was clicked for are being probed.
+    starting: bool,
+    /// The settings the inputs restored from the last session were queued
+    /// with, used when they are started again.
+    resumed: HashMap<OsString, Overrides>,
+    /// The input folder new files are queued from as they appear.
+    watch_dir: Option<PathBuf>,
+    watch_recursive: bool,
+    api_port: String,
+    api_token: String,
+    /// The port and token the HTTP API is served with, while it is.
+    api: Option<(u16, String)>,
+    instance: Option<Arc<interprocess::local_socket::Listener>>,
 
     state: RealEsrganState,
 }
@@ -61,6 +219,8 @@ struct RealEsrgan {
 #[derive(Default)]
 struct RealEsrganState {
     selected_files: Vec<OsString>,
+    /// Selected files unticked on the Inputs page, which are not started.
+    deselected: HashSet<OsString>,
     output_dir: OsString,
 }
 
@@ -70,71 +230,204 @@ pub enum PathType {
     Output = 1,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum Page {
     #[default]
     Processing,
+    Inputs,
     Output,
+    Queue,
     Log,
+    Preview,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum JobAction {
+    MoveUp,
+    MoveDown,
+    MoveToTop,
+    Remove,
+    ShowLog,
+    /// Opens the before/after comparison of a finished job.
+    Compare,
+}
+
+/// Who asked for inputs to be queued, and is told how it went once they
+/// have been probed.
+#[derive(Debug, Clone)]
+enum Requester {
+    /// The Start or Compare button.
+    Start,
+    Watch(PathBuf),
+    Api(Call),
+    Forwarded,
+    Import,
+}
+
+/// Inputs being probed in the background before they are queued.
+#[derive(Debug, Clone)]
+pub struct QueueRequest {
+    files: Vec<(OsString, Overrides)>,
+    variants: Option<Vec<Variant>>,
+    unattended: bool,
+    requester: Requester,
+}
+
+/// What to do once the running jobs end, after the user asked to close the
+/// window.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+enum Shutdown {
+    #[default]
+    None,
+    WaitForJobs,
+    /// The jobs that were still to finish, and are kept to be resumed.
+    CancelJobs(Vec<JobId>),
 }
 
+/// The scale realesrgan itself runs at. Other ratios are reached by running
+/// it several times and resampling the result.
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum UpscaleRatio {
-    One = 1,
     Two = 2,
     Three = 3,
     #[default]
     Four = 4,
 }
 
+/// An entry of the job filter on the Log page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobFilter {
+    All,
+    Job(JobId),
+}
+
+impl std::fmt::Display for JobFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobFilter::All => write!(f, "All jobs"),
+            JobFilter::Job(id) => write!(f, "Job #{}", id),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Message {
     AdvancedOptionsClicked(bool),
+    ApiCalled(Call),
+    ApiClicked(bool),
+    ApiFailed(String),
+    ApiPortChanged(String),
+    ApiTokenChanged(String),
     AskPath { path_type: PathType },
+    CropEditorClosed,
+    CropEditorLoaded(OsString, Result<Thumbnail, String>),
+    CropEditorOpened(OsString),
+    CropHeightChanged(String),
+    CropModeSelected(CropMode),
+    CropSelected(Rectangle),
+    CropWidthChanged(String),
+    CropXChanged(String),
+    CropYChanged(String),
+    FfmpegPathChanged(String),
+    FitColorChanged(String),
+    FitHeightChanged(String),
+    FitModeSelected(FitMode),
+    FitWidthChanged(String),
     GpuIdChanged(String),
+    InputToggled(OsString, bool),
+    InputsLoadClicked,
+    InputsProbed(QueueRequest, Vec<Result<(InputInfo, bool), String>>),
+    InputsSelectAllClicked(bool),
+    JobActionClicked(JobId, JobAction),
+    JpegBackgroundChanged(String),
+    JpegQualityChanged(String),
+    JpegSubsamplingSelected(ChromaSubsampling),
+    LaunchForwarded(Args),
+    LogCapacityChanged(String),
+    LogCopyClicked,
+    LogJobFilterSelected(JobFilter),
+    LogSaveClicked,
+    LogSearchChanged(String),
+    LogSeveritySelected(Severity),
+    LogToFileClicked(bool),
+    MeasureQualityClicked(bool),
+    MetadataExifClicked(bool),
+    MetadataIccClicked(bool),
+    MetadataXmpClicked(bool),
+    JobTimeoutChanged(String),
+    MaxJobsChanged(String),
     ModelPathChanged(String),
     ModelNameChanged(String),
     OutputFormatChanged(Format),
     OutputNameChanged(String),
     PathChanged { path_type: PathType, path: String },
+    PngCompressionSelected(PngCompression),
+    PreviewActualSizeClicked,
+    PreviewFitClicked,
+    PreviewInteracted(Interaction),
+    PreviewLoaded(JobId, Result<Arc<Images>, String>),
+    ScaleModeSelected(ScaleMode),
+    ScaleValueChanged(String),
+    QualityMeasured(JobId, Result<Quality, String>),
+    QueueClearDoneClicked,
+    QueueExportClicked,
+    QueueImportClicked,
+    QueueSaveReportClicked,
+    StallTimeoutChanged(String),
     StartClicked,
+    TransparencySelected(Transparency),
     CheckerReady(mpsc::Sender<CheckerTask>),
+    CompareClicked,
+    CompareModelsChanged(String),
+    CompareTtaSelected(TtaVariants),
+    ContactSheetWritten(Result<PathBuf, String>),
     ChildUpdate(CheckerResult),
+    CloseRequested,
     SwitchPage(Page),
     Tick,
+    ThumbnailLoaded(PathBuf, Result<Thumbnail, String>),
     TTAModeClicked(bool),
     UpscaleRatioSelected(UpscaleRatio),
+    VideoCodecChanged(String),
+    VideoCrfChanged(String),
+    WatchClicked(bool),
+    WatchFailed(String),
+    WatchFound(PathBuf),
+    WatchRecursiveClicked(bool),
+    WebpLosslessClicked(bool),
+    WebpQualityChanged(String),
 }
 
 impl RealEsrgan {
     fn add_input_paths(&mut self, path: String) -> Result<(), String> {
-        let path = PathBuf::from(path);
+        let files = Self::list_inputs(Path::new(&path))?;
+        self.state
+            .selected_files
+            .extend(files.into_iter().map(PathBuf::into_os_string));
+
+        Ok(())
+    }
 
+    /// The supported files in `path` if it is a folder, or else `path` itself.
+    fn list_inputs(path: &Path) -> Result<Vec<PathBuf>, String> {
         if path.is_dir() {
             let dir = fs::read_dir(path).map_err(|e| e.to_string())?;
             let files = dir.into_iter().filter_map(|entry_res| {
                 let path = entry_res.map(|entry| entry.path()).ok()?;
-                let extension = path.extension()?.to_string_lossy();
 
-                if path.is_file()
-                    && ["png", "jpg", "jpeg", "webp"]
-                        .iter()
-                        .any(|&ext| ext == extension.to_ascii_lowercase())
-                {
+                if path.is_file() && input::is_supported(&path) {
                     Some(path)
                 } else {
                     None
                 }
             });
 
-            for file in files {
-                self.state.selected_files.push(file.into())
-            }
+            Ok(files.collect())
         } else if path.is_file() {
-            self.state.selected_files.push(path.into())
-        };
-
-        Ok(())
+            Ok(vec![path.to_path_buf()])
+        } else {
+            Ok(Vec::new())
+        }
     }
 
     fn add_output_path(&mut self, path: String) -> Result<(), String> {
@@ -155,7 +448,7 @@ impl RealEsrgan {
     fn generate_output_filename(
         format: &str,
         input: PathBuf,
-        ratio: UpscaleRatio,
+        scale: &str,
         model: &str,
     ) -> Result<OsString, String> {
         enum FileFormat {
@@ -225,7 +518,7 @@ impl RealEsrgan {
             for fmt in format_parsed {
                 match fmt {
                     Name => output_filename.extend(filename.as_bytes()),
-                    Scale => output_filename.push(b'0' + ratio as u8),
+                    Scale => output_filename.extend(scale.as_bytes()),
                     Model => output_filename.extend(model.as_bytes()),
                     Other(s) => output_filename.extend(s.as_bytes()),
                 }
@@ -244,7 +537,7 @@ impl RealEsrgan {
             for fmt in format_parsed {
                 match fmt {
                     Name => output_filename.extend(filename.encode_wide()),
-                    Scale => output_filename.push(b'0' as u16 + ratio as u16),
+                    Scale => output_filename.extend(scale.encode_utf16()),
                     Model => output_filename.extend(model.encode_utf16()),
                     Other(s) => output_filename.extend(s.encode_utf16()),
                 }
@@ -254,6 +547,22 @@ impl RealEsrgan {
         }
     }
 
+    /// Parses a timeout given in seconds. An empty string or zero disables the
+    /// timeout.
+    fn parse_timeout(secs: &str, name: &str) -> Result<Option<Duration>, String> {
+        let secs = secs.trim();
+
+        if secs.is_empty() {
+            return Ok(None);
+        }
+
+        match secs.parse::<u64>() {
+            Ok(0) => Ok(None),
+            Ok(secs) => Ok(Some(Duration::from_secs(secs))),
+            Err(_) => Err(format!("{} must be a whole number of seconds.", name)),
+        }
+    }
+
     fn reset_start_button(&mut self) {
         self.start_button_text.clear();
         self.start_button_text.push_str("Click Here to Start");
@@ -265,7 +574,51 @@ impl RealEsrgan {
             .push_str(&format!("Click Here to Start ({})", err));
     }
 
-    fn start(&mut self) {
+    fn encoding(&self, format: Format) -> Result<Encoding, String> {
+        let quality = |value: &str, min| {
+            value
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|q| (min..=100).contains(q))
+                .ok_or_else(|| format!("\"{}\" is not a valid quality, use {} to 100.", value, min))
+        };
+
+        // Inputs that cannot switch format, such as the pages of an archive,
+        // are flattened onto white when switching is chosen.
+        let background = match self.transparency {
+            Transparency::Color => {
+                let [r, g, b, _] = postprocess::parse_color(&self.jpeg_background)?;
+                Background::Color([r, g, b])
+            }
+            Transparency::Checkerboard => Background::Checkerboard,
+            Transparency::SwitchToPng | Transparency::SwitchToWebp => Background::Color([255; 3]),
+        };
+
+        Ok(match format {
+            Format::Png => Encoding::Png {
+                compression: self.png_compression,
+            },
+            Format::Jpg => Encoding::Jpeg {
+                quality: quality(&self.jpeg_quality, 1)?,
+                subsampling: self.jpeg_subsampling,
+                background,
+            },
+            Format::Webp => Encoding::Webp {
+                quality: if self.webp_lossless {
+                    100.0
+                } else {
+                    quality(&self.webp_quality, 0)? as f32
+                },
+                lossless: self.webp_lossless,
+            },
+        })
+    }
+
+    /// Queues the selected inputs. With `variants`, queues only the input open
+    /// in the crop editor, or else the first selected one, once for each of
+    /// them, to compare them on a contact sheet.
+    fn start(&mut self, variants: Option<Vec<Variant>>) -> Command<Message> {
         let error_dialog = |msg| {
             rfd::MessageDialog::new()
                 .set_buttons(rfd::MessageButtons::Ok)
@@ -284,12 +637,19 @@ impl RealEsrgan {
                 .show()
         };
 
+        if self.starting {
+            return Command::none();
+        }
+
         self.reset_start_button();
 
         if self.state.selected_files.is_empty() {
             match self.add_input_paths(self.input.clone()) {
                 Ok(()) => (),
-                Err(msg) => return self.show_error_on_start_button(&msg),
+                Err(msg) => {
+                    self.show_error_on_start_button(&msg);
+                    return Command::none();
+                }
             }
         }
 
@@ -310,235 +670,2146 @@ impl RealEsrgan {
                         self.output = self.state.output_dir.to_string_lossy().to_string();
                     } else {
                         error_dialog("Unable to obtain an output directory.");
-                        return;
+                        return Command::none();
                     }
                 } else {
-                    return;
+                    return Command::none();
                 }
             }
             match self.add_output_path(self.output.clone()) {
                 Ok(()) => (),
-                Err(msg) => return self.show_error_on_start_button(&msg),
+                Err(msg) => {
+                    self.show_error_on_start_button(&msg);
+                    return Command::none();
+                }
             }
         } else if !PathBuf::from(&self.state.output_dir).exists() {
             error_dialog("Invalid output directory.");
-            return;
+            return Command::none();
         }
 
-        if self.upscale_ratio as u32 == 1 {
-            error_dialog("An upscale ratio greater than 1 is not specified.");
-            return;
+        let selected = self
+            .state
+            .selected_files
+            .iter()
+            .filter(|f| !self.state.deselected.contains(*f));
+
+        let selected = if variants.is_some() {
+            self.crop_input
+                .iter()
+                .chain(selected)
+                .take(1)
+                .cloned()
+                .collect::<Vec<_>>()
+        } else {
+            selected.cloned().collect::<Vec<_>>()
+        };
+
+        if selected.is_empty() {
+            self.show_error_on_start_button("No inputs are selected.");
+            return Command::none();
         }
 
-        let model_name = if self.model_name.is_empty() {
-            "realesrgan-x4plus-anime"
+        let selected = selected
+            .into_iter()
+            .map(|f| {
+                let overrides = self.resumed.get(&f).cloned().unwrap_or_default();
+                (f, overrides)
+            })
+            .collect();
+
+        self.starting = true;
+        self.queue_jobs(selected, variants, false, Requester::Start)
+    }
+
+    /// Probes `files` in the background, and then queues a job for each of
+    /// them, or one for each of `variants` to compare them, with the settings
+    /// as they are then but for the overrides given with each file. How it
+    /// went is told to `requester`.
+    ///
+    /// When `unattended`, nothing is asked: undecodable inputs are skipped,
+    /// as are inputs whose output already exists.
+    fn queue_jobs(
+        &mut self,
+        files: Vec<(OsString, Overrides)>,
+        variants: Option<Vec<Variant>>,
+        unattended: bool,
+        requester: Requester,
+    ) -> Command<Message> {
+        let video = self.refuse_while_closing().and_then(|()| {
+            VideoSettings::parse(&self.ffmpeg_path, &self.video_codec, &self.video_crf)
+        });
+
+        let ffmpeg_path = match video {
+            Ok(video) => video.ffmpeg_path,
+            Err(e) => {
+                self.report_queued(requester, Err(e));
+                return Command::none();
+            }
+        };
+
+        let inputs = files
+            .iter()
+            .map(|(f, overrides)| {
+                let flattened = overrides.format.unwrap_or(self.format) == Format::Jpg;
+                (PathBuf::from(f), flattened)
+            })
+            .collect::<Vec<_>>();
+
+        let request = QueueRequest {
+            files,
+            variants,
+            unattended,
+            requester,
+        };
+
+        Command::perform(
+            async_std::task::spawn_blocking(move || {
+                let probed = inputs
+                    .iter()
+                    .map(|(path, flattened)| Self::probe_input(path, *flattened, &ffmpeg_path))
+                    .collect();
+
+                (request, probed)
+            }),
+            |(request, probed)| Message::InputsProbed(request, probed),
+        )
+    }
+
+    /// Reads what `path` is, and whether it uses its alpha channel if it is
+    /// to be `flattened` onto a background.
+    fn probe_input(
+        path: &Path,
+        flattened: bool,
+        ffmpeg_path: &str,
+    ) -> Result<(InputInfo, bool), String> {
+        let info = if video::is_video(path) {
+            video::probe(path, ffmpeg_path)
+        } else if archive::is_archive(path) {
+            archive::probe(path)
+        } else {
+            InputInfo::probe(path)
+        }?;
+
+        // Only still images that may lose their alpha channel are decoded to
+        // find out if they use it.
+        let transparent = if flattened && info.has_alpha && info.is_still() {
+            input::has_transparency(path)?
         } else {
-            &self.model_name
+            false
         };
 
-        if (self.upscale_ratio as u32) < 4 && model_name.contains("realesrgan-x4plus") {
-            let keep_going = ask(concat!(
-                "The upscale ratio is possibly incompatible with the model.\n",
-                "The output may possibly be distorted.\n\n",
-                "Do you wish to continue?"
-            ));
+        Ok((info, transparent))
+    }
+
+    /// Queues the jobs of `request` once its inputs have been `probed`, and
+    /// returns them.
+    fn queue_probed(
+        &mut self,
+        request: QueueRequest,
+        probed: Vec<Result<(InputInfo, bool), String>>,
+    ) -> Result<Vec<JobId>, String> {
+        self.refuse_while_closing()?;
+
+        let QueueRequest {
+            files,
+            variants,
+            unattended,
+            ..
+        } = request;
+
+        /// What an input is queued with once its overrides are applied.
+        struct Target {
+            scale: ScaleTarget,
+            format: Format,
+            output_dir: PathBuf,
+            filename_format: String,
+            variants: Vec<Variant>,
+        }
+
+        let ask = |msg| {
+            rfd::MessageDialog::new()
+                .set_buttons(rfd::MessageButtons::YesNo)
+                .set_title("Output Path Selection")
+                .set_description(msg)
+                .set_level(rfd::MessageLevel::Info)
+                .show()
+        };
+
+        let video = VideoSettings::parse(&self.ffmpeg_path, &self.video_codec, &self.video_crf)?;
+
+        let comparing = variants.is_some();
+        let mut inputs = Vec::new();
+        let mut rejected = Vec::new();
+
+        for ((f, overrides), probed) in files.into_iter().zip(probed) {
+            match probed {
+                Ok((info, transparent)) => inputs.push((f, overrides, info, transparent)),
+                Err(e) => rejected.push(format!("{}: {}", Path::new(&f).to_string_lossy(), e)),
+            }
+        }
+
+        if !rejected.is_empty() {
+            for reason in rejected.iter() {
+                self.push_log(Severity::Error, format!("Rejected {}", reason));
+            }
+
+            let mut list = rejected
+                .iter()
+                .take(10)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            if rejected.len() > 10 {
+                list.push_str(&format!("\n...and {} more", rejected.len() - 10));
+            }
+
+            if inputs.is_empty() {
+                return Err(format!("None of the inputs can be decoded.\n\n{}", list));
+            }
+
+            let keep_going = unattended || rfd::MessageDialog::new()
+                .set_buttons(rfd::MessageButtons::YesNo)
+                .set_title("Invalid Inputs")
+                .set_description(&format!(
+                    "These inputs cannot be decoded and will be skipped:\n\n{}\n\nDo you wish to continue with the rest?",
+                    list
+                ))
+                .set_level(rfd::MessageLevel::Warning)
+                .show();
 
             if !keep_going {
-                return;
+                return Ok(Vec::new());
             }
         }
 
-        for f in self.state.selected_files.iter() {
-            let input = PathBuf::from(f);
-            let mut output = PathBuf::from(&self.state.output_dir);
+        let fit = self
+            .fit_mode
+            .parse(&self.fit_width, &self.fit_height, &self.fit_color)?;
 
-            let output_ext = match self.format {
-                Format::Png => "png",
-                Format::Jpg => "jpg",
-                Format::Webp => "webp",
+        let crop = self.crop_mode.parse(
+            &self.crop_x,
+            &self.crop_y,
+            &self.crop_width,
+            &self.crop_height,
+        )?;
+
+        if comparing && !inputs[0].2.is_still() {
+            return Err(String::from(
+                "Only still images can be used to compare models.",
+            ));
+        }
+
+        let mut targeted = Vec::new();
+
+        for (f, overrides, info, transparent) in inputs {
+            let scale = match &overrides.scale {
+                Some(value) => scale::parse_target(value)
+                    .map_err(|e| format!("{}\n\n{}", Path::new(&f).to_string_lossy(), e))?,
+                None => self.scale_mode.parse(&self.scale_value)?,
             };
 
-            let filename = match Self::generate_output_filename(
-                &self.filename_format,
-                input,
-                self.upscale_ratio,
-                &model_name,
-            ) {
-                Ok(f) => f,
-                Err(e) => {
-                    error_dialog(&e);
-                    return;
+            let output_dir = match overrides.output_dir {
+                Some(dir) if !dir.is_dir() => {
+                    return Err(format!("{} is not a folder.", dir.to_string_lossy()));
                 }
+                Some(dir) => dir,
+                None => PathBuf::from(&self.state.output_dir),
             };
 
-            output.push(&filename);
-            output.set_extension(output_ext);
+            let filename_format = overrides
+                .output_name
+                .unwrap_or_else(|| self.filename_format.clone());
 
-            let sent = self
-                .checker
-                .as_mut()
-                .unwrap()
-                .start_send(CheckerTask::NewChild {
-                    input_path: f.clone(),
-                    output_path: output.into_os_string(),
-                    upscale_ratio: self.upscale_ratio as u32,
-                    gpu_id: self.gpu_id.clone(),
-                    model_path: self.model_path.clone(),
-                    model_name: self.model_name.clone(),
-                    tta_mode: self.tta_mode,
-                });
-
-            if sent.is_err() {
-                let err = sent.unwrap_err().to_string();
-                let err = format!("Unabled to start a background task for RealESRGAN: {}", err);
-                error_dialog(&err);
-                break;
+            // Each variant of a comparison gets its own output, named after it.
+            let filename_format = if comparing && !filename_format.contains("{model}") {
+                format!("{}-{{model}}", filename_format)
             } else {
-                self.processing = true;
+                filename_format
+            };
+
+            let variants = variants.clone().unwrap_or_else(|| {
+                vec![Variant {
+                    model: overrides.model.unwrap_or_else(|| self.model_name.clone()),
+                    tta: overrides.tta.unwrap_or(self.tta_mode),
+                }]
+            });
+
+            let target = Target {
+                scale,
+                format: overrides.format.unwrap_or(self.format),
+                output_dir,
+                filename_format,
+                variants,
+            };
+
+            targeted.push((f, info, transparent, target));
+        }
+
+        let incompatible = targeted.iter().any(|(_, _, _, target)| {
+            target.variants.iter().any(|variant| {
+                variant
+                    .model_name(DEFAULT_MODEL)
+                    .contains("realesrgan-x4plus")
+            })
+        });
+
+        if (self.upscale_ratio as u32) < 4 && incompatible {
+            let keep_going = unattended
+                || ask(concat!(
+                    "The upscale ratio is possibly incompatible with the model.\n",
+                    "The output may possibly be distorted.\n\n",
+                    "Do you wish to continue?"
+                ));
+
+            if !keep_going {
+                return Ok(Vec::new());
             }
         }
-    }
 
-    fn apply_checker_updates(&mut self, result: CheckerResult) {
-        use CheckerResult::*;
+        let timeouts = Self::parse_timeout(&self.job_timeout, "Job timeout").and_then(|timeout| {
+            Self::parse_timeout(&self.stall_timeout, "Stall timeout")
+                .map(|stall_timeout| (timeout, stall_timeout))
+        });
 
-        let make_log = |buffer: &mut VecDeque<String>, log| {
-            buffer.push_back(log);
+        let (timeout, stall_timeout) = timeouts?;
 
-            if buffer.len() >= 255 {
-                buffer.pop_front();
-            }
-        };
+        let mut jobs = Vec::new();
+        let mut transparent_jobs = Vec::new();
+        let mut uncropped_jobs = Vec::new();
+        let mut skipped = Vec::new();
 
-        match result {
-            Ended => {
-                self.processing = false;
-            }
+        let runs = targeted.iter().flat_map(|(f, info, transparent, target)| {
+            target
+                .variants
+                .iter()
+                .map(move |variant| (f.clone(), info.clone(), *transparent, target, variant))
+        });
 
-            ChildLog(pid, raw_log) => {
-                let log = format!("pid #{}: {}", pid, raw_log);
-                make_log(&mut self.log, log);
-            }
+        for (f, info, transparent, target, variant) in runs {
+            let input = PathBuf::from(&f);
+            let input_name = input.to_string_lossy().to_string();
+            let mut output = target.output_dir.clone();
 
-            ChildExited(pid, exit) => {
-                if !exit.success() {
-                    self.show_error_on_start_button(&format!(
-                        "realesrgan returned {}",
-                        exit.code().unwrap_or(-1)
-                    ));
-                } else {
-                    make_log(&mut self.log, format!("pid #{}: complete!", pid));
-                }
+            let model_tag = if comparing {
+                variant.file_tag(DEFAULT_MODEL)
+            } else {
+                variant.model_name(DEFAULT_MODEL).to_owned()
+            };
+
+            let format = match self.transparency {
+                Transparency::SwitchToPng if transparent => Format::Png,
+                Transparency::SwitchToWebp if transparent => Format::Webp,
+                _ => target.format,
+            };
+
+            let settings = JobSettings {
+                model_scale: self.upscale_ratio as u32,
+                scale: target.scale,
+                gpu_id: self.gpu_id.clone(),
+                model_path: self.model_path.clone(),
+                model_name: variant.model.clone(),
+                tta_mode: variant.tta,
+                timeout,
+                stall_timeout,
+                fit,
+                crop,
+                encoding: self.encoding(format)?,
+                filename_format: target.filename_format.clone(),
+                metadata: self.metadata,
+                video: video.clone(),
+                measure_quality: self.measure_quality,
+            };
+
+            // Animations, videos and archives are put back together in the
+            // container they came in.
+            let output_ext = match format {
+                _ if info.video().is_some() || info.format == InputFormat::Archive => input
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_lowercase())
+                    .unwrap_or_default(),
+                _ if info.is_animated() => info.format_name().to_owned(),
+                Format::Png => String::from("png"),
+                Format::Jpg => String::from("jpg"),
+                Format::Webp => String::from("webp"),
+            };
+
+            // Only still images are cropped, the others are upscaled whole.
+            let uncropped = settings.crop.is_some() && !info.is_still();
+            let region = match settings.crop {
+                Some(crop) if info.is_still() => match crop.region(info.dimensions) {
+                    Ok(region) => Some(region),
+                    Err(e) => return Err(format!("{}\n\n{}", input.to_string_lossy(), e)),
+                },
+                _ => None,
+            };
+
+            let dimensions = region.map_or(info.dimensions, |region| region.dimensions());
+
+            let plan = ScalePlan::new(target.scale, self.upscale_ratio as u32, Some(dimensions))
+                .map_err(|e| format!("{}\n\n{}", input.to_string_lossy(), e))?;
+
+            let filename = Self::generate_output_filename(
+                &target.filename_format,
+                input,
+                &scale::format_factor(plan.factor),
+                &model_tag,
+            )?;
+
+            output.push(&filename);
+            output.set_extension(output_ext);
+
+            if unattended && output.exists() {
+                skipped.push(input_name);
+                continue;
             }
 
-            ChildErrored(pid, err) => {
-                rfd::MessageDialog::new()
-                    .set_title("Error")
-                    .set_level(rfd::MessageLevel::Error)
-                    .set_description(&format!(
-                        "Unexpected error occured while running RealESRGAN: {}",
-                        err
-                    ))
-                    .show();
+            let job_id = self.next_job_id + jobs.len() as JobId;
 
-                make_log(&mut self.log, format!("pid #{} ERROR: {}", pid, err));
+            if transparent {
+                transparent_jobs.push((job_id, format));
             }
 
-            SpawnError(err) => {
-                rfd::MessageDialog::new()
-                    .set_title("Error")
-                    .set_level(rfd::MessageLevel::Error)
-                    .set_description(&format!("Unable to spawn a realesrgan instance:\n{}", err))
-                    .show();
+            if uncropped {
+                uncropped_jobs.push(job_id);
             }
+
+            jobs.push(Job::new(
+                job_id,
+                f,
+                output.into_os_string(),
+                settings,
+                info,
+                region,
+                plan,
+            ));
         }
-    }
-}
 
-impl Application for RealEsrgan {
-    type Executor = executor::Default;
-    type Message = Message;
-    type Theme = Theme;
-    type Flags = ();
-
-    fn new(_flags: ()) -> (Self, Command<Message>) {
-        (
-            Self {
-                start_button_text: String::from("Click Here to Start"),
-                filename_format: String::from("{name}-{scale}x"),
-                ..Default::default()
-            },
-            Command::none(),
-        )
-    }
+        self.next_job_id += jobs.len() as JobId;
 
-    fn title(&self) -> String {
-        String::from("realesrgan-ncnn-vulkan-gui")
+        if comparing && !jobs.is_empty() {
+            let input = jobs[0].input_path.clone();
+            let target = &targeted[0].3;
+
+            self.comparisons.push(Comparison {
+                sheet_path: comparison::sheet_path(&target.output_dir, Path::new(&input)),
+                input,
+                crop: jobs[0].crop,
+                jobs: jobs
+                    .iter()
+                    .map(|job| job.id)
+                    .zip(target.variants.clone())
+                    .collect(),
+            });
+        }
+
+        let mismatched = jobs
+            .iter()
+            .filter(|job| job.input.extension_mismatch)
+            .map(|job| (job.id, job.input.format_name().to_uppercase()))
+            .collect::<Vec<_>>();
+
+        let queued = jobs.iter().map(|job| job.id).collect::<Vec<_>>();
+        self.jobs.extend(jobs);
+
+        for input in skipped {
+            self.push_log(
+                Severity::Info,
+                format!("Skipped {}, its output already exists", input),
+            );
+        }
+
+        for (job_id, format) in mismatched {
+            self.push_job_log(
+                job_id,
+                Severity::Warning,
+                format!(
+                    "the extension does not match the content, which is {}",
+                    format
+                ),
+            );
+        }
+
+        for (job_id, format) in transparent_jobs {
+            let action = match format {
+                Format::Png => String::from("saving it as PNG instead"),
+                Format::Webp => String::from("saving it as WebP instead"),
+                Format::Jpg if self.transparency == Transparency::Checkerboard => {
+                    String::from("flattening it onto a checkerboard")
+                }
+                Format::Jpg => format!("flattening it onto {}", self.jpeg_background.trim()),
+            };
+
+            self.push_job_log(
+                job_id,
+                Severity::Info,
+                format!("the input has transparency, {}", action),
+            );
+        }
+
+        for job_id in uncropped_jobs {
+            self.push_job_log(
+                job_id,
+                Severity::Warning,
+                String::from("only still images can be cropped, upscaling the whole input"),
+            );
+        }
+
+        self.dispatch_jobs();
+
+        Ok(queued)
     }
 
-    fn update(&mut self, message: Message) -> Command<Message> {
-        match message {
-            Message::AdvancedOptionsClicked(check) => self.advanced_options = check,
-            Message::AskPath {
-                path_type: PathType::Input,
-            } => {
-                let dialog = rfd::FileDialog::new()
-                    .add_filter("Supported images", &["png", "jpg", "jpeg", "webp"])
-                    .add_filter("PNG images", &["png"])
-                    .add_filter("JPEG images", &["jpg", "jpeg"])
-                    .add_filter("WebP images", &["webp"])
-                    .set_title("Input files")
-                    .pick_files();
+    /// Tells `requester` how queueing its inputs went.
+    fn report_queued(&mut self, requester: Requester, queued: Result<Vec<JobId>, String>) {
+        let error_dialog = |msg: &str| {
+            rfd::MessageDialog::new()
+                .set_title("Error")
+                .set_level(rfd::MessageLevel::Error)
+                .set_description(msg)
+                .show()
+        };
 
-                if let Some(files) = dialog {
-                    if files.len() > 0 {
-                        let path = files[0].to_string_lossy().to_string();
-                        self.input = path;
+        match requester {
+            Requester::Start => {
+                self.starting = false;
+
+                match queued {
+                    // Whatever was left over from the last session has either
+                    // been resumed just now or replaced by the user's new
+                    // selection.
+                    Ok(_) => {
+                        SavedQueue::discard();
+                        self.resumed.clear();
+                    }
+                    Err(e) => {
+                        error_dialog(&e);
                     }
-                    self.state.selected_files =
-                        files.into_iter().map(|p| p.into_os_string()).collect();
                 }
             }
-            Message::AskPath {
-                path_type: PathType::Output,
-            } => {
-                let dialog = rfd::FileDialog::new().pick_folder();
+            Requester::Watch(path) => {
+                if let Err(e) = queued {
+                    self.push_log(
+                        Severity::Error,
+                        format!("Unable to queue {}: {}", path.to_string_lossy(), e),
+                    );
+                }
+            }
+            Requester::Api(call) => {
+                let response = match queued {
+                    Ok(ids) if ids.is_empty() => {
+                        Response::error(409, "The output of this input already exists.")
+                    }
+                    Ok(ids) => {
+                        if let Request::Submit { path, .. } = &call.request {
+                            self.push_log(
+                                Severity::Info,
+                                format!("Queued {} from the HTTP API", path.to_string_lossy()),
+                            );
+                        }
+
+                        let jobs = self
+                            .jobs
+                            .iter()
+                            .filter(|job| ids.contains(&job.id))
+                            .map(|job| api::job_json(job, DEFAULT_MODEL))
+                            .collect::<Vec<_>>();
+
+                        Response::Json(201, serde_json::json!({ "jobs": jobs }))
+                    }
+                    Err(e) => Response::error(400, &e),
+                };
 
-                if let Some(dir) = dialog {
-                    self.state.output_dir = dir.into();
-                    self.output = self.state.output_dir.to_string_lossy().to_string();
+                call.reply(response);
+            }
+            Requester::Forwarded => {
+                if let Err(e) = queued {
+                    self.push_log(
+                        Severity::Error,
+                        format!("Unable to queue the files of another launch: {}", e),
+                    );
                 }
             }
-            Message::CheckerReady(sender) => self.checker = Some(sender),
-            Message::ChildUpdate(result) => self.apply_checker_updates(result),
-            Message::GpuIdChanged(id) => self.gpu_id = id,
-            Message::ModelNameChanged(name) => self.model_name = name,
-            Message::ModelPathChanged(path) => self.model_path = path,
-            Message::OutputFormatChanged(format) => self.format = format,
-            Message::OutputNameChanged(name) => self.filename_format = name,
-            Message::PathChanged { path_type, path } => match path_type {
-                PathType::Input => {
-                    self.state.selected_files.clear();
-                    self.input = path
+            Requester::Import => {
+                if let Err(e) = queued {
+                    error_dialog(&e);
                 }
-                PathType::Output => {
+            }
+        }
+    }
+
+    /// Starts watching the input folder, which has to be a folder other than
+    /// the output one, and one that does not hold it if subfolders are
+    /// watched too.
+    fn start_watching(&mut self) -> Result<(), String> {
+        self.refuse_while_closing()?;
+
+        let dir = PathBuf::from(&self.input);
+
+        if !dir.is_dir() {
+            return Err(String::from(
+                "The input path has to be a folder to watch it.",
+            ));
+        }
+
+        if self.state.output_dir.is_empty() {
+            self.add_output_path(self.output.clone())?;
+        }
+
+        self.check_watched(&dir, self.watch_recursive)?;
+
+        self.reset_start_button();
+        self.push_log(
+            Severity::Info,
+            format!("Watching {}", dir.to_string_lossy()),
+        );
+        self.watch_dir = Some(dir);
+
+        Ok(())
+    }
+
+    /// Makes sure the outputs are not written to where `dir` is watched.
+    fn check_watched(&self, dir: &Path, recursive: bool) -> Result<(), String> {
+        let output_dir = fs::canonicalize(&self.state.output_dir).ok();
+        let watched = fs::canonicalize(dir).ok();
+
+        if output_dir == watched {
+            return Err(String::from(
+                "The output folder has to differ from the watched one.",
+            ));
+        }
+
+        let nested = match (&output_dir, &watched) {
+            (Some(output_dir), Some(watched)) => output_dir.starts_with(watched),
+            _ => false,
+        };
+
+        if nested && recursive {
+            return Err(String::from(
+                "The output folder has to be outside of the watched one to include subfolders.",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Sets the output folder from the output path, if it has been edited
+    /// since it was last set.
+    fn ensure_output_dir(&mut self) -> Result<(), String> {
+        if self.state.output_dir.is_empty() {
+            self.add_output_path(self.output.clone())
+        } else if !Path::new(&self.state.output_dir).is_dir() {
+            Err(String::from("Invalid output directory."))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Queues a file that has appeared in the watched folder, unless it has
+    /// been already, or is the output of a job in the queue, which the output
+    /// folder may have been changed to since watching started.
+    fn queue_watched(&mut self, path: PathBuf) -> Command<Message> {
+        let queued = self
+            .jobs
+            .iter()
+            .any(|job| Path::new(&job.input_path) == path || Path::new(&job.output_path) == path);
+
+        if self.watch_dir.is_none() || queued {
+            return Command::none();
+        }
+
+        if let Err(e) = self.ensure_output_dir() {
+            self.report_queued(Requester::Watch(path), Err(e));
+            return Command::none();
+        }
+
+        self.queue_jobs(
+            vec![(path.clone().into_os_string(), Overrides::default())],
+            None,
+            true,
+            Requester::Watch(path),
+        )
+    }
+
+    fn start_api(&mut self) -> Result<(), String> {
+        self.refuse_while_closing()?;
+
+        let port = self
+            .api_port
+            .trim()
+            .parse::<u16>()
+            .ok()
+            .filter(|&port| port > 0)
+            .ok_or_else(|| format!("\"{}\" is not a valid API port.", self.api_port))?;
+
+        let token = self.api_token.trim().to_owned();
+
+        if token.is_empty() {
+            return Err(String::from("The API needs a token."));
+        }
+
+        self.push_log(
+            Severity::Info,
+            format!("Serving the HTTP API on http://127.0.0.1:{}", port),
+        );
+        self.api = Some((port, token));
+
+        Ok(())
+    }
+
+    fn answer_api(&mut self, call: Call) -> Command<Message> {
+        let job_json = |job: &Job| api::job_json(job, DEFAULT_MODEL);
+        let find = |jobs: &[Job], job_id| jobs.iter().position(|job| job.id == job_id);
+        let no_such_job = || Response::error(404, "There is no such job.");
+
+        let response = match &call.request {
+            Request::Submit { path, overrides } => {
+                let file = (path.clone().into_os_string(), overrides.clone());
+                let checked = match &overrides.output_dir {
+                    Some(_) => Ok(()),
+                    None => self.ensure_output_dir(),
+                };
+
+                // Answered once the input has been probed.
+                return match checked {
+                    Ok(()) => self.queue_jobs(vec![file], None, true, Requester::Api(call)),
+                    Err(e) => {
+                        call.reply(Response::error(400, &e));
+                        Command::none()
+                    }
+                };
+            }
+            Request::List => {
+                let jobs = self.jobs.iter().map(job_json).collect::<Vec<_>>();
+                Response::Json(200, serde_json::json!({ "jobs": jobs }))
+            }
+            Request::Status(job_id) => match find(&self.jobs, *job_id) {
+                Some(i) => Response::Json(200, job_json(&self.jobs[i])),
+                None => no_such_job(),
+            },
+            Request::Cancel(job_id) => match self.cancel_job(*job_id) {
+                Ok(()) => match find(&self.jobs, *job_id) {
+                    Some(i) => Response::Json(202, job_json(&self.jobs[i])),
+                    None => no_such_job(),
+                },
+                Err(e) if find(&self.jobs, *job_id).is_none() => Response::error(404, &e),
+                Err(e) => Response::error(409, &e),
+            },
+            Request::Download(job_id) => match find(&self.jobs, *job_id) {
+                Some(i) if self.jobs[i].status == JobStatus::Finished => {
+                    Response::File(PathBuf::from(&self.jobs[i].output_path))
+                }
+                Some(_) => Response::error(409, "The job has not finished."),
+                None => no_such_job(),
+            },
+        };
+
+        call.reply(response);
+        Command::none()
+    }
+
+    /// Cancels a job that is not done yet. A running one is only cancelled
+    /// once its child has been killed.
+    fn cancel_job(&mut self, job_id: JobId) -> Result<(), String> {
+        let Some(job) = self.jobs.iter_mut().find(|job| job.id == job_id) else {
+            return Err(String::from("There is no such job."));
+        };
+
+        match job.status {
+            JobStatus::Pending => {
+                job.set_status(JobStatus::Cancelled);
+                self.push_job_log(job_id, Severity::Warning, String::from("cancelled"));
+            }
+            JobStatus::Running => {
+                let checker = self
+                    .checker
+                    .as_mut()
+                    .ok_or_else(|| String::from("The job cannot be cancelled yet."))?;
+
+                checker
+                    .start_send(CheckerTask::Cancel(job_id))
+                    .map_err(|e| format!("Unable to cancel the job: {}", e))?;
+            }
+            _ => return Err(String::from("The job is already done.")),
+        }
+
+        self.processing = self.jobs.iter().any(|job| !job.status.is_done());
+
+        Ok(())
+    }
+
+    /// Hands pending jobs over to the checker, in queue order, until the
+    /// maximum number of parallel jobs is running.
+    fn dispatch_jobs(&mut self) {
+        let max_jobs = self.max_jobs.trim().parse::<usize>().unwrap_or(1).max(1);
+
+        if let Some(checker) = self.checker.as_mut() {
+            loop {
+                let running = self
+                    .jobs
+                    .iter()
+                    .filter(|job| job.status == JobStatus::Running)
+                    .count();
+
+                if running >= max_jobs {
+                    break;
+                }
+
+                let Some(job) = self
+                    .jobs
+                    .iter_mut()
+                    .find(|job| job.status == JobStatus::Pending)
+                else {
+                    break;
+                };
+
+                match checker.start_send(job.task()) {
+                    Ok(()) => job.set_status(JobStatus::Running),
+                    // Try again on the next tick.
+                    Err(e) if e.is_full() => break,
+                    Err(e) => {
+                        let err =
+                            format!("Unabled to start a background task for RealESRGAN: {}", e);
+                        job.set_status(JobStatus::Failed(err.clone()));
+
+                        rfd::MessageDialog::new()
+                            .set_title("Error")
+                            .set_level(rfd::MessageLevel::Error)
+                            .set_description(&err)
+                            .show();
+                    }
+                }
+            }
+        }
+
+        self.processing = self.jobs.iter().any(|job| !job.status.is_done());
+    }
+
+    /// Fills in the settings given on the command line, and opens its inputs.
+    fn apply_args(&mut self, args: Args) -> Command<Message> {
+        if let Some(target) = args.scale {
+            (self.scale_mode, self.scale_value) = target.mode_and_value();
+        }
+
+        if let Some(model) = args.model {
+            self.model_name = model;
+        }
+
+        if let Some(format) = args.format {
+            self.format = format;
+        }
+
+        if let Some(dir) = args.output_dir {
+            self.output = dir.to_string_lossy().to_string();
+
+            if let Err(e) = self.add_output_path(self.output.clone()) {
+                self.push_log(
+                    Severity::Warning,
+                    format!("Unable to use the output path: {}", e),
+                );
+            }
+        }
+
+        if args.inputs.is_empty() {
+            return Command::none();
+        }
+
+        let thumbnails = self.open_inputs(args.inputs);
+
+        if !args.start {
+            return thumbnails;
+        }
+
+        // Started once the window is up, like a click on Start.
+        Command::batch([
+            thumbnails,
+            Command::perform(async {}, |()| Message::StartClicked),
+        ])
+    }
+
+    /// Makes `paths`, files or folders, the selected inputs, as if they had
+    /// been picked with the ... button.
+    fn open_inputs(&mut self, paths: Vec<PathBuf>) -> Command<Message> {
+        self.state.selected_files.clear();
+        self.state.deselected.clear();
+        self.watch_dir = None;
+
+        for path in &paths {
+            if let Err(e) = self.add_input_paths(path.to_string_lossy().to_string()) {
+                self.push_log(
+                    Severity::Warning,
+                    format!("Unable to open {}: {}", path.to_string_lossy(), e),
+                );
+            }
+        }
+
+        if let Some(path) = paths.first() {
+            self.input = path.to_string_lossy().to_string();
+        }
+
+        self.request_thumbnails()
+    }
+
+    /// Adds the files handed over by a later launch to the queue, with the
+    /// settings it was given, leaving the selection and the watched folder
+    /// as they are. Nothing is asked, as the user is busy elsewhere.
+    fn queue_forwarded(&mut self, args: Args) -> Command<Message> {
+        let overrides = Overrides {
+            scale: args.scale.map(|scale| scale.to_string()),
+            model: args.model,
+            format: args.format,
+            output_dir: args.output_dir,
+            ..Default::default()
+        };
+
+        if args.inputs.is_empty() {
+            if overrides.scale.is_some()
+                || overrides.model.is_some()
+                || overrides.format.is_some()
+                || overrides.output_dir.is_some()
+            {
+                self.push_log(
+                    Severity::Warning,
+                    String::from("Ignored the options of another launch, as it had no files"),
+                );
+            }
+
+            return Command::none();
+        }
+
+        let mut files = Vec::new();
+
+        for path in &args.inputs {
+            match Self::list_inputs(path) {
+                Ok(found) => files.extend(
+                    found
+                        .into_iter()
+                        .map(|file| (file.into_os_string(), overrides.clone())),
+                ),
+                Err(e) => self.push_log(
+                    Severity::Warning,
+                    format!("Unable to open {}: {}", path.to_string_lossy(), e),
+                ),
+            }
+        }
+
+        if files.is_empty() {
+            return Command::none();
+        }
+
+        let checked = match &overrides.output_dir {
+            Some(_) => Ok(()),
+            None => self.ensure_output_dir(),
+        };
+
+        match checked {
+            Ok(()) => self.queue_jobs(files, None, true, Requester::Forwarded),
+            Err(e) => {
+                self.report_queued(Requester::Forwarded, Err(e));
+                Command::none()
+            }
+        }
+    }
+
+    /// Makes thumbnails for the selected inputs that have none yet.
+    fn request_thumbnails(&mut self) -> Command<Message> {
+        self.thumbnails
+            .request(self.state.selected_files.iter().map(PathBuf::from));
+        self.load_thumbnails()
+    }
+
+    fn load_thumbnails(&mut self) -> Command<Message> {
+        Command::batch(self.thumbnails.start().into_iter().map(|path| {
+            let input = path.clone();
+
+            Command::perform(
+                async_std::task::spawn_blocking(move || {
+                    thumbnail::generate(&input, thumbnail::SIZE)
+                }),
+                move |thumbnail| Message::ThumbnailLoaded(path, thumbnail),
+            )
+        }))
+    }
+
+    /// Compares the output of each newly finished job with its input, in the
+    /// background, if asked to when it was started.
+    fn measure_quality(&mut self) -> Command<Message> {
+        let mut commands = Vec::new();
+
+        for job in self.jobs.iter_mut().filter(|job| job.needs_measuring()) {
+            let job_id = job.id;
+            let input = PathBuf::from(&job.input_path);
+            let output = PathBuf::from(&job.output_path);
+            let crop = job.crop;
+            let upright = job.post_process().turns_upright();
+
+            job.quality = Some(Measurement::Running);
+
+            commands.push(Command::perform(
+                async_std::task::spawn_blocking(move || {
+                    quality::measure(&input, crop, upright, &output)
+                }),
+                move |result| Message::QualityMeasured(job_id, result),
+            ));
+        }
+
+        Command::batch(commands)
+    }
+
+    /// Makes the contact sheet of each comparison whose jobs are all done, in
+    /// the background.
+    fn write_contact_sheets(&mut self) -> Command<Message> {
+        let is_done = |job_id: &JobId| {
+            self.jobs
+                .iter()
+                .find(|job| job.id == *job_id)
+                .is_none_or(|job| job.status.is_done())
+        };
+
+        let (done, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.comparisons)
+            .into_iter()
+            .partition(|comparison| comparison.jobs.iter().all(|(job_id, _)| is_done(job_id)));
+
+        self.comparisons = pending;
+
+        let mut commands = Vec::new();
+
+        for comparison in done {
+            let outputs = comparison
+                .jobs
+                .iter()
+                .filter_map(|(job_id, variant)| {
+                    let job = self.jobs.iter().find(|job| job.id == *job_id)?;
+                    (job.status == JobStatus::Finished)
+                        .then(|| (variant.clone(), PathBuf::from(&job.output_path)))
+                })
+                .collect::<Vec<_>>();
+
+            if outputs.is_empty() {
+                self.push_log(
+                    Severity::Warning,
+                    String::from(
+                        "None of the compared models finished, so there is no contact sheet.",
+                    ),
+                );
+                continue;
+            }
+
+            let Comparison {
+                input,
+                crop,
+                sheet_path,
+                ..
+            } = comparison;
+
+            commands.push(Command::perform(
+                async_std::task::spawn_blocking(move || {
+                    comparison::contact_sheet(
+                        Path::new(&input),
+                        crop,
+                        &outputs,
+                        DEFAULT_MODEL,
+                        &sheet_path,
+                    )
+                    .map(|()| sheet_path)
+                }),
+                Message::ContactSheetWritten,
+            ));
+            self.sheets_in_progress += 1;
+        }
+
+        Command::batch(commands)
+    }
+
+    /// Shows `input` on the Inputs page to draw the crop region on, made into
+    /// a larger thumbnail in the background.
+    fn open_crop_editor(&mut self, input: OsString) -> Command<Message> {
+        let path = PathBuf::from(&input);
+
+        self.crop_input = Some(input.clone());
+        self.crop_image = None;
+
+        Command::perform(
+            async_std::task::spawn_blocking(move || {
+                thumbnail::generate(&path, thumbnail::CROP_EDITOR_SIZE)
+            }),
+            move |image| Message::CropEditorLoaded(input, image),
+        )
+    }
+
+    /// The crop region typed in, as drawn over the crop editor, in fractions
+    /// of the size of the input.
+    fn crop_overlay(&self) -> Option<Rectangle> {
+        let crop = self
+            .crop_mode
+            .parse(
+                &self.crop_x,
+                &self.crop_y,
+                &self.crop_width,
+                &self.crop_height,
+            )
+            .ok()??;
+
+        let (x, y, width, height) = match crop {
+            Crop::Percent {
+                x,
+                y,
+                width,
+                height,
+            } => (x / 100.0, y / 100.0, width / 100.0, height / 100.0),
+            Crop::Pixels(region) => {
+                let Some(Ok(Thumbnail {
+                    dimensions: Some((w, h)),
+                    ..
+                })) = &self.crop_image
+                else {
+                    return None;
+                };
+                let (w, h) = (*w as f64, *h as f64);
+
+                (
+                    region.x as f64 / w,
+                    region.y as f64 / h,
+                    region.width as f64 / w,
+                    region.height as f64 / h,
+                )
+            }
+        };
+
+        Some(Rectangle {
+            x: x as f32,
+            y: y as f32,
+            width: width.min(1.0 - x) as f32,
+            height: height.min(1.0 - y) as f32,
+        })
+    }
+
+    /// Fills in the crop fields from a region drawn in the crop editor.
+    fn select_crop(&mut self, selection: Rectangle) {
+        if self.crop_mode == CropMode::None {
+            self.crop_mode = CropMode::Percent;
+        }
+
+        // The edges are rounded rather than the size, so that the region
+        // never ends up past the edge of the input.
+        let (scale_x, scale_y, format): (f64, f64, fn(f64) -> String) = match self.crop_mode {
+            CropMode::Pixels => {
+                let Some(Ok(Thumbnail {
+                    dimensions: Some((w, h)),
+                    ..
+                })) = &self.crop_image
+                else {
+                    return;
+                };
+
+                (*w as f64, *h as f64, |value| format!("{}", value.round()))
+            }
+            _ => (100.0, 100.0, |value| format!("{:.1}", value)),
+        };
+
+        let round = |value: f32, scale: f64| match self.crop_mode {
+            CropMode::Pixels => (value as f64 * scale).round(),
+            _ => (value as f64 * scale * 10.0).round() / 10.0,
+        };
+
+        let left = round(selection.x, scale_x);
+        let right = round(selection.x + selection.width, scale_x);
+        let top = round(selection.y, scale_y);
+        let bottom = round(selection.y + selection.height, scale_y);
+
+        self.crop_x = format(left);
+        self.crop_y = format(top);
+        self.crop_width = format(right - left);
+        self.crop_height = format(bottom - top);
+    }
+
+    /// The crop settings, shown both on the Processing page and over the crop
+    /// editor.
+    fn crop_controls(&self) -> Element<'_, Message> {
+        let mut controls = row![
+            text("Crop").size(20).width(120),
+            pick_list(
+                &CropMode::ALL[..],
+                Some(self.crop_mode),
+                Message::CropModeSelected
+            )
+            .width(160),
+        ]
+        .align_items(Alignment::Center)
+        .spacing(8);
+
+        if self.crop_mode != CropMode::None {
+            let unit = if self.crop_mode == CropMode::Pixels {
+                "px"
+            } else {
+                "%"
+            };
+            let field = |label, value, on_input: fn(String) -> Message| {
+                row![
+                    text(label).size(16),
+                    text_input("", value).on_input(on_input).size(16).width(70),
+                ]
+                .align_items(Alignment::Center)
+                .spacing(4)
+            };
+
+            controls = controls
+                .push(field("X", &self.crop_x, Message::CropXChanged))
+                .push(field("Y", &self.crop_y, Message::CropYChanged))
+                .push(field("W", &self.crop_width, Message::CropWidthChanged))
+                .push(field("H", &self.crop_height, Message::CropHeightChanged))
+                .push(text(unit).size(16));
+        }
+
+        controls.into()
+    }
+
+    /// Switches to the Preview page and decodes the input and output of a
+    /// finished job in the background.
+    fn open_preview(&mut self, job_id: JobId) -> Command<Message> {
+        let Some(job) = self.jobs.iter().find(|job| job.id == job_id) else {
+            return Command::none();
+        };
+
+        let input = PathBuf::from(&job.input_path);
+        let output = PathBuf::from(&job.output_path);
+        let crop = job.crop;
+
+        self.preview = None;
+        self.preview_status = format!(
+            "Loading {}...",
+            input.file_name().unwrap_or_default().to_string_lossy()
+        );
+        self.current_page = Page::Preview;
+
+        Command::perform(
+            async_std::task::spawn_blocking(move || {
+                Images::load(&input, crop, &output).map(Arc::new)
+            }),
+            move |images| Message::PreviewLoaded(job_id, images),
+        )
+    }
+
+    fn apply_job_action(&mut self, job_id: JobId, action: JobAction) {
+        let Some(i) = self.jobs.iter().position(|job| job.id == job_id) else {
+            return;
+        };
+
+        let is_pending = |job: &Job| job.status == JobStatus::Pending;
+
+        match action {
+            JobAction::MoveUp if is_pending(&self.jobs[i]) => {
+                if let Some(j) = self.jobs[..i].iter().rposition(is_pending) {
+                    self.jobs.swap(i, j);
+                }
+            }
+            JobAction::MoveDown if is_pending(&self.jobs[i]) => {
+                if let Some(j) = self.jobs[i + 1..].iter().position(is_pending) {
+                    self.jobs.swap(i, i + 1 + j);
+                }
+            }
+            JobAction::MoveToTop if is_pending(&self.jobs[i]) => {
+                if let Some(j) = self.jobs.iter().position(is_pending) {
+                    let job = self.jobs.remove(i);
+                    self.jobs.insert(j, job);
+                }
+            }
+            JobAction::Remove if self.jobs[i].status != JobStatus::Running => {
+                self.jobs.remove(i);
+
+                if self.log_filter.job == Some(job_id) {
+                    self.log_filter.job = None;
+                }
+            }
+            JobAction::ShowLog => {
+                self.log_filter.job = Some(job_id);
+                self.current_page = Page::Log;
+            }
+            _ => (),
+        }
+
+        self.processing = self.jobs.iter().any(|job| !job.status.is_done());
+    }
+
+    fn push_log(&mut self, severity: Severity, message: String) {
+        self.log.push(LogEntry::new(severity, message));
+    }
+
+    fn push_job_log(&mut self, job_id: JobId, severity: Severity, message: String) {
+        let input = self
+            .jobs
+            .iter()
+            .find(|job| job.id == job_id)
+            .and_then(|job| {
+                PathBuf::from(&job.input_path)
+                    .file_name()
+                    .map(|f| f.to_owned())
+            })
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        self.log
+            .push(LogEntry::new(severity, message).with_job(job_id, input));
+    }
+
+    fn save_log(&mut self) {
+        let dialog = rfd::FileDialog::new()
+            .add_filter("Log files", &["log", "txt"])
+            .set_file_name("realesrgan.log")
+            .set_title("Save log")
+            .save_file();
+
+        let Some(path) = dialog else {
+            return;
+        };
+
+        if let Err(e) = fs::write(&path, self.log.export(&self.log_filter)) {
+            rfd::MessageDialog::new()
+                .set_title("Error")
+                .set_level(rfd::MessageLevel::Error)
+                .set_description(&format!("Unable to save the log: {}", e))
+                .show();
+        }
+    }
+
+    fn save_report(&mut self) {
+        let dialog = rfd::FileDialog::new()
+            .add_filter("CSV files", &["csv"])
+            .set_file_name("realesrgan-report.csv")
+            .set_title("Save report")
+            .save_file();
+
+        let Some(path) = dialog else {
+            return;
+        };
+
+        if let Err(e) = fs::write(&path, quality::report(&self.jobs)) {
+            rfd::MessageDialog::new()
+                .set_title("Error")
+                .set_level(rfd::MessageLevel::Error)
+                .set_description(&format!("Unable to save the report: {}", e))
+                .show();
+        }
+    }
+
+    /// Queues the inputs listed in a manifest, each with its own settings, the
+    /// same way as if they had been selected and started in the window.
+    fn import_manifest(&mut self) -> Command<Message> {
+        let dialog = rfd::FileDialog::new()
+            .add_filter("Manifests", &["json", "toml"])
+            .set_title("Import manifest")
+            .pick_file();
+
+        let Some(path) = dialog else {
+            return Command::none();
+        };
+
+        let files = Manifest::load(&path)
+            .map_err(|e| format!("Unable to read the manifest: {}", e))
+            .and_then(|manifest| {
+                if manifest.inputs.is_empty() {
+                    return Err(String::from("The manifest lists no inputs."));
+                }
+
+                manifest
+                    .inputs
+                    .iter()
+                    .map(|item| {
+                        item.overrides()
+                            .map(|overrides| (item.path.clone().into_os_string(), overrides))
+                            .map_err(|e| format!("{}\n\n{}", item.path.to_string_lossy(), e))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .and_then(|files| {
+                if files
+                    .iter()
+                    .any(|(_, overrides)| overrides.output_dir.is_none())
+                {
+                    self.ensure_output_dir()?;
+                }
+
+                Ok(files)
+            });
+
+        match files {
+            Ok(files) => self.queue_jobs(files, None, false, Requester::Import),
+            Err(e) => {
+                self.report_queued(Requester::Import, Err(e));
+                Command::none()
+            }
+        }
+    }
+
+    fn export_manifest(&mut self) {
+        let dialog = rfd::FileDialog::new()
+            .add_filter("JSON files", &["json"])
+            .add_filter("TOML files", &["toml"])
+            .set_file_name("realesrgan-manifest.json")
+            .set_title("Export manifest")
+            .save_file();
+
+        let Some(path) = dialog else {
+            return;
+        };
+
+        let manifest = Manifest::from_jobs(&self.jobs);
+        let uploads = self.jobs.len() - manifest.inputs.len();
+
+        if let Err(e) = manifest.save(&path) {
+            rfd::MessageDialog::new()
+                .set_title("Error")
+                .set_level(rfd::MessageLevel::Error)
+                .set_description(&format!("Unable to export the manifest: {}", e))
+                .show();
+        } else if uploads > 0 {
+            self.push_log(
+                Severity::Warning,
+                format!(
+                    "Left {} upload(s) out of the manifest, as they are removed on closing",
+                    uploads
+                ),
+            );
+        }
+    }
+
+    fn set_job_status(&mut self, job_id: JobId, status: JobStatus) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == job_id) {
+            job.set_status(status);
+        }
+    }
+
+    fn restore_queue(&mut self, queue: SavedQueue) {
+        if queue.inputs.is_empty() {
+            return;
+        }
+
+        self.push_log(
+            Severity::Info,
+            format!(
+                "Restored {} unfinished file(s) from the last session. Click Start to resume.",
+                queue.inputs.len()
+            ),
+        );
+
+        self.input = queue.inputs[0].path.to_string_lossy().to_string();
+        self.output = queue.output_dir.to_string_lossy().to_string();
+        self.state.output_dir = queue.output_dir.into_os_string();
+        self.state.selected_files = queue
+            .inputs
+            .iter()
+            .map(|item| item.path.clone().into_os_string())
+            .collect();
+        self.resumed = queue
+            .inputs
+            .iter()
+            .filter_map(|item| Some((item.path.clone().into_os_string(), item.overrides().ok()?)))
+            .collect();
+    }
+
+    fn request_close(&mut self) -> Command<Message> {
+        if !self.processing {
+            api::remove_uploads();
+            return window::close();
+        }
+
+        let wait = rfd::MessageDialog::new()
+            .set_buttons(rfd::MessageButtons::YesNo)
+            .set_title("Jobs Still Running")
+            .set_description(concat!(
+                "Some images are still being upscaled.\n\n",
+                "Do you want to wait for them to finish before closing?"
+            ))
+            .set_level(rfd::MessageLevel::Warning)
+            .show();
+
+        if wait {
+            self.begin_shutdown(Shutdown::WaitForJobs);
+            self.push_log(
+                Severity::Info,
+                String::from("The window will close once all jobs have finished."),
+            );
+            return Command::none();
+        }
+
+        let cancel = rfd::MessageDialog::new()
+            .set_buttons(rfd::MessageButtons::OkCancelCustom(
+                String::from("Cancel Jobs"),
+                String::from("Detach"),
+            ))
+            .set_title("Jobs Still Running")
+            .set_description(concat!(
+                "Cancel Jobs: stop the running jobs, delete their incomplete outputs ",
+                "and keep them to be resumed on the next launch.\n\n",
+                "Detach: hide the window and let the jobs finish in the background."
+            ))
+            .set_level(rfd::MessageLevel::Warning)
+            .show();
+
+        if !cancel {
+            // Finished outputs are only moved into place by the checker, so
+            // the application has to outlive the window until then.
+            self.begin_shutdown(Shutdown::WaitForJobs);
+            return window::change_mode(window::Mode::Hidden);
+        }
+
+        let interrupted = self
+            .jobs
+            .iter()
+            .filter(|job| !job.status.is_done())
+            .map(|job| job.id)
+            .collect::<Vec<_>>();
+
+        for job in self.jobs.iter_mut() {
+            if job.status == JobStatus::Pending {
+                job.set_status(JobStatus::Cancelled);
+            }
+        }
+
+        // Jobs are only started through the checker, so without it the
+        // pending ones were all there was.
+        let Some(checker) = self.checker.as_mut() else {
+            self.begin_shutdown(Shutdown::CancelJobs(interrupted));
+            self.processing = false;
+            return self.finish_shutdown();
+        };
+
+        match checker.start_send(CheckerTask::CancelAll) {
+            Ok(()) => {
+                self.begin_shutdown(Shutdown::CancelJobs(interrupted));
+                Command::none()
+            }
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_title("Error")
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_description(&format!("Unable to cancel the running jobs: {}", e))
+                    .show();
+                Command::none()
+            }
+        }
+    }
+
+    /// Stops taking new work, which would keep a hidden window from ever
+    /// closing, until the running jobs have ended.
+    fn begin_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = shutdown;
+        self.watch_dir = None;
+        self.api = None;
+        // Later launches start an instance of their own instead.
+        self.instance = None;
+    }
+
+    fn refuse_while_closing(&self) -> Result<(), String> {
+        match self.shutdown {
+            Shutdown::None => Ok(()),
+            _ => Err(String::from("No more jobs are taken while closing.")),
+        }
+    }
+
+    /// Closes the window, if asked to, once the jobs and whatever is made from
+    /// their outputs are done.
+    fn close_when_done(&mut self) -> Command<Message> {
+        let measuring = self
+            .jobs
+            .iter()
+            .any(|job| job.quality == Some(Measurement::Running));
+
+        if self.shutdown == Shutdown::None
+            || self.processing
+            || self.sheets_in_progress > 0
+            || measuring
+        {
+            return Command::none();
+        }
+
+        self.finish_shutdown()
+    }
+
+    /// Called once the last job has ended after the user asked to close the
+    /// window.
+    fn finish_shutdown(&mut self) -> Command<Message> {
+        // Jobs cancelled earlier on, from the Queue page or over HTTP, are
+        // not resumed.
+        if let Shutdown::CancelJobs(interrupted) = &self.shutdown {
+            let queue = SavedQueue {
+                output_dir: self.state.output_dir.clone().into(),
+                inputs: self
+                    .jobs
+                    .iter()
+                    .filter(|job| {
+                        interrupted.contains(&job.id)
+                            && job.status == JobStatus::Cancelled
+                            && !api::is_upload(Path::new(&job.input_path))
+                    })
+                    .map(Item::from_job)
+                    .collect(),
+            };
+
+            if !queue.inputs.is_empty() {
+                if let Err(e) = queue.save() {
+                    rfd::MessageDialog::new()
+                        .set_title("Error")
+                        .set_level(rfd::MessageLevel::Error)
+                        .set_description(&format!("Unable to save the unfinished jobs: {}", e))
+                        .show();
+                }
+            }
+        }
+
+        api::remove_uploads();
+        window::close()
+    }
+
+    fn apply_checker_updates(&mut self, result: CheckerResult) {
+        use CheckerResult::*;
+
+        match result {
+            Ended => {
+                self.processing = self.jobs.iter().any(|job| !job.status.is_done());
+            }
+
+            ChildSpawned(job_id, pid) => {
+                let Some(job) = self.jobs.iter_mut().find(|job| job.id == job_id) else {
+                    return;
+                };
+
+                job.pid = Some(pid);
+                job.step += 1;
+                job.progress = 0.0;
+
+                let (pass, passes, frames) = (job.step, job.plan.passes, job.input.frames);
+                let unit = match job.input.format {
+                    InputFormat::Archive => "pages",
+                    _ => "frames",
+                };
+
+                let log = match (job.input.is_still(), passes > 1) {
+                    (false, true) => format!(
+                        "started pass {} of {} over {} {} (pid {})",
+                        pass, passes, frames, unit, pid
+                    ),
+                    (false, false) => {
+                        format!("started upscaling {} {} (pid {})", frames, unit, pid)
+                    }
+                    (true, true) => format!("started pass {} of {} (pid {})", pass, passes, pid),
+                    (true, false) => format!("started (pid {})", pid),
+                };
+
+                self.push_job_log(job_id, Severity::Info, log);
+            }
+
+            ChildConverting(job_id) => {
+                let message = match self.jobs.iter().find(|job| job.id == job_id) {
+                    Some(Job {
+                        crop: Some(region), ..
+                    }) => format!("cropping the input to {}", region),
+                    _ => String::from("converting the input to PNG"),
+                };
+
+                self.push_job_log(job_id, Severity::Info, message);
+            }
+
+            ChildSplitting(job_id) => {
+                let Some(job) = self.jobs.iter().find(|job| job.id == job_id) else {
+                    return;
+                };
+
+                let log = match job.input.format {
+                    InputFormat::Video(_) => {
+                        format!("extracting {} frames with ffmpeg", job.input.frames)
+                    }
+                    InputFormat::Archive => format!("extracting {} pages", job.input.frames),
+                    InputFormat::Image(_) => {
+                        format!("splitting the animation into {} frames", job.input.frames)
+                    }
+                };
+                self.push_job_log(job_id, Severity::Info, log);
+            }
+
+            ChildProgress(job_id, progress) => {
+                if let Some(job) = self.jobs.iter_mut().find(|job| job.id == job_id) {
+                    job.progress = progress;
+                }
+            }
+
+            ChildPostProcessing(job_id) => {
+                if let Some(job) = self.jobs.iter_mut().find(|job| job.id == job_id) {
+                    job.progress = 100.0;
+                }
+
+                self.push_job_log(job_id, Severity::Info, String::from("post-processing"));
+            }
+
+            ChildEncoding(job_id) => {
+                if let Some(job) = self.jobs.iter_mut().find(|job| job.id == job_id) {
+                    job.progress = 100.0;
+                }
+
+                self.push_job_log(
+                    job_id,
+                    Severity::Info,
+                    String::from("encoding the video with ffmpeg"),
+                );
+            }
+
+            ChildLog(job_id, raw_log) => {
+                // The CLI reports its progress as lines like "12.34%", for
+                // each frame of its own. That over all frames comes from the
+                // checker instead.
+                let progress = raw_log
+                    .trim()
+                    .strip_suffix('%')
+                    .and_then(|p| p.parse::<f32>().ok());
+
+                if let Some(progress) = progress {
+                    let job = self.jobs.iter_mut().find(|job| job.id == job_id);
+
+                    if let Some(job) = job.filter(|job| job.input.is_still()) {
+                        job.progress = progress;
+                    }
+                }
+
+                self.push_job_log(job_id, Severity::Debug, raw_log);
+            }
+
+            ChildExited(job_id, exit) => {
+                if !exit.success() {
+                    let err = format!("realesrgan returned {}", exit.code().unwrap_or(-1));
+                    self.set_job_status(job_id, JobStatus::Failed(err.clone()));
+                    self.push_job_log(job_id, Severity::Error, err.clone());
+                    self.show_error_on_start_button(&err);
+                } else {
+                    self.set_job_status(job_id, JobStatus::Finished);
+                    self.push_job_log(job_id, Severity::Info, String::from("complete!"));
+                }
+            }
+
+            ChildErrored(job_id, err) => {
+                self.set_job_status(job_id, JobStatus::Failed(err.clone()));
+
+                rfd::MessageDialog::new()
+                    .set_title("Error")
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_description(&format!(
+                        "Unexpected error occured while running RealESRGAN: {}",
+                        err
+                    ))
+                    .show();
+
+                self.push_job_log(job_id, Severity::Error, err);
+            }
+
+            ChildTimedOut(job_id, reason) => {
+                self.set_job_status(job_id, JobStatus::TimedOut(reason.clone()));
+                self.show_error_on_start_button(&format!("realesrgan timed out: {}", reason));
+                self.push_job_log(job_id, Severity::Error, format!("timed out: {}", reason));
+            }
+
+            ChildCancelled(job_id) => {
+                self.set_job_status(job_id, JobStatus::Cancelled);
+                self.push_job_log(job_id, Severity::Warning, String::from("cancelled"));
+            }
+
+            SpawnError(job_id, err) => {
+                self.set_job_status(job_id, JobStatus::Failed(err.clone()));
+                self.push_job_log(job_id, Severity::Error, format!("unable to spawn: {}", err));
+
+                rfd::MessageDialog::new()
+                    .set_title("Error")
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_description(&format!("Unable to spawn a realesrgan instance:\n{}", err))
+                    .show();
+            }
+        }
+    }
+}
+
+impl Application for RealEsrgan {
+    type Executor = executor::Default;
+    type Message = Message;
+    type Theme = Theme;
+    type Flags = Flags;
+
+    fn new(flags: Flags) -> (Self, Command<Message>) {
+        let mut app = Self {
+            start_button_text: String::from("Click Here to Start"),
+            filename_format: String::from("{name}-{scale}x"),
+            scale_value: String::from("4"),
+            fit_color: String::from("#000000"),
+            crop_x: String::from("0"),
+            crop_y: String::from("0"),
+            crop_width: String::from("100"),
+            crop_height: String::from("100"),
+            compare_models: String::from(
+                "realesrgan-x4plus, realesrgan-x4plus-anime, realesr-animevideov3",
+            ),
+            ffmpeg_path: String::from("ffmpeg"),
+            video_codec: String::from("libx264"),
+            video_crf: String::from("18"),
+            jpeg_quality: String::from("90"),
+            jpeg_background: String::from("#FFFFFF"),
+            webp_quality: String::from("90"),
+            metadata: MetadataOptions::ALL,
+            stall_timeout: String::from("300"),
+            max_jobs: String::from("1"),
+            log_capacity: log::DEFAULT_CAPACITY.to_string(),
+            api_port: String::from("8765"),
+            api_token: api::generate_token(),
+            instance: flags.instance.map(Arc::new),
+            ..Default::default()
+        };
+
+        // Another instance may be running after all, and writing outputs of
+        // its own.
+        let leftovers = match &app.instance {
+            Some(_) => partial::clean_up_leftovers(),
+            None => 0,
+        };
+
+        if leftovers > 0 {
+            app.push_log(
+                Severity::Info,
+                format!(
+                    "Removed {} incomplete output(s) left over from the last session.",
+                    leftovers
+                ),
+            );
+        }
+
+        match SavedQueue::load() {
+            Some(Ok(queue)) => app.restore_queue(queue),
+            Some(Err(e)) => app.push_log(
+                Severity::Warning,
+                format!("Unable to load the saved queue: {}", e),
+            ),
+            None => (),
+        }
+
+        let command = Command::batch([app.request_thumbnails(), app.apply_args(flags.args)]);
+
+        (app, command)
+    }
+
+    fn title(&self) -> String {
+        String::from("realesrgan-ncnn-vulkan-gui")
+    }
+
+    fn update(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::AdvancedOptionsClicked(check) => self.advanced_options = check,
+            Message::ApiCalled(call) => return self.answer_api(call),
+            Message::ApiClicked(true) => {
+                if let Err(e) = self.start_api() {
+                    self.show_error_on_start_button(&e);
+                }
+            }
+            Message::ApiClicked(false) => self.api = None,
+            Message::ApiFailed(e) => {
+                self.api = None;
+                self.push_log(
+                    Severity::Error,
+                    format!("Unable to serve the HTTP API: {}", e),
+                );
+            }
+            Message::ApiPortChanged(port) => self.api_port = port,
+            Message::ApiTokenChanged(token) => self.api_token = token,
+            Message::CropEditorClosed => {
+                self.crop_input = None;
+                self.crop_image = None;
+            }
+            Message::CropEditorLoaded(input, image) => {
+                if self.crop_input.as_ref() == Some(&input) {
+                    self.crop_image = Some(image);
+                }
+            }
+            Message::CropEditorOpened(input) => return self.open_crop_editor(input),
+            Message::CropHeightChanged(height) => self.crop_height = height,
+            Message::CropModeSelected(mode) => self.crop_mode = mode,
+            Message::CropSelected(selection) => self.select_crop(selection),
+            Message::CropWidthChanged(width) => self.crop_width = width,
+            Message::CropXChanged(x) => self.crop_x = x,
+            Message::CropYChanged(y) => self.crop_y = y,
+            Message::AskPath {
+                path_type: PathType::Input,
+            } => {
+                let mut supported = input::supported_extensions();
+                supported.extend(video::EXTENSIONS);
+                supported.extend(archive::EXTENSIONS);
+
+                let mut dialog = rfd::FileDialog::new().add_filter("Supported files", &supported);
+
+                for (name, extensions) in input::FILTERS {
+                    dialog = dialog.add_filter(name, extensions);
+                }
+
+                dialog = dialog.add_filter("Videos", video::EXTENSIONS);
+                dialog = dialog.add_filter("Comic archives", archive::EXTENSIONS);
+
+                let dialog = dialog.set_title("Input files").pick_files();
+
+                if let Some(files) = dialog {
+                    if !files.is_empty() {
+                        let path = files[0].to_string_lossy().to_string();
+                        self.input = path;
+                    }
+                    self.state.selected_files =
+                        files.into_iter().map(|p| p.into_os_string()).collect();
+                    self.state.deselected.clear();
+                    self.watch_dir = None;
+
+                    return self.request_thumbnails();
+                }
+            }
+            Message::AskPath {
+                path_type: PathType::Output,
+            } => {
+                let dialog = rfd::FileDialog::new().pick_folder();
+
+                if let Some(dir) = dialog {
+                    self.state.output_dir = dir.into();
+                    self.output = self.state.output_dir.to_string_lossy().to_string();
+                }
+            }
+            Message::CheckerReady(sender) => self.checker = Some(sender),
+            Message::ChildUpdate(result) => {
+                self.apply_checker_updates(result);
+                self.dispatch_jobs();
+
+                let commands =
+                    Command::batch([self.measure_quality(), self.write_contact_sheets()]);
+
+                return Command::batch([commands, self.close_when_done()]);
+            }
+            Message::CompareClicked => {
+                match comparison::parse_variants(&self.compare_models, self.compare_tta) {
+                    Ok(variants) => return self.start(Some(variants)),
+                    Err(e) => self.show_error_on_start_button(&e),
+                }
+            }
+            Message::CompareModelsChanged(models) => self.compare_models = models,
+            Message::CompareTtaSelected(tta) => self.compare_tta = tta,
+            Message::ContactSheetWritten(result) => {
+                self.sheets_in_progress -= 1;
+
+                match result {
+                    Ok(path) => self.push_log(
+                        Severity::Info,
+                        format!("Wrote the model comparison to {}", path.to_string_lossy()),
+                    ),
+                    Err(e) => self.push_log(
+                        Severity::Error,
+                        format!("Unable to write the model comparison: {}", e),
+                    ),
+                }
+
+                return self.close_when_done();
+            }
+            Message::CloseRequested => return self.request_close(),
+            Message::FfmpegPathChanged(path) => self.ffmpeg_path = path,
+            Message::FitColorChanged(color) => self.fit_color = color,
+            Message::FitHeightChanged(height) => self.fit_height = height,
+            Message::FitModeSelected(mode) => self.fit_mode = mode,
+            Message::FitWidthChanged(width) => self.fit_width = width,
+            Message::GpuIdChanged(id) => self.gpu_id = id,
+            Message::InputToggled(path, selected) => {
+                if selected {
+                    self.state.deselected.remove(&path);
+                } else {
+                    self.state.deselected.insert(path);
+                }
+            }
+            Message::InputsLoadClicked => {
+                self.state.selected_files.clear();
+                self.state.deselected.clear();
+
+                if let Err(e) = self.add_input_paths(self.input.clone()) {
+                    self.push_log(Severity::Error, format!("Unable to load the inputs: {}", e));
+                }
+
+                return self.request_thumbnails();
+            }
+            Message::InputsProbed(request, probed) => {
+                let requester = request.requester.clone();
+                let queued = self.queue_probed(request, probed);
+                self.report_queued(requester, queued);
+            }
+            Message::InputsSelectAllClicked(selected) => {
+                if selected {
+                    self.state.deselected.clear();
+                } else {
+                    self.state.deselected = self.state.selected_files.iter().cloned().collect();
+                }
+            }
+            Message::JobActionClicked(job_id, JobAction::Compare) => {
+                return self.open_preview(job_id)
+            }
+            Message::JobActionClicked(job_id, action) => self.apply_job_action(job_id, action),
+            Message::JpegQualityChanged(quality) => self.jpeg_quality = quality,
+            Message::JpegSubsamplingSelected(subsampling) => self.jpeg_subsampling = subsampling,
+            Message::JpegBackgroundChanged(color) => self.jpeg_background = color,
+            Message::LaunchForwarded(args) => {
+                return Command::batch([self.queue_forwarded(args), window::gain_focus()]);
+            }
+            Message::TransparencySelected(transparency) => self.transparency = transparency,
+            Message::LogCapacityChanged(capacity) => {
+                if let Ok(capacity) = capacity.trim().parse() {
+                    self.log.set_capacity(capacity);
+                }
+                self.log_capacity = capacity;
+            }
+            Message::LogCopyClicked => {
+                return iced::clipboard::write(self.log.export(&self.log_filter));
+            }
+            Message::LogJobFilterSelected(filter) => {
+                self.log_filter.job = match filter {
+                    JobFilter::All => None,
+                    JobFilter::Job(id) => Some(id),
+                }
+            }
+            Message::LogSaveClicked => self.save_log(),
+            Message::LogSearchChanged(search) => self.log_filter.search = search,
+            Message::LogSeveritySelected(severity) => self.log_filter.min_severity = severity,
+            Message::LogToFileClicked(enabled) => {
+                if let Err(e) = self.log.set_write_to_file(enabled) {
+                    self.push_log(
+                        Severity::Error,
+                        format!("Unable to open the log file: {}", e),
+                    );
+                }
+            }
+            Message::JobTimeoutChanged(secs) => self.job_timeout = secs,
+            Message::MaxJobsChanged(jobs) => self.max_jobs = jobs,
+            Message::MeasureQualityClicked(measure) => self.measure_quality = measure,
+            Message::MetadataExifClicked(keep) => self.metadata.exif = keep,
+            Message::MetadataIccClicked(keep) => self.metadata.icc = keep,
+            Message::MetadataXmpClicked(keep) => self.metadata.xmp = keep,
+            Message::ModelNameChanged(name) => self.model_name = name,
+            Message::ModelPathChanged(path) => self.model_path = path,
+            Message::OutputFormatChanged(format) => self.format = format,
+            Message::OutputNameChanged(name) => self.filename_format = name,
+            Message::PathChanged { path_type, path } => match path_type {
+                PathType::Input => {
+                    self.state.selected_files.clear();
+                    self.state.deselected.clear();
+                    self.watch_dir = None;
+                    self.input = path
+                }
+                PathType::Output => {
                     self.state.output_dir.clear();
                     self.output = path
                 }
             },
-            Message::StartClicked => self.start(),
+            Message::PngCompressionSelected(compression) => self.png_compression = compression,
+            Message::PreviewActualSizeClicked => {
+                if let Some(preview) = self.preview.as_mut() {
+                    preview.actual_size();
+                }
+            }
+            Message::PreviewFitClicked => {
+                if let Some(preview) = self.preview.as_mut() {
+                    preview.fit();
+                }
+            }
+            Message::PreviewInteracted(interaction) => {
+                if let Some(preview) = self.preview.as_mut() {
+                    preview.interact(interaction);
+                }
+            }
+            Message::PreviewLoaded(job_id, images) => match images {
+                Ok(images) => self.preview = Some(Preview::new(job_id, images)),
+                Err(e) => {
+                    self.preview_status = format!("Unable to load the comparison: {}", e);
+                    self.push_job_log(job_id, Severity::Error, format!("unable to compare: {}", e));
+                }
+            },
+            Message::QualityMeasured(job_id, result) => {
+                let (measurement, severity, log) = match result {
+                    Ok(quality) => (
+                        Measurement::Done(quality),
+                        Severity::Info,
+                        quality.to_string(),
+                    ),
+                    Err(e) => (
+                        Measurement::Failed(e.clone()),
+                        Severity::Warning,
+                        format!("unable to measure the quality: {}", e),
+                    ),
+                };
+
+                if let Some(job) = self.jobs.iter_mut().find(|job| job.id == job_id) {
+                    job.quality = Some(measurement);
+                }
+
+                self.push_job_log(job_id, severity, log);
+
+                return self.close_when_done();
+            }
+            Message::QueueExportClicked => self.export_manifest(),
+            Message::QueueImportClicked => return self.import_manifest(),
+            Message::QueueSaveReportClicked => self.save_report(),
+            Message::QueueClearDoneClicked => {
+                self.jobs.retain(|job| !job.status.is_done());
+                self.log_filter.job = None;
+            }
+            Message::StallTimeoutChanged(secs) => self.stall_timeout = secs,
+            Message::StartClicked => return self.start(None),
             Message::SwitchPage(page) => self.current_page = page,
             Message::Tick => {
+                self.dispatch_jobs();
                 let _ = self.checker.as_mut().unwrap().try_send(CheckerTask::Poll);
             }
+            Message::ThumbnailLoaded(path, thumbnail) => {
+                self.thumbnails.finish(path, thumbnail);
+                return self.load_thumbnails();
+            }
             Message::TTAModeClicked(check) => self.tta_mode = check,
+            Message::ScaleModeSelected(mode) => self.scale_mode = mode,
+            Message::ScaleValueChanged(value) => self.scale_value = value,
             Message::UpscaleRatioSelected(ratio) => self.upscale_ratio = ratio,
+            Message::VideoCodecChanged(codec) => self.video_codec = codec,
+            Message::VideoCrfChanged(crf) => self.video_crf = crf,
+            Message::WatchClicked(true) => {
+                if let Err(e) = self.start_watching() {
+                    self.show_error_on_start_button(&e);
+                }
+            }
+            Message::WatchClicked(false) => {
+                if let Some(dir) = self.watch_dir.take() {
+                    self.push_log(
+                        Severity::Info,
+                        format!("Stopped watching {}", dir.to_string_lossy()),
+                    );
+                }
+            }
+            Message::WatchFailed(e) => {
+                let dir = self.watch_dir.as_deref().unwrap_or_else(|| Path::new(""));
+                self.push_log(
+                    Severity::Warning,
+                    format!("Watching {}: {}", dir.to_string_lossy(), e),
+                );
+            }
+            Message::WatchFound(path) => return self.queue_watched(path),
+            Message::WatchRecursiveClicked(recursive) => {
+                let checked = match &self.watch_dir {
+                    Some(dir) => self.check_watched(dir, recursive),
+                    None => Ok(()),
+                };
+
+                match checked {
+                    Ok(()) => self.watch_recursive = recursive,
+                    Err(e) => self.show_error_on_start_button(&e),
+                }
+            }
+            Message::WebpLosslessClicked(lossless) => self.webp_lossless = lossless,
+            Message::WebpQualityChanged(quality) => self.webp_quality = quality,
         };
 
         Command::none()
     }
 
-    fn view(&self) -> Element<Message> {
+    fn view(&self) -> Element<'_, Message> {
         let textbox = |label, text_ref, path_type| {
             row![
                 text(label).size(20).width(100),
@@ -570,36 +2841,32 @@ impl Application for RealEsrgan {
 
         let start = row![Space::with_width(16), start_button, Space::with_width(16),];
 
-        let menubar = row![
-            button("Processing")
-                .on_press(Message::SwitchPage(Page::Processing))
-                .width(120)
-                .style(match self.current_page {
-                    Page::Processing => theme::Button::Primary,
-                    Page::Output => theme::Button::Secondary,
-                    Page::Log => theme::Button::Secondary,
-                }),
-            button("Output")
-                .on_press(Message::SwitchPage(Page::Output))
-                .width(120)
-                .style(match self.current_page {
-                    Page::Processing => theme::Button::Secondary,
-                    Page::Output => theme::Button::Primary,
-                    Page::Log => theme::Button::Secondary,
-                }),
-            button("Log")
-                .on_press(Message::SwitchPage(Page::Log))
+        let page_button = |label, page| {
+            button(label)
+                .on_press(Message::SwitchPage(page))
                 .width(120)
-                .style(match self.current_page {
-                    Page::Processing => theme::Button::Secondary,
-                    Page::Output => theme::Button::Secondary,
-                    Page::Log => theme::Button::Primary,
-                }),
+                .style(if self.current_page == page {
+                    theme::Button::Primary
+                } else {
+                    theme::Button::Secondary
+                })
+        };
+
+        let mut menubar = row![
+            page_button("Processing", Page::Processing),
+            page_button("Inputs", Page::Inputs),
+            page_button("Output", Page::Output),
+            page_button("Queue", Page::Queue),
+            page_button("Log", Page::Log),
         ]
         .align_items(Alignment::Center)
         .spacing(8)
         .padding(8);
 
+        if self.preview.is_some() || self.current_page == Page::Preview {
+            menubar = menubar.push(page_button("Preview", Page::Preview));
+        }
+
         // This is made a macro to workaround callback type checking woes
         macro_rules! textbox {
             (INTERNAL $label:expr, $text_ref:expr, $callback:expr, $advanced:expr) => {{
@@ -643,9 +2910,25 @@ impl Application for RealEsrgan {
                     .size(20)
                 };
 
+                let scale = row![
+                    text("Upscale to").size(20).width(120),
+                    pick_list(
+                        &ScaleMode::ALL[..],
+                        Some(self.scale_mode),
+                        Message::ScaleModeSelected
+                    )
+                    .width(160),
+                    text_input("", &self.scale_value)
+                        .on_input(Message::ScaleValueChanged)
+                        .size(20)
+                        .width(120),
+                ]
+                .align_items(Alignment::Center)
+                .padding([16, 16, 0, 16])
+                .spacing(16);
+
                 let upscale_ratio = row![
-                    text("Upscale ratio ").size(20).width(120),
-                    option("1x", UpscaleRatio::One),
+                    text("Model scale ").size(20).width(120),
                     option("2x", UpscaleRatio::Two),
                     option("3x", UpscaleRatio::Three),
                     option("4x", UpscaleRatio::Four),
@@ -653,31 +2936,248 @@ impl Application for RealEsrgan {
                 .padding(16)
                 .spacing(32);
 
+                let mut compare_button = button("Compare");
+
+                if !self.processing {
+                    compare_button = compare_button.on_press(Message::CompareClicked);
+                }
+
+                let compare = row![
+                    text("Compare").size(20).width(120),
+                    text_input("Models, separated by commas", &self.compare_models)
+                        .on_input(Message::CompareModelsChanged)
+                        .size(16),
+                    pick_list(
+                        &TtaVariants::ALL[..],
+                        Some(self.compare_tta),
+                        Message::CompareTtaSelected
+                    )
+                    .width(200),
+                    compare_button,
+                ]
+                .align_items(Alignment::Center)
+                .padding([0, 16])
+                .spacing(8);
+
                 column![
+                    scale,
+                    container(self.crop_controls()).padding([16, 16, 0, 16]),
                     upscale_ratio,
+                    compare,
                     column![
                         checkbox(
                             "Enable TTA mode (performance intensive)",
                             self.tta_mode,
-                            |b| Message::TTAModeClicked(b)
+                            Message::TTAModeClicked
                         ),
                         checkbox(
                             "Advanced options",
                             self.advanced_options,
-                            |b| Message::AdvancedOptionsClicked(b)
+                            Message::AdvancedOptionsClicked
+                        ),
+                        checkbox(
+                            "Accept jobs over HTTP on localhost",
+                            self.api.is_some(),
+                            Message::ApiClicked
                         ),
+                        row![
+                            checkbox(
+                                "Watch the input folder and upscale new files",
+                                self.watch_dir.is_some(),
+                                Message::WatchClicked
+                            ),
+                            checkbox(
+                                "Include subfolders",
+                                self.watch_recursive,
+                                Message::WatchRecursiveClicked
+                            ),
+                        ]
+                        .spacing(32),
                     ]
                         .spacing(12)
                         .padding(12),
-                    textbox!(advanced "GPU ID", &self.gpu_id, |id| Message::GpuIdChanged(id)),
-                    textbox!(advanced "Path to Model", &self.model_path, |path| Message::ModelPathChanged(path)),
-                    textbox!(advanced "RealESRGAN Model", &self.model_name, |name| Message::ModelNameChanged(name)),
+                    textbox!(advanced "GPU ID", &self.gpu_id, Message::GpuIdChanged),
+                    textbox!(advanced "Path to Model", &self.model_path, Message::ModelPathChanged),
+                    textbox!(advanced "RealESRGAN Model", &self.model_name, Message::ModelNameChanged),
+                    textbox!(advanced "Job Timeout (s)", &self.job_timeout, Message::JobTimeoutChanged),
+                    textbox!(advanced "Stall Timeout (s)", &self.stall_timeout, Message::StallTimeoutChanged),
+                    textbox!(advanced "Parallel Jobs", &self.max_jobs, Message::MaxJobsChanged),
+                    textbox!(advanced "API Port", &self.api_port, Message::ApiPortChanged),
+                    textbox!(advanced "API Token", &self.api_token, Message::ApiTokenChanged),
                 ]
                 .align_items(Alignment::Start)
                 .padding(8)
                 .spacing(8)
             }
 
+            Page::Inputs if self.crop_input.is_some() => {
+                let name = self
+                    .crop_input
+                    .as_ref()
+                    .and_then(|input| Path::new(input).file_name())
+                    .unwrap_or_default()
+                    .to_string_lossy();
+
+                let editor: Element<Message> = match &self.crop_image {
+                    None => text("Loading...").into(),
+                    Some(Err(e)) => text(format!("Unable to load the input: {}", e)).into(),
+                    Some(Ok(Thumbnail {
+                        handle: Some(handle),
+                        ..
+                    })) => {
+                        Selection::new(handle.clone(), self.crop_overlay(), Message::CropSelected)
+                            .into()
+                    }
+                    Some(Ok(_)) => text("Videos cannot be cropped.").into(),
+                };
+
+                let mut compare_button = button("Compare Models");
+
+                if !self.processing {
+                    compare_button = compare_button.on_press(Message::CompareClicked);
+                }
+
+                column![
+                    row![
+                        text(format!("Crop region on {}", name))
+                            .size(20)
+                            .width(Length::Fill),
+                        compare_button,
+                        button("Done").on_press(Message::CropEditorClosed),
+                    ]
+                    .align_items(Alignment::Center)
+                    .spacing(8),
+                    self.crop_controls(),
+                    text(concat!(
+                        "Drag over the image to draw the region. It is applied to every still ",
+                        "image started, at the same position relative to its size when in percent."
+                    ))
+                    .size(14)
+                    .style(Color::from([0.5, 0.5, 0.5])),
+                    container(editor)
+                        .width(Length::Fill)
+                        .height(Length::Fill)
+                        .center_x()
+                        .center_y(),
+                ]
+                .padding([0, 32, 16, 32])
+                .spacing(8)
+            }
+
+            Page::Inputs => {
+                const COLUMNS: usize = 5;
+
+                let total = self.state.selected_files.len();
+                let selected = self
+                    .state
+                    .selected_files
+                    .iter()
+                    .filter(|f| !self.state.deselected.contains(*f))
+                    .count();
+
+                let header = row![
+                    text(format!("{} of {} inputs selected", selected, total))
+                        .size(20)
+                        .width(Length::Fill),
+                    button("Select All").on_press(Message::InputsSelectAllClicked(true)),
+                    button("Select None").on_press(Message::InputsSelectAllClicked(false)),
+                    button("Load Input Path").on_press(Message::InputsLoadClicked),
+                ]
+                .align_items(Alignment::Center)
+                .spacing(8);
+
+                let mut grid = column![].spacing(16);
+
+                for files in self.state.selected_files.chunks(COLUMNS) {
+                    let mut tiles = row![].spacing(16);
+
+                    for f in files {
+                        let path = Path::new(f);
+                        let mut name = path
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_string();
+
+                        if name.chars().count() > 16 {
+                            name = name.chars().take(15).chain(Some('…')).collect();
+                        }
+
+                        let (thumbnail, details): (Element<Message>, String) =
+                            match self.thumbnails.get(path) {
+                                None => (text("Loading...").size(14).into(), String::new()),
+                                Some(Ok(thumbnail)) => (
+                                    match &thumbnail.handle {
+                                        Some(handle) => mouse_area(image(handle.clone()))
+                                            .on_press(Message::CropEditorOpened(f.clone()))
+                                            .into(),
+                                        None => text("Video").size(14).into(),
+                                    },
+                                    thumbnail
+                                        .dimensions
+                                        .map(|(width, height)| format!("{}x{}", width, height))
+                                        .unwrap_or_default(),
+                                ),
+                                Some(Err(e)) => (
+                                    tooltip(
+                                        text("Unreadable")
+                                            .size(14)
+                                            .style(Color::from([0.8, 0.0, 0.0])),
+                                        e,
+                                        tooltip::Position::Bottom,
+                                    )
+                                    .style(theme::Container::Box)
+                                    .into(),
+                                    String::new(),
+                                ),
+                            };
+
+                        let path = f.clone();
+                        let tile = column![
+                            container(thumbnail)
+                                .width(thumbnail::SIZE as f32)
+                                .height(thumbnail::SIZE as f32)
+                                .center_x()
+                                .center_y(),
+                            checkbox(name, !self.state.deselected.contains(f), move |selected| {
+                                Message::InputToggled(path.clone(), selected)
+                            })
+                            .text_size(14),
+                            text(details).size(12).style(Color::from([0.5, 0.5, 0.5])),
+                        ]
+                        .width(thumbnail::SIZE as f32)
+                        .spacing(4);
+
+                        tiles = tiles.push(tile);
+                    }
+
+                    grid = grid.push(tiles);
+                }
+
+                if total > 0 {
+                    grid = grid.push(
+                        text("Click a thumbnail to draw the crop region on it.")
+                            .size(14)
+                            .style(Color::from([0.5, 0.5, 0.5])),
+                    );
+                } else {
+                    grid = grid.push(text(concat!(
+                        "No inputs are selected. Pick files with the button next to the ",
+                        "input path, or type a file or folder there and click Load Input Path."
+                    )));
+                }
+
+                column![
+                    header.padding([0, 32]),
+                    scrollable(row![
+                        Space::with_width(32),
+                        grid.push(vertical_space(32)).width(Length::Fill),
+                        Space::with_width(32),
+                    ]),
+                ]
+                .spacing(8)
+            }
+
             Page::Output => {
                 let option = |label, value| {
                     radio(label, value, Some(self.format), |val| {
@@ -695,8 +3195,180 @@ impl Application for RealEsrgan {
                 .padding(16)
                 .spacing(16);
 
+                let encoder_options = match self.format {
+                    Format::Png => row![
+                        text("Compression").size(20).width(160),
+                        pick_list(
+                            &PngCompression::ALL[..],
+                            Some(self.png_compression),
+                            Message::PngCompressionSelected
+                        )
+                        .width(120),
+                    ],
+                    Format::Jpg => row![
+                        text("Quality").size(20).width(160),
+                        text_input("1-100", &self.jpeg_quality)
+                            .on_input(Message::JpegQualityChanged)
+                            .size(20)
+                            .width(80),
+                        text("Chroma Subsampling").size(20),
+                        pick_list(
+                            &ChromaSubsampling::ALL[..],
+                            Some(self.jpeg_subsampling),
+                            Message::JpegSubsamplingSelected
+                        )
+                        .width(100),
+                    ],
+                    Format::Webp => {
+                        let mut quality =
+                            text_input("0-100", &self.webp_quality).size(20).width(80);
+
+                        if !self.webp_lossless {
+                            quality = quality.on_input(Message::WebpQualityChanged);
+                        }
+
+                        row![
+                            text("Quality").size(20).width(160),
+                            quality,
+                            checkbox("Lossless", self.webp_lossless, Message::WebpLosslessClicked),
+                        ]
+                    }
+                }
+                .align_items(Alignment::Center)
+                .padding([0, 16])
+                .spacing(16);
+
+                let mut encoder_options = column![encoder_options].spacing(16);
+
+                if self.format == Format::Jpg {
+                    let mut transparency = row![
+                        text("Transparency").size(20).width(160),
+                        pick_list(
+                            &Transparency::ALL[..],
+                            Some(self.transparency),
+                            Message::TransparencySelected
+                        )
+                        .width(240),
+                    ]
+                    .align_items(Alignment::Center)
+                    .padding([0, 16])
+                    .spacing(16);
+
+                    if self.transparency == Transparency::Color {
+                        transparency = transparency.push(
+                            text_input("#RRGGBB", &self.jpeg_background)
+                                .on_input(Message::JpegBackgroundChanged)
+                                .size(20)
+                                .width(110),
+                        );
+                    }
+
+                    encoder_options = encoder_options.push(transparency);
+                }
+
+                let size_label = if self.fit_mode == FitMode::PadToAspect {
+                    ("Aspect W", "Aspect H")
+                } else {
+                    ("Width", "Height")
+                };
+
+                let mut fit = row![
+                    text("Fit to Size").size(20).width(160),
+                    pick_list(
+                        &FitMode::ALL[..],
+                        Some(self.fit_mode),
+                        Message::FitModeSelected
+                    )
+                    .width(180),
+                ]
+                .align_items(Alignment::Center)
+                .padding([0, 16])
+                .spacing(16);
+
+                let has_width = !matches!(self.fit_mode, FitMode::None | FitMode::Height);
+                let has_height = !matches!(self.fit_mode, FitMode::None | FitMode::Width);
+
+                if has_width {
+                    fit = fit.push(
+                        text_input(size_label.0, &self.fit_width)
+                            .on_input(Message::FitWidthChanged)
+                            .size(20)
+                            .width(90),
+                    );
+                }
+
+                if has_height {
+                    fit = fit.push(
+                        text_input(size_label.1, &self.fit_height)
+                            .on_input(Message::FitHeightChanged)
+                            .size(20)
+                            .width(90),
+                    );
+                }
+
+                if self.fit_mode == FitMode::PadToAspect {
+                    fit = fit.push(
+                        text_input("#RRGGBB", &self.fit_color)
+                            .on_input(Message::FitColorChanged)
+                            .size(20)
+                            .width(110),
+                    );
+                }
+
+                let metadata = row![
+                    text("Keep Metadata").size(20).width(160),
+                    checkbox(
+                        "ICC Profile",
+                        self.metadata.icc,
+                        Message::MetadataIccClicked
+                    ),
+                    checkbox("EXIF", self.metadata.exif, Message::MetadataExifClicked),
+                    checkbox("XMP", self.metadata.xmp, Message::MetadataXmpClicked),
+                ]
+                .align_items(Alignment::Center)
+                .padding([0, 16])
+                .spacing(16);
+
+                let quality = row![
+                    text("Quality").size(20).width(160),
+                    checkbox(
+                        "Measure PSNR, SSIM and sharpness against still inputs",
+                        self.measure_quality,
+                        Message::MeasureQualityClicked,
+                    ),
+                ]
+                .align_items(Alignment::Center)
+                .padding([0, 16])
+                .spacing(16);
+
+                let video = row![
+                    text("Video").size(20).width(160),
+                    text_input("ffmpeg", &self.ffmpeg_path)
+                        .on_input(Message::FfmpegPathChanged)
+                        .size(20)
+                        .width(200),
+                    text("Codec").size(20),
+                    text_input("libx264", &self.video_codec)
+                        .on_input(Message::VideoCodecChanged)
+                        .size(20)
+                        .width(110),
+                    text("CRF").size(20),
+                    text_input("0-63", &self.video_crf)
+                        .on_input(Message::VideoCrfChanged)
+                        .size(20)
+                        .width(60),
+                ]
+                .align_items(Alignment::Center)
+                .padding([0, 16])
+                .spacing(16);
+
                 column![
                     format_radio,
+                    encoder_options,
+                    fit,
+                    metadata,
+                    quality,
+                    video,
                     textbox!("Output Name", &self.filename_format, |name| {
                         Message::OutputNameChanged(name)
                     })
@@ -712,11 +3384,237 @@ impl Application for RealEsrgan {
                 .spacing(16)
             }
 
+            Page::Queue => {
+                let header = row![
+                    text("Input").width(Length::Fill),
+                    text("Size").width(100),
+                    text("Status").width(90),
+                    text("Progress").width(70),
+                    text("Time").width(60),
+                    Space::with_width(230),
+                ]
+                .spacing(8);
+
+                let mut queue = column![].spacing(8);
+
+                for job in self.jobs.iter() {
+                    let input = PathBuf::from(&job.input_path);
+                    let name = input.file_name().unwrap_or_default().to_string_lossy();
+
+                    let (width, height) = job.input.dimensions;
+                    let mut details = format!(
+                        "{}, {}-bit{}",
+                        job.input.format_name().to_uppercase(),
+                        job.input.bit_depth,
+                        if job.input.has_alpha { ", alpha" } else { "" },
+                    );
+
+                    if job.input.format == InputFormat::Archive {
+                        details.push_str(&format!(", {} pages", job.input.frames));
+                    } else if job.input.frames > 1 {
+                        details.push_str(&format!(", {} frames", job.input.frames));
+                    }
+
+                    if let Some(region) = job.crop {
+                        details.push_str(&format!(", cropped to {}", region));
+                    }
+
+                    let size = tooltip(
+                        text(format!("{}x{}", width, height)).width(100),
+                        details,
+                        tooltip::Position::Bottom,
+                    )
+                    .style(theme::Container::Box);
+
+                    let name: Element<Message> = if job.input.extension_mismatch {
+                        tooltip(
+                            text(format!("{} (!)", name)).style(Color::from([0.8, 0.5, 0.0])),
+                            "The extension does not match the content of the file",
+                            tooltip::Position::Bottom,
+                        )
+                        .style(theme::Container::Box)
+                        .into()
+                    } else {
+                        text(name).into()
+                    };
+
+                    let status: Element<Message> = match &job.status {
+                        JobStatus::Failed(reason) | JobStatus::TimedOut(reason) => tooltip(
+                            text(&job.status).width(90),
+                            reason,
+                            tooltip::Position::Bottom,
+                        )
+                        .style(theme::Container::Box)
+                        .into(),
+                        status => text(status).width(90).into(),
+                    };
+
+                    let elapsed = job
+                        .elapsed()
+                        .map(|d| format!("{}s", d.as_secs()))
+                        .unwrap_or_default();
+
+                    let action = |label, action| {
+                        button(text(label).size(14))
+                            .on_press(Message::JobActionClicked(job.id, action))
+                            .style(theme::Button::Secondary)
+                    };
+
+                    let mut actions = row![].spacing(4);
+
+                    if job.status == JobStatus::Pending {
+                        actions = actions
+                            .push(action("Up", JobAction::MoveUp))
+                            .push(action("Down", JobAction::MoveDown))
+                            .push(action("Top", JobAction::MoveToTop));
+                    }
+
+                    if job.status != JobStatus::Running {
+                        actions = actions.push(action("Remove", JobAction::Remove));
+                    }
+
+                    actions = actions.push(action("Log", JobAction::ShowLog));
+
+                    let mut name = column![
+                        name,
+                        text(job.output_path.to_string_lossy())
+                            .size(12)
+                            .style(Color::from([0.5, 0.5, 0.5])),
+                    ]
+                    .width(Length::Fill);
+
+                    let quality = match &job.quality {
+                        Some(Measurement::Running) => Some(String::from("Measuring quality...")),
+                        Some(Measurement::Done(quality)) => Some(quality.to_string()),
+                        Some(Measurement::Failed(e)) => Some(format!("Not measured: {}", e)),
+                        None => None,
+                    };
+
+                    if let Some(quality) = quality {
+                        name =
+                            name.push(text(quality).size(12).style(Color::from([0.5, 0.5, 0.5])));
+                    }
+
+                    // Only still images and animations can be shown side by side.
+                    let comparable = job.status == JobStatus::Finished
+                        && matches!(job.input.format, InputFormat::Image(_));
+
+                    if comparable {
+                        name = name.push(
+                            text("Click to compare with the input")
+                                .size(12)
+                                .style(Color::from([0.3, 0.5, 0.8])),
+                        );
+                    }
+
+                    let name: Element<Message> = if comparable {
+                        mouse_area(name)
+                            .on_press(Message::JobActionClicked(job.id, JobAction::Compare))
+                            .into()
+                    } else {
+                        name.into()
+                    };
+
+                    queue = queue.push(
+                        row![
+                            name,
+                            size,
+                            status,
+                            text(format!("{:.0}%", job.total_progress())).width(70),
+                            text(elapsed).width(60),
+                            actions.width(230),
+                        ]
+                        .align_items(Alignment::Center)
+                        .spacing(8),
+                    );
+                }
+
+                queue = queue.push(vertical_space(32));
+
+                column![
+                    row![
+                        header,
+                        button("Import Manifest").on_press(Message::QueueImportClicked),
+                        button("Export Manifest").on_press(Message::QueueExportClicked),
+                        button("Save Report").on_press(Message::QueueSaveReportClicked),
+                        button("Clear Finished").on_press(Message::QueueClearDoneClicked),
+                    ]
+                    .align_items(Alignment::Center)
+                    .padding([0, 32])
+                    .spacing(8),
+                    scrollable(row![
+                        Space::with_width(32),
+                        queue.width(Length::Fill),
+                        Space::with_width(32),
+                    ]),
+                ]
+                .spacing(8)
+            }
+
             Page::Log => {
+                let job_filters = Some(JobFilter::All)
+                    .into_iter()
+                    .chain(self.jobs.iter().map(|job| JobFilter::Job(job.id)))
+                    .collect::<Vec<_>>();
+
+                let selected_job = match self.log_filter.job {
+                    Some(id) => JobFilter::Job(id),
+                    None => JobFilter::All,
+                };
+
+                let filters = row![
+                    pick_list(
+                        &Severity::ALL[..],
+                        Some(self.log_filter.min_severity),
+                        Message::LogSeveritySelected
+                    )
+                    .width(120),
+                    pick_list(
+                        job_filters,
+                        Some(selected_job),
+                        Message::LogJobFilterSelected
+                    )
+                    .width(120),
+                    text_input("Search", &self.log_filter.search)
+                        .on_input(Message::LogSearchChanged),
+                    button("Copy").on_press(Message::LogCopyClicked),
+                    button("Save").on_press(Message::LogSaveClicked),
+                ]
+                .align_items(Alignment::Center)
+                .spacing(8);
+
+                let settings = row![
+                    text("Keep last").size(16),
+                    text_input("", &self.log_capacity)
+                        .on_input(Message::LogCapacityChanged)
+                        .width(80),
+                    text("entries").size(16),
+                    Space::with_width(16),
+                    checkbox(
+                        "Also write to realesrgan-ncnn-vulkan-gui.log",
+                        self.log.is_writing_to_file(),
+                        Message::LogToFileClicked
+                    )
+                    .size(16),
+                ]
+                .align_items(Alignment::Center)
+                .spacing(8);
+
                 let mut log_screen = column![];
 
-                for log in self.log.iter() {
-                    log_screen = log_screen.push(text(log));
+                for entry in self
+                    .log
+                    .iter()
+                    .filter(|entry| self.log_filter.matches(entry))
+                {
+                    let style = match entry.severity {
+                        Severity::Debug => theme::Text::Color(Color::from([0.5, 0.5, 0.5])),
+                        Severity::Info => theme::Text::Default,
+                        Severity::Warning => theme::Text::Color(Color::from([0.8, 0.5, 0.0])),
+                        Severity::Error => theme::Text::Color(Color::from([0.8, 0.0, 0.0])),
+                    };
+
+                    log_screen = log_screen.push(text(entry).style(style));
                 }
 
                 log_screen = log_screen.push(vertical_space(32));
@@ -727,8 +3625,59 @@ impl Application for RealEsrgan {
                     Space::with_width(32),
                 ]);
 
-                column![scrollable_log]
+                column![
+                    column![filters, settings].padding([0, 32]).spacing(8),
+                    scrollable_log
+                ]
+                .spacing(8)
             }
+
+            Page::Preview => match &self.preview {
+                None => column![text(&self.preview_status).size(20)].padding(32),
+                Some(preview) => {
+                    let ((before_w, before_h), (after_w, after_h)) = preview.dimensions();
+                    let name = self
+                        .jobs
+                        .iter()
+                        .find(|job| job.id == preview.job_id)
+                        .and_then(|job| Path::new(&job.input_path).file_name())
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+
+                    let control = |label, message| {
+                        button(text(label).size(14))
+                            .on_press(message)
+                            .style(theme::Button::Secondary)
+                    };
+
+                    let controls = row![
+                        control("Fit", Message::PreviewFitClicked),
+                        control("1:1", Message::PreviewActualSizeClicked),
+                        control("-", Message::PreviewInteracted(Interaction::Zoom(0.8))),
+                        control("+", Message::PreviewInteracted(Interaction::Zoom(1.25))),
+                        text(format!("{:.0}%", preview.zoom() * 100.0)).width(60),
+                        text(format!(
+                            "{}: input {}x{}, output {}x{}",
+                            name, before_w, before_h, after_w, after_h
+                        )),
+                    ]
+                    .align_items(Alignment::Center)
+                    .spacing(8);
+
+                    column![
+                        controls,
+                        Viewer::new(preview, Message::PreviewInteracted),
+                        text(concat!(
+                            "The input is on the left and the output on the right. ",
+                            "Click or drag to move the split line, right-drag to pan and scroll to zoom."
+                        ))
+                        .size(14)
+                        .style(Color::from([0.5, 0.5, 0.5])),
+                    ]
+                    .padding([0, 16, 16, 16])
+                    .spacing(8)
+                }
+            },
         };
 
         column![textboxes, start, menubar, menu]
@@ -737,9 +3686,27 @@ impl Application for RealEsrgan {
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
-        Subscription::batch([
+        let mut subscriptions = vec![
             iced::time::every(Duration::from_millis(500)).map(|_| Message::Tick),
             ChildrenStatusChecker::children_status_checker(),
-        ])
+            iced::subscription::events_with(|event, _| match event {
+                Event::Window(window::Event::CloseRequested) => Some(Message::CloseRequested),
+                _ => None,
+            }),
+        ];
+
+        if let Some(dir) = &self.watch_dir {
+            subscriptions.push(watch::watch(dir.clone(), self.watch_recursive));
+        }
+
+        if let Some(listener) = &self.instance {
+            subscriptions.push(instance::listen(listener.clone()));
+        }
+
+        if let Some((port, token)) = &self.api {
+            subscriptions.push(api::serve(*port, token.clone()));
+        }
+
+        Subscription::batch(subscriptions)
     }
 }
@@ -0,0 +1,245 @@
+//! Batch manifests, which list inputs to queue along with the settings each
+//! of them is upscaled with, in JSON or, for files ending in .toml, TOML.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::api;
+use crate::encode::Encoding;
+use crate::job::{Job, Overrides};
+use crate::Format;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub inputs: Vec<Item>,
+}
+
+/// An input, and whatever it is queued with in place of the settings in the
+/// window.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Item {
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tta: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_dir: Option<PathBuf>,
+}
+
+impl Item {
+    pub fn from_job(job: &Job) -> Self {
+        let settings = &job.settings;
+        let output = Path::new(&job.output_path);
+        let format = match settings.encoding {
+            Encoding::Png { .. } => "png",
+            Encoding::Jpeg { .. } => "jpg",
+            Encoding::Webp { .. } => "webp",
+        };
+
+        Item {
+            path: PathBuf::from(&job.input_path),
+            scale: Some(settings.scale.to_string()),
+            model: Some(settings.model_name.clone()).filter(|model| !model.is_empty()),
+            tta: Some(settings.tta_mode),
+            format: Some(String::from(format)),
+            output_name: Some(settings.filename_format.clone()),
+            output_dir: output.parent().map(Path::to_path_buf),
+        }
+    }
+
+    pub fn overrides(&self) -> Result<Overrides, String> {
+        let format = match &self.format {
+            Some(format) => Some(Format::parse(format)?),
+            None => None,
+        };
+
+        Ok(Overrides {
+            scale: self.scale.clone(),
+            model: self.model.clone(),
+            tta: self.tta,
+            format,
+            output_name: self.output_name.clone(),
+            output_dir: self.output_dir.clone(),
+        })
+    }
+}
+
+impl Manifest {
+    /// Relative paths in the manifest are taken to be relative to the folder
+    /// it is in.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut manifest: Self = read(path)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+
+        for item in manifest.inputs.iter_mut() {
+            item.path = base.join(&item.path);
+            item.output_dir = item.output_dir.as_ref().map(|dir| base.join(dir));
+        }
+
+        Ok(manifest)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = if is_toml(path) {
+            toml::to_string_pretty(self).map_err(|e| e.to_string())?
+        } else {
+            serde_json::to_string_pretty(self).map_err(|e| e.to_string())?
+        };
+
+        fs::write(path, text).map_err(|e| e.to_string())
+    }
+
+    /// Lists `jobs` with everything a manifest can hold, so that they are
+    /// queued the same way again when it is imported. Uploads are left out,
+    /// as they do not outlive the application.
+    pub fn from_jobs(jobs: &[Job]) -> Self {
+        Manifest {
+            inputs: jobs
+                .iter()
+                .filter(|job| !api::is_upload(Path::new(&job.input_path)))
+                .map(Item::from_job)
+                .collect(),
+        }
+    }
+}
+
+/// Reads a manifest, or anything written like one, as JSON or TOML by its
+/// extension.
+pub fn read<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+    if is_toml(path) {
+        toml::from_str(&text).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_str(&text).map_err(|e| e.to_string())
+    }
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A folder of its own for each test, as they run in parallel.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("upscaler-manifest-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn loads_json_and_toml_relative_to_the_manifest() {
+        let dir = temp_dir("load");
+        let json = dir.join("batch.json");
+        let toml = dir.join("batch.TOML");
+        // Written as a string literal, which reads the same in JSON and TOML.
+        let absolute = std::env::temp_dir().join("b.png");
+        let absolute_literal = serde_json::to_string(&absolute).unwrap();
+
+        fs::write(
+            &json,
+            format!(
+                r#"{{"inputs": [{{"path": "a.png", "scale": "2x", "output_dir": "out"}}, {{"path": {}}}]}}"#,
+                absolute_literal
+            ),
+        )
+        .unwrap();
+        fs::write(
+            &toml,
+            format!(
+                "[[inputs]]\npath = \"a.png\"\nscale = \"2x\"\noutput_dir = \"out\"\n\n[[inputs]]\npath = {}\n",
+                absolute_literal
+            ),
+        )
+        .unwrap();
+
+        let loaded = [Manifest::load(&json), Manifest::load(&toml)];
+        let _ = fs::remove_dir_all(&dir);
+
+        for manifest in loaded {
+            let inputs = manifest.unwrap().inputs;
+
+            assert_eq!(inputs.len(), 2);
+            assert_eq!(inputs[0].path, dir.join("a.png"));
+            assert_eq!(inputs[0].scale.as_deref(), Some("2x"));
+            assert_eq!(inputs[0].output_dir, Some(dir.join("out")));
+            assert_eq!(inputs[1].path, absolute);
+            assert_eq!(inputs[1].output_dir, None);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_fields_and_formats() {
+        let dir = temp_dir("reject");
+        let misspelt = dir.join("misspelt.json");
+        let format = dir.join("format.json");
+
+        fs::write(
+            &misspelt,
+            r#"{"inputs": [{"path": "a.png", "scael": "2x"}]}"#,
+        )
+        .unwrap();
+        fs::write(
+            &format,
+            r#"{"inputs": [{"path": "a.png", "format": "gif"}]}"#,
+        )
+        .unwrap();
+
+        let misspelt = Manifest::load(&misspelt);
+        let format = Manifest::load(&format);
+        let missing = Manifest::load(&dir.join("missing.json"));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(misspelt.is_err());
+        assert!(missing.is_err());
+        assert!(format.unwrap().inputs[0].overrides().is_err());
+    }
+
+    #[test]
+    fn saved_manifests_load_again() {
+        let dir = temp_dir("save");
+        let manifest = Manifest {
+            inputs: vec![Item {
+                path: dir.join("a.png"),
+                scale: Some(String::from("1920w")),
+                tta: Some(true),
+                format: Some(String::from("webp")),
+                ..Default::default()
+            }],
+        };
+
+        let loaded = ["batch.json", "batch.toml"].map(|name| {
+            let path = dir.join(name);
+            manifest.save(&path).and_then(|()| Manifest::load(&path))
+        });
+        let _ = fs::remove_dir_all(&dir);
+
+        for loaded in loaded {
+            let item = &loaded.unwrap().inputs[0];
+            let overrides = item.overrides().unwrap();
+
+            assert_eq!(item.path, dir.join("a.png"));
+            assert_eq!(overrides.scale.as_deref(), Some("1920w"));
+            assert_eq!(overrides.tta, Some(true));
+            assert_eq!(overrides.format, Some(Format::Webp));
+            assert_eq!(overrides.model, None);
+        }
+    }
+}
@@ -0,0 +1,777 @@
+//! Carrying the ICC profile, EXIF and XMP metadata of an input over to its
+//! output, as the CLI drops all of them.
+
+use std::fs;
+use std::path::Path;
+
+use img_parts::jpeg::{Jpeg, JpegSegment};
+use img_parts::png::{Png, PngChunk};
+use img_parts::riff::{RiffChunk, RiffContent};
+use img_parts::webp::WebP;
+use img_parts::{Bytes, DynImage, ImageEXIF, ImageICC};
+
+const JPEG_APP1: u8 = 0xE1;
+const JPEG_APP2: u8 = 0xE2;
+const JPEG_EXIF_PREFIX: &[u8] = b"Exif\0\0";
+const JPEG_ICC_PREFIX: &[u8] = b"ICC_PROFILE\0";
+const JPEG_XMP_PREFIX: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// The most a JPEG segment can hold, after its length field.
+const JPEG_SEGMENT_MAX_SIZE: usize = 65533;
+
+const PNG_ITXT: [u8; 4] = *b"iTXt";
+const PNG_IDAT: [u8; 4] = *b"IDAT";
+const PNG_SRGB: [u8; 4] = *b"sRGB";
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp\0";
+
+const WEBP_VP8X: [u8; 4] = *b"VP8X";
+const WEBP_VP8L: [u8; 4] = *b"VP8L";
+const WEBP_ALPH: [u8; 4] = *b"ALPH";
+const WEBP_ANIM: [u8; 4] = *b"ANIM";
+const WEBP_ICCP: [u8; 4] = *b"ICCP";
+const WEBP_EXIF: [u8; 4] = *b"EXIF";
+const WEBP_XMP: [u8; 4] = *b"XMP ";
+
+const TAG_IMAGE_WIDTH: u16 = 0x0100;
+const TAG_IMAGE_LENGTH: u16 = 0x0101;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_EXIF_IFD_POINTER: u16 = 0x8769;
+const TAG_PIXEL_X_DIMENSION: u16 = 0xA002;
+const TAG_PIXEL_Y_DIMENSION: u16 = 0xA003;
+
+/// Which metadata is copied from the input to the output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MetadataOptions {
+    pub icc: bool,
+    pub exif: bool,
+    pub xmp: bool,
+}
+
+impl MetadataOptions {
+    pub const ALL: MetadataOptions = MetadataOptions {
+        icc: true,
+        exif: true,
+        xmp: true,
+    };
+
+    pub fn any(&self) -> bool {
+        self.icc || self.exif || self.xmp
+    }
+}
+
+#[derive(Debug, Default)]
+struct Metadata {
+    icc: Option<Bytes>,
+    exif: Option<Bytes>,
+    xmp: Option<Bytes>,
+}
+
+impl Metadata {
+    fn read(image: &DynImage, options: MetadataOptions) -> Self {
+        let xmp = match image {
+            DynImage::Jpeg(jpeg) => jpeg
+                .segments_by_marker(JPEG_APP1)
+                .find_map(|segment| strip_prefix(segment.contents(), JPEG_XMP_PREFIX)),
+            DynImage::Png(png) => png
+                .chunks_by_type(PNG_ITXT)
+                .find_map(|chunk| png_xmp(chunk.contents())),
+            DynImage::WebP(webp) => webp
+                .chunk_by_id(WEBP_XMP)
+                .and_then(|chunk| chunk.content().data().cloned()),
+        };
+
+        // The WebP spec has EXIF stored without the prefix it has in a JPEG,
+        // but img-parts only reads it with one. Both are found in the wild.
+        let exif = match image {
+            DynImage::WebP(webp) => webp
+                .chunk_by_id(WEBP_EXIF)
+                .and_then(|chunk| chunk.content().data())
+                .map(|data| strip_prefix(data, JPEG_EXIF_PREFIX).unwrap_or_else(|| data.clone())),
+            _ => image.exif(),
+        };
+
+        Self {
+            icc: image.icc_profile().filter(|_| options.icc),
+            exif: exif.filter(|_| options.exif),
+            xmp: xmp.filter(|_| options.xmp),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.icc.is_none() && self.exif.is_none() && self.xmp.is_none()
+    }
+
+    /// Updates the pixel dimensions recorded in the EXIF and XMP data, and
+    /// resets their orientation if the pixels have been turned `upright`.
+    fn update(&mut self, (width, height): (u32, u32), upright: bool) {
+        let mut ifd0 = vec![(TAG_IMAGE_WIDTH, width), (TAG_IMAGE_LENGTH, height)];
+        let mut properties = vec![
+            ("exif:PixelXDimension", width),
+            ("exif:PixelYDimension", height),
+            ("tiff:ImageWidth", width),
+            ("tiff:ImageLength", height),
+        ];
+
+        if upright {
+            ifd0.push((TAG_ORIENTATION, 1));
+            properties.push(("tiff:Orientation", 1));
+        }
+
+        if let Some(exif) = &self.exif {
+            let mut patched = exif.to_vec();
+            let exif_ifd = [
+                (TAG_PIXEL_X_DIMENSION, width),
+                (TAG_PIXEL_Y_DIMENSION, height),
+            ];
+
+            // If the EXIF data cannot be understood, it is copied over as is.
+            if patch_exif(&mut patched, &ifd0, &exif_ifd).is_some() {
+                self.exif = Some(Bytes::from(patched));
+            }
+        }
+
+        if let Some(xmp) = &self.xmp {
+            if let Ok(xmp) = std::str::from_utf8(xmp) {
+                let patched = patch_xmp(xmp, &properties);
+                self.xmp = Some(Bytes::from(patched));
+            }
+        }
+    }
+}
+
+fn read_metadata(input: &Path, options: MetadataOptions) -> Result<Metadata, String> {
+    let source = fs::read(input).map_err(|e| e.to_string())?;
+
+    // The CLI has managed to decode the input, so a container img-parts does
+    // not understand is not an error, it simply has nothing to carry over.
+    Ok(match DynImage::from_bytes(Bytes::from(source)) {
+        Ok(Some(image)) => Metadata::read(&image, options),
+        _ => Metadata::default(),
+    })
+}
+
+/// The EXIF orientation of the image at `input`, if it is not upright.
+pub fn orientation(input: &Path) -> Option<u16> {
+    let exif = read_metadata(input, MetadataOptions::ALL).ok()?.exif?;
+    let mut exif = exif.to_vec();
+    let tiff = Tiff::new(&mut exif)?;
+
+    tiff.find(tiff.u32(4)? as usize, TAG_ORIENTATION)
+        .and_then(|orientation| u16::try_from(orientation).ok())
+        .filter(|orientation| (2..=8).contains(orientation))
+}
+
+/// Copies the metadata selected in `options` from the file at `input` into
+/// `encoded`, which has just been encoded at `dimensions`, and turned
+/// `upright` if the input has an EXIF orientation.
+///
+/// Inputs in a container without metadata support are left alone.
+pub fn carry_over(
+    input: &Path,
+    encoded: Vec<u8>,
+    dimensions: (u32, u32),
+    upright: bool,
+    options: MetadataOptions,
+) -> Result<Vec<u8>, String> {
+    let mut metadata = read_metadata(input, options)?;
+
+    if metadata.is_empty() {
+        return Ok(encoded);
+    }
+
+    metadata.update(dimensions, upright);
+
+    let output = DynImage::from_bytes(Bytes::from(encoded))
+        .map_err(|e| format!("Unable to add metadata to the output: {}", e))?
+        .ok_or_else(|| String::from("Unable to add metadata to the output."))?;
+
+    let bytes = match output {
+        DynImage::Jpeg(jpeg) => write_jpeg(jpeg, metadata).encoder().bytes(),
+        DynImage::Png(png) => write_png(png, metadata).encoder().bytes(),
+        DynImage::WebP(webp) => write_webp(webp, metadata)?.encoder().bytes(),
+    };
+
+    Ok(bytes.to_vec())
+}
+
+fn write_jpeg(mut jpeg: Jpeg, metadata: Metadata) -> Jpeg {
+    let mut segments = Vec::new();
+
+    if let Some(exif) = metadata.exif {
+        if JPEG_EXIF_PREFIX.len() + exif.len() <= JPEG_SEGMENT_MAX_SIZE {
+            segments.push(JpegSegment::new_with_contents(
+                JPEG_APP1,
+                Bytes::from([JPEG_EXIF_PREFIX, &exif].concat()),
+            ));
+        }
+    }
+
+    // Extended XMP, spread over several segments, is not supported.
+    if let Some(xmp) = metadata.xmp {
+        if JPEG_XMP_PREFIX.len() + xmp.len() <= JPEG_SEGMENT_MAX_SIZE {
+            segments.push(JpegSegment::new_with_contents(
+                JPEG_APP1,
+                Bytes::from([JPEG_XMP_PREFIX, &xmp].concat()),
+            ));
+        }
+    }
+
+    if let Some(icc) = metadata.icc {
+        // Each segment also holds its sequence number and the segment count.
+        let chunk_size = JPEG_SEGMENT_MAX_SIZE - JPEG_ICC_PREFIX.len() - 2;
+        let chunks = icc.chunks(chunk_size);
+        let count = chunks.len();
+
+        if count <= u8::MAX as usize {
+            for (i, chunk) in chunks.enumerate() {
+                let header = [(i + 1) as u8, count as u8];
+                segments.push(JpegSegment::new_with_contents(
+                    JPEG_APP2,
+                    Bytes::from([JPEG_ICC_PREFIX, &header, chunk].concat()),
+                ));
+            }
+        }
+    }
+
+    jpeg.set_exif(None);
+    jpeg.set_icc_profile(None);
+    jpeg.segments_mut().retain(|segment| {
+        !(segment.marker() == JPEG_APP1 && segment.contents().starts_with(JPEG_XMP_PREFIX))
+    });
+
+    // Right after the JFIF header, where readers expect them.
+    let position = jpeg
+        .segments()
+        .iter()
+        .position(|segment| !(0xE0..=0xEF).contains(&segment.marker()))
+        .unwrap_or(jpeg.segments().len());
+
+    jpeg.segments_mut().splice(position..position, segments);
+    jpeg
+}
+
+fn write_png(mut png: Png, metadata: Metadata) -> Png {
+    // A PNG may not carry both an sRGB chunk and an ICC profile.
+    if metadata.icc.is_some() {
+        png.remove_chunks_by_type(PNG_SRGB);
+    }
+
+    png.set_icc_profile(metadata.icc);
+    png.set_exif(metadata.exif);
+
+    if let Some(xmp) = metadata.xmp {
+        png.chunks_mut()
+            .retain(|chunk| !(chunk.kind() == PNG_ITXT && png_xmp(chunk.contents()).is_some()));
+
+        // Uncompressed, with empty language and translated keyword fields.
+        let contents = [PNG_XMP_KEYWORD, &[0, 0, 0, 0], &xmp].concat();
+        let position = png
+            .chunks()
+            .iter()
+            .position(|chunk| chunk.kind() == PNG_IDAT)
+            .unwrap_or(1);
+
+        png.chunks_mut()
+            .insert(position, PngChunk::new(PNG_ITXT, Bytes::from(contents)));
+    }
+
+    png
+}
+
+/// img-parts neither knows about XMP in WebP nor keeps the flags of an
+/// extended WebP up to date, so the chunks are laid out here instead.
+fn write_webp(webp: WebP, metadata: Metadata) -> Result<WebP, String> {
+    let (width, height) = webp_dimensions(&webp)
+        .ok_or_else(|| String::from("Unable to read the dimensions of the WebP output."))?;
+
+    let has_alpha = webp.chunk_by_id(WEBP_VP8X).map_or_else(
+        || {
+            webp.has_chunk(WEBP_ALPH)
+                || webp
+                    .chunk_by_id(WEBP_VP8L)
+                    .and_then(|chunk| chunk.content().data()?.get(4).copied())
+                    .is_some_and(|header| header & 0x10 != 0)
+        },
+        |vp8x| {
+            vp8x.content()
+                .data()
+                .and_then(|data| data.first())
+                .is_some_and(|flags| flags & 0x10 != 0)
+        },
+    );
+
+    let image_chunks = webp
+        .chunks()
+        .iter()
+        .filter(|chunk| ![WEBP_VP8X, WEBP_ICCP, WEBP_EXIF, WEBP_XMP].contains(&chunk.id()))
+        .cloned();
+
+    let mut flags = 0u8;
+    let mut chunks = Vec::new();
+
+    if let Some(icc) = metadata.icc {
+        flags |= 0x20;
+        chunks.push(RiffChunk::new(WEBP_ICCP, RiffContent::Data(icc)));
+    }
+
+    if has_alpha {
+        flags |= 0x10;
+    }
+
+    if webp.has_chunk(WEBP_ANIM) {
+        flags |= 0x02;
+    }
+
+    chunks.extend(image_chunks);
+
+    if let Some(exif) = metadata.exif {
+        flags |= 0x08;
+        chunks.push(RiffChunk::new(WEBP_EXIF, RiffContent::Data(exif)));
+    }
+
+    if let Some(xmp) = metadata.xmp {
+        flags |= 0x04;
+        chunks.push(RiffChunk::new(WEBP_XMP, RiffContent::Data(xmp)));
+    }
+
+    let mut vp8x = vec![flags, 0, 0, 0];
+    vp8x.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+    vp8x.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+    chunks.insert(
+        0,
+        RiffChunk::new(WEBP_VP8X, RiffContent::Data(Bytes::from(vp8x))),
+    );
+
+    let riff = RiffChunk::new(
+        *b"RIFF",
+        RiffContent::List {
+            kind: Some(*b"WEBP"),
+            subchunks: chunks,
+        },
+    );
+
+    WebP::new(riff).map_err(|e| e.to_string())
+}
+
+/// The canvas size of `webp`. img-parts reads the wrong bytes of the VP8X
+/// header, so only simple WebPs are left to it.
+pub fn webp_dimensions(webp: &WebP) -> Option<(u32, u32)> {
+    let Some(vp8x) = webp.chunk_by_id(WEBP_VP8X) else {
+        return webp.dimensions();
+    };
+
+    let data = vp8x.content().data()?.get(4..10)?;
+    let u24 = |at: usize| u32::from_le_bytes([data[at], data[at + 1], data[at + 2], 0]);
+
+    Some((u24(0) + 1, u24(3) + 1))
+}
+
+fn strip_prefix(contents: &Bytes, prefix: &[u8]) -> Option<Bytes> {
+    contents
+        .starts_with(prefix)
+        .then(|| contents.slice(prefix.len()..))
+}
+
+/// Returns the XMP packet in a PNG iTXt chunk, if it holds an uncompressed
+/// one.
+fn png_xmp(contents: &Bytes) -> Option<Bytes> {
+    let rest = strip_prefix(contents, PNG_XMP_KEYWORD)?;
+
+    // Compression flag and method.
+    if rest.first() != Some(&0) {
+        return None;
+    }
+
+    // Skip the language tag and the translated keyword.
+    let mut offset = 2;
+    for _ in 0..2 {
+        offset += rest.get(offset..)?.iter().position(|&b| b == 0)? + 1;
+    }
+
+    Some(rest.slice(offset..))
+}
+
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+
+/// A TIFF structure, as EXIF data is stored.
+struct Tiff<'a> {
+    data: &'a mut [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a mut [u8]) -> Option<Self> {
+        let big_endian = match data.get(0..2)? {
+            b"II" => false,
+            b"MM" => true,
+            _ => return None,
+        };
+
+        Some(Tiff { data, big_endian })
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes = self.data.get(at..at + 2)?.try_into().ok()?;
+
+        Some(match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let bytes = self.data.get(at..at + 4)?.try_into().ok()?;
+
+        Some(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    fn write(&mut self, at: usize, bytes: &[u8]) -> Option<()> {
+        self.data
+            .get_mut(at..at + bytes.len())?
+            .copy_from_slice(bytes);
+        Some(())
+    }
+
+    /// The value of `tag` in the IFD at `ifd`, if it is a single SHORT or
+    /// LONG.
+    fn find(&self, ifd: usize, tag: u16) -> Option<u32> {
+        let count = self.u16(ifd)? as usize;
+
+        (0..count).map(|i| ifd + 2 + i * 12).find_map(|entry| {
+            if self.u16(entry)? != tag || self.u32(entry + 4)? != 1 {
+                return None;
+            }
+
+            match self.u16(entry + 2)? {
+                SHORT => self.u16(entry + 8).map(u32::from),
+                LONG => self.u32(entry + 8),
+                _ => None,
+            }
+        })
+    }
+
+    /// Overwrites the values of the given tags in the IFD at `ifd`, and
+    /// returns the offset of the Exif IFD if this IFD points to one.
+    fn patch_ifd(&mut self, ifd: usize, tags: &[(u16, u32)]) -> Option<Option<usize>> {
+        let count = self.u16(ifd)? as usize;
+        let mut exif_ifd = None;
+
+        for i in 0..count {
+            let entry = ifd + 2 + i * 12;
+            let tag = self.u16(entry)?;
+
+            if tag == TAG_EXIF_IFD_POINTER {
+                exif_ifd = Some(self.u32(entry + 8)? as usize);
+                continue;
+            }
+
+            let Some(&(_, value)) = tags.iter().find(|(t, _)| *t == tag) else {
+                continue;
+            };
+
+            if self.u32(entry + 4)? != 1 || !matches!(self.u16(entry + 2)?, SHORT | LONG) {
+                continue;
+            }
+
+            // Both types fit in the entry itself, so a SHORT that is too small
+            // for the new value can simply become a LONG.
+            let (kind, value) = match u16::try_from(value) {
+                Ok(short) if self.u16(entry + 2)? == SHORT => {
+                    let mut bytes = [0; 4];
+                    bytes[..2].copy_from_slice(&match self.big_endian {
+                        true => short.to_be_bytes(),
+                        false => short.to_le_bytes(),
+                    });
+                    (SHORT, bytes)
+                }
+                _ => (
+                    LONG,
+                    match self.big_endian {
+                        true => value.to_be_bytes(),
+                        false => value.to_le_bytes(),
+                    },
+                ),
+            };
+
+            let kind = match self.big_endian {
+                true => kind.to_be_bytes(),
+                false => kind.to_le_bytes(),
+            };
+
+            self.write(entry + 2, &kind)?;
+            self.write(entry + 8, &value)?;
+        }
+
+        Some(exif_ifd)
+    }
+}
+
+/// Sets the given tags of IFD0 and of the Exif IFD, wherever they are
+/// present, and drops IFD1, as the thumbnail it holds no longer matches the
+/// image. Returns `None` if the data is malformed.
+fn patch_exif(exif: &mut [u8], ifd0_tags: &[(u16, u32)], exif_tags: &[(u16, u32)]) -> Option<()> {
+    let mut tiff = Tiff::new(exif)?;
+
+    let ifd0 = tiff.u32(4)? as usize;
+    let exif_ifd = tiff.patch_ifd(ifd0, ifd0_tags)?;
+
+    if let Some(exif_ifd) = exif_ifd {
+        tiff.patch_ifd(exif_ifd, exif_tags)?;
+    }
+
+    // The thumbnail is left in the data, but nothing points to it anymore.
+    let next_ifd = ifd0 + 2 + tiff.u16(ifd0)? as usize * 12;
+    tiff.write(next_ifd, &[0; 4])
+}
+
+/// Sets the given properties of an XMP packet, whether they are written as
+/// attributes or as elements.
+fn patch_xmp(xmp: &str, properties: &[(&str, u32)]) -> String {
+    let mut xmp = xmp.to_owned();
+
+    for &(name, value) in properties {
+        let value = value.to_string();
+
+        for (open, close) in [
+            (format!("{}=\"", name), String::from("\"")),
+            (format!("{}='", name), String::from("'")),
+            (format!("<{}>", name), format!("</{}>", name)),
+        ] {
+            xmp = replace_between(&xmp, &open, &close, &value);
+        }
+    }
+
+    xmp
+}
+
+fn replace_between(text: &str, open: &str, close: &str, value: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(open) {
+        let after_open = start + open.len();
+
+        let Some(end) = rest[after_open..].find(close) else {
+            break;
+        };
+
+        result.push_str(&rest[..after_open]);
+        result.push_str(value);
+        rest = &rest[after_open + end..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// EXIF data with the size and orientation in IFD0, the pixel dimensions
+    /// in the Exif IFD at 62 and an empty IFD1 at 92, as for a thumbnail.
+    fn exif(big_endian: bool) -> Vec<u8> {
+        let u16 = |value: u16| match big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        };
+        let u32 = |value: u32| match big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        };
+        let entry = |tag: u16, kind: u16, value: u32| {
+            let value = match kind {
+                SHORT => [u16(value as u16), [0, 0]].concat(),
+                _ => u32(value).to_vec(),
+            };
+            [&u16(tag)[..], &u16(kind), &u32(1), &value].concat()
+        };
+
+        [
+            match big_endian {
+                true => b"MM".to_vec(),
+                false => b"II".to_vec(),
+            },
+            u16(42).to_vec(),
+            u32(8).to_vec(),
+            u16(4).to_vec(),
+            entry(TAG_IMAGE_WIDTH, SHORT, 100),
+            entry(TAG_IMAGE_LENGTH, LONG, 80),
+            entry(TAG_ORIENTATION, SHORT, 6),
+            entry(TAG_EXIF_IFD_POINTER, LONG, 62),
+            u32(92).to_vec(),
+            u16(2).to_vec(),
+            entry(TAG_PIXEL_X_DIMENSION, SHORT, 100),
+            entry(TAG_PIXEL_Y_DIMENSION, SHORT, 80),
+            u32(0).to_vec(),
+            u16(0).to_vec(),
+            u32(0).to_vec(),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn patches_exif_in_either_byte_order() {
+        for big_endian in [false, true] {
+            let mut data = exif(big_endian);
+            let patched = patch_exif(
+                &mut data,
+                &[
+                    (TAG_IMAGE_WIDTH, 70000),
+                    (TAG_IMAGE_LENGTH, 300),
+                    (TAG_ORIENTATION, 1),
+                ],
+                &[(TAG_PIXEL_X_DIMENSION, 70000), (TAG_PIXEL_Y_DIMENSION, 300)],
+            );
+            assert_eq!(patched, Some(()));
+
+            let tiff = Tiff::new(&mut data).unwrap();
+            assert_eq!(tiff.find(8, TAG_IMAGE_WIDTH), Some(70000));
+            assert_eq!(tiff.find(8, TAG_IMAGE_LENGTH), Some(300));
+            assert_eq!(tiff.find(8, TAG_ORIENTATION), Some(1));
+            assert_eq!(tiff.find(62, TAG_PIXEL_X_DIMENSION), Some(70000));
+            assert_eq!(tiff.find(62, TAG_PIXEL_Y_DIMENSION), Some(300));
+
+            // Too large for a SHORT, so it has become a LONG.
+            assert_eq!(tiff.u16(10 + 2), Some(LONG));
+            assert_eq!(tiff.u16(64 + 2), Some(LONG));
+            // The small one stays a SHORT.
+            assert_eq!(tiff.u16(76 + 2), Some(SHORT));
+
+            // IFD1 is unlinked.
+            assert_eq!(tiff.u32(58), Some(0));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_exif() {
+        assert_eq!(patch_exif(&mut b"XX\0*".to_vec(), &[], &[]), None);
+
+        let mut truncated = exif(false);
+        truncated.truncate(40);
+        assert_eq!(
+            patch_exif(&mut truncated, &[(TAG_IMAGE_WIDTH, 1)], &[]),
+            None
+        );
+    }
+
+    #[test]
+    fn patches_xmp_attributes_and_elements() {
+        let xmp = concat!(
+            r#"<rdf:Description exif:PixelXDimension="100" exif:PixelYDimension='80'>"#,
+            "<tiff:ImageWidth>100</tiff:ImageWidth>",
+            "<tiff:Orientation>6</tiff:Orientation>",
+            "<tiff:ImageLengthUnit>px</tiff:ImageLengthUnit>",
+            "</rdf:Description>",
+        );
+
+        let patched = patch_xmp(
+            xmp,
+            &[
+                ("exif:PixelXDimension", 400),
+                ("exif:PixelYDimension", 320),
+                ("tiff:ImageWidth", 400),
+                ("tiff:ImageLength", 320),
+                ("tiff:Orientation", 1),
+            ],
+        );
+
+        assert_eq!(
+            patched,
+            concat!(
+                r#"<rdf:Description exif:PixelXDimension="400" exif:PixelYDimension='320'>"#,
+                "<tiff:ImageWidth>400</tiff:ImageWidth>",
+                "<tiff:Orientation>1</tiff:Orientation>",
+                "<tiff:ImageLengthUnit>px</tiff:ImageLengthUnit>",
+                "</rdf:Description>",
+            )
+        );
+    }
+
+    fn webp(chunks: Vec<RiffChunk>) -> WebP {
+        WebP::new(RiffChunk::new(
+            *b"RIFF",
+            RiffContent::List {
+                kind: Some(*b"WEBP"),
+                subchunks: chunks,
+            },
+        ))
+        .unwrap()
+    }
+
+    fn data(bytes: &[u8]) -> RiffContent {
+        RiffContent::Data(Bytes::copy_from_slice(bytes))
+    }
+
+    #[test]
+    fn rebuilds_vp8x_of_simple_webp() {
+        // A lossless 3x2 image with alpha: the width and height, less one, in
+        // 14 bits each, then the alpha bit.
+        let header = (2u32 | (1 << 14) | (1 << 28)).to_le_bytes();
+        let vp8l = [&[0x2F], &header[..], &[0; 4]].concat();
+        let metadata = Metadata {
+            icc: Some(Bytes::from_static(b"icc")),
+            exif: Some(Bytes::from_static(b"exif")),
+            xmp: Some(Bytes::from_static(b"xmp")),
+        };
+
+        let output =
+            write_webp(webp(vec![RiffChunk::new(WEBP_VP8L, data(&vp8l))]), metadata).unwrap();
+        let ids = output
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.id())
+            .collect::<Vec<_>>();
+
+        assert_eq!(ids, [WEBP_VP8X, WEBP_ICCP, WEBP_VP8L, WEBP_EXIF, WEBP_XMP]);
+        assert_eq!(webp_dimensions(&output), Some((3, 2)));
+
+        let vp8x = output
+            .chunk_by_id(WEBP_VP8X)
+            .unwrap()
+            .content()
+            .data()
+            .unwrap()
+            .clone();
+        assert_eq!(vp8x[0], 0x20 | 0x10 | 0x08 | 0x04);
+        assert_eq!(vp8x.len(), 10);
+    }
+
+    #[test]
+    fn rebuilds_vp8x_of_extended_webp() {
+        // 300x200 with alpha, and an ICC profile that is dropped.
+        let vp8x = [0x30, 0, 0, 0, 0x2B, 0x01, 0, 0xC7, 0, 0];
+        let input = webp(vec![
+            RiffChunk::new(WEBP_VP8X, data(&vp8x)),
+            RiffChunk::new(WEBP_ICCP, data(b"old")),
+            RiffChunk::new(WEBP_ALPH, data(&[0])),
+            RiffChunk::new(*b"VP8 ", data(&[0; 10])),
+        ]);
+        let metadata = Metadata {
+            exif: Some(Bytes::from_static(b"exif")),
+            ..Metadata::default()
+        };
+
+        let output = write_webp(input, metadata).unwrap();
+        let ids = output
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.id())
+            .collect::<Vec<_>>();
+
+        assert_eq!(ids, [WEBP_VP8X, WEBP_ALPH, *b"VP8 ", WEBP_EXIF]);
+        assert_eq!(webp_dimensions(&output), Some((300, 200)));
+
+        let vp8x = output
+            .chunk_by_id(WEBP_VP8X)
+            .unwrap()
+            .content()
+            .data()
+            .unwrap()
+            .clone();
+        assert_eq!(vp8x[0], 0x10 | 0x08);
+    }
+}
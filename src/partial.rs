@@ -0,0 +1,200 @@
+//! Outputs are written under a temporary name next to their final path, and
+//! are only renamed into place once the CLI has exited successfully and the
+//! result has been verified. This keeps an interrupted job from leaving a
+//! truncated image behind that looks finished.
+
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Inserted between the file stem and the extension of a partial output. The
+/// extension itself is kept, as the CLI picks the output format from it.
+const PARTIAL_MARKER: &str = ".partial";
+
+/// Every partial output handed to the CLI is recorded here until its job is
+/// done, so that the ones left over by a crash can be found again on the
+/// next launch.
+const JOURNAL_FILE: &str = "realesrgan-ncnn-vulkan-gui.partial";
+
+pub fn partial_path(output_path: &Path) -> PathBuf {
+    let mut file_name = OsString::from(output_path.file_stem().unwrap_or_default());
+    file_name.push(PARTIAL_MARKER);
+
+    if let Some(ext) = output_path.extension() {
+        file_name.push(".");
+        file_name.push(ext);
+    }
+
+    output_path.with_file_name(file_name)
+}
+
+/// Where the lossless output of realesrgan pass `pass` is kept, when the job
+/// needs more passes or post-processing before it is written to
+/// `output_path`.
+pub fn intermediate_path(output_path: &Path, pass: u32) -> PathBuf {
+    let mut file_name = OsString::from(output_path.file_stem().unwrap_or_default());
+    file_name.push(format!("{}-{}.png", PARTIAL_MARKER, pass));
+
+    output_path.with_file_name(file_name)
+}
+
+/// Where an input the CLI cannot read, or the cropped region of an input, is
+/// written to before the first pass.
+pub fn converted_input_path(output_path: &Path) -> PathBuf {
+    let mut file_name = OsString::from(output_path.file_stem().unwrap_or_default());
+    file_name.push(format!("{}-input.png", PARTIAL_MARKER));
+
+    output_path.with_file_name(file_name)
+}
+
+/// Where the frames of an animated input are kept while they are upscaled.
+pub fn frames_dir(output_path: &Path) -> PathBuf {
+    let mut file_name = OsString::from(output_path.file_stem().unwrap_or_default());
+    file_name.push(format!("{}-frames", PARTIAL_MARKER));
+
+    output_path.with_file_name(file_name)
+}
+
+/// Whether `path` is a partial output, or one of the files or folders kept
+/// next to it while a job runs.
+pub fn is_temporary(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().contains(PARTIAL_MARKER))
+}
+
+pub fn record(partial_path: &Path) -> io::Result<()> {
+    let mut journal = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(JOURNAL_FILE)?;
+
+    writeln!(journal, "{}", partial_path.to_string_lossy())
+}
+
+/// Takes `paths` off the journal, once they have been moved into place or
+/// removed.
+pub fn forget(paths: &[PathBuf]) -> io::Result<()> {
+    let journal = match fs::read_to_string(JOURNAL_FILE) {
+        Ok(journal) => journal,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let kept = journal
+        .lines()
+        .filter(|line| !line.is_empty() && !paths.iter().any(|path| path.as_os_str() == *line))
+        .map(|line| format!("{}\n", line))
+        .collect::<String>();
+
+    if kept.is_empty() {
+        fs::remove_file(JOURNAL_FILE)
+    } else {
+        fs::write(JOURNAL_FILE, kept)
+    }
+}
+
+/// Verifies the partial output by decoding all of it, as a truncated image
+/// may still have an intact header, and moves it to its final path.
+pub fn finish(partial_path: &Path, output_path: &Path) -> Result<(), String> {
+    let verified = image::io::Reader::open(partial_path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| e.to_string())
+        .and_then(|reader| reader.decode().map_err(|e| e.to_string()))
+        .map(|image| (image.width(), image.height()));
+
+    finish_verified(partial_path, output_path, verified)
+}
+
+/// Moves the partial output to its final path, given the dimensions the
+/// caller has read from it to verify it.
+pub fn finish_verified(
+    partial_path: &Path,
+    output_path: &Path,
+    verified: Result<(u32, u32), String>,
+) -> Result<(), String> {
+    match verified {
+        Ok((width, height)) if width > 0 && height > 0 => {
+            fs::rename(partial_path, output_path).map_err(|e| e.to_string())
+        }
+        Ok(_) => {
+            discard(partial_path);
+            Err(String::from("the output image is empty"))
+        }
+        Err(e) => {
+            discard(partial_path);
+            Err(format!("the output image is invalid: {}", e))
+        }
+    }
+}
+
+pub fn discard(partial_path: &Path) {
+    let _ = remove(partial_path);
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Deletes the partial outputs left over from previous sessions, returning
+/// how many were found.
+pub fn clean_up_leftovers() -> usize {
+    let Ok(journal) = fs::read_to_string(JOURNAL_FILE) else {
+        return 0;
+    };
+
+    let removed = journal
+        .lines()
+        .filter(|line| !line.is_empty())
+        .filter(|line| remove(Path::new(line)).is_ok())
+        .count();
+
+    let _ = fs::remove_file(JOURNAL_FILE);
+
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{ImageFormat, Rgb, RgbImage};
+
+    #[test]
+    fn moves_only_complete_outputs_into_place() {
+        let dir = std::env::temp_dir().join(format!("upscaler-partial-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let image = RgbImage::from_fn(64, 64, |x, y| {
+            Rgb([(x * 7 + y * 13) as u8, (x * y) as u8, (x ^ y) as u8])
+        });
+        let finished = [ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::WebP].map(|format| {
+            let output = dir.join(format!("out.{}", format.extensions_str()[0]));
+            let partial = partial_path(&output);
+            image.save_with_format(&partial, format).unwrap();
+
+            let complete = finish(&partial, &output).map(|()| output.exists());
+
+            // Cut short, as by a job that died halfway.
+            image.save_with_format(&partial, format).unwrap();
+            let encoded = fs::read(&partial).unwrap();
+            fs::write(&partial, &encoded[..encoded.len() / 2]).unwrap();
+            let _ = fs::remove_file(&output);
+
+            let truncated = finish(&partial, &output);
+
+            (complete, truncated, partial.exists() || output.exists())
+        });
+        let _ = fs::remove_dir_all(&dir);
+
+        for (complete, truncated, left_behind) in finished {
+            assert_eq!(complete, Ok(true));
+            assert!(truncated.is_err());
+            assert!(!left_behind);
+        }
+    }
+}
@@ -0,0 +1,377 @@
+//! Work done in Rust on the image written by the CLI, before it is moved to
+//! its final path.
+
+use std::fmt;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::animation::Animation;
+use crate::encode::Encoding;
+use crate::metadata::{self, MetadataOptions};
+
+/// How the upscaled image is fitted to the size set on the Output page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FitMode {
+    #[default]
+    None,
+    Box,
+    Cover,
+    Width,
+    Height,
+    PadToAspect,
+}
+
+impl FitMode {
+    pub const ALL: [FitMode; 6] = [
+        FitMode::None,
+        FitMode::Box,
+        FitMode::Cover,
+        FitMode::Width,
+        FitMode::Height,
+        FitMode::PadToAspect,
+    ];
+
+    /// Parses the values typed next to the mode. For `PadToAspect`, `width`
+    /// and `height` are the two sides of the aspect ratio.
+    pub fn parse(self, width: &str, height: &str, color: &str) -> Result<Option<Fit>, String> {
+        let length = |value: &str, name| {
+            let value = value.trim();
+            value
+                .parse::<u32>()
+                .ok()
+                .filter(|&length| length > 0)
+                .ok_or_else(|| format!("\"{}\" is not a valid {}.", value, name))
+        };
+
+        Ok(Some(match self {
+            FitMode::None => return Ok(None),
+            FitMode::Box => Fit::Box(length(width, "width")?, length(height, "height")?),
+            FitMode::Cover => Fit::Cover(length(width, "width")?, length(height, "height")?),
+            FitMode::Width => Fit::Width(length(width, "width")?),
+            FitMode::Height => Fit::Height(length(height, "height")?),
+            FitMode::PadToAspect => Fit::PadToAspect {
+                aspect: (
+                    length(width, "aspect ratio")?,
+                    length(height, "aspect ratio")?,
+                ),
+                color: parse_color(color)?,
+            },
+        }))
+    }
+}
+
+impl fmt::Display for FitMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FitMode::None => "Keep size",
+            FitMode::Box => "Fit inside box",
+            FitMode::Cover => "Fill and crop",
+            FitMode::Width => "Fixed width",
+            FitMode::Height => "Fixed height",
+            FitMode::PadToAspect => "Pad to aspect ratio",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fit {
+    /// Shrink or grow the image until it just fits inside the box.
+    Box(u32, u32),
+    /// Fill the box entirely, cropping whatever overflows around the centre.
+    Cover(u32, u32),
+    Width(u32),
+    Height(u32),
+    /// Extend the canvas around the centred image until it has this aspect
+    /// ratio, filling the new area with `color`.
+    PadToAspect {
+        aspect: (u32, u32),
+        color: [u8; 4],
+    },
+}
+
+impl Fit {
+    fn apply(self, image: DynamicImage) -> DynamicImage {
+        let (width, height) = (image.width(), image.height());
+
+        match self {
+            Fit::Box(w, h) => image.resize(w, h, FilterType::Lanczos3),
+            Fit::Cover(w, h) => image.resize_to_fill(w, h, FilterType::Lanczos3),
+            Fit::Width(w) => {
+                let h = (height as u64 * w as u64 / width as u64).max(1) as u32;
+                image.resize_exact(w, h, FilterType::Lanczos3)
+            }
+            Fit::Height(h) => {
+                let w = (width as u64 * h as u64 / height as u64).max(1) as u32;
+                image.resize_exact(w, h, FilterType::Lanczos3)
+            }
+            Fit::PadToAspect {
+                aspect: (aspect_w, aspect_h),
+                color,
+            } => {
+                // Only ever grow one side, so nothing of the image is lost.
+                let (canvas_w, canvas_h) =
+                    if width as u64 * aspect_h as u64 > height as u64 * aspect_w as u64 {
+                        let h = (width as u64 * aspect_h as u64 / aspect_w as u64) as u32;
+                        (width, h.max(height))
+                    } else {
+                        let w = (height as u64 * aspect_w as u64 / aspect_h as u64) as u32;
+                        (w.max(width), height)
+                    };
+
+                if (canvas_w, canvas_h) == (width, height) {
+                    return image;
+                }
+
+                let mut canvas = RgbaImage::from_pixel(canvas_w, canvas_h, Rgba(color));
+                let x = (canvas_w - width) / 2;
+                let y = (canvas_h - height) / 2;
+                imageops::overlay(&mut canvas, &image.to_rgba8(), x as i64, y as i64);
+
+                DynamicImage::ImageRgba8(canvas)
+            }
+        }
+    }
+}
+
+/// Parses `#RRGGBB` or `#RRGGBBAA`, with or without the `#`.
+pub fn parse_color(color: &str) -> Result<[u8; 4], String> {
+    let invalid = || format!("\"{}\" is not a valid colour, use #RRGGBB.", color);
+    let hex = color.trim().trim_start_matches('#');
+
+    // from_str_radix() would take a sign too.
+    if !matches!(hex.len(), 6 | 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let mut rgba = [255; 4];
+
+    for (i, channel) in rgba.iter_mut().enumerate().take(hex.len() / 2) {
+        *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+
+    Ok(rgba)
+}
+
+/// The post-processing a job needs after its last realesrgan pass.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PostProcess {
+    /// Resample the upscaled image to exactly this size.
+    pub output_size: Option<(u32, u32)>,
+    /// Then fit it to the size set on the Output page.
+    pub fit: Option<Fit>,
+    pub encoding: Encoding,
+    pub metadata: MetadataOptions,
+}
+
+impl PostProcess {
+    /// Whether the CLI has to write a lossless intermediate for this to work
+    /// on, instead of writing the output directly.
+    pub fn is_needed(&self) -> bool {
+        self.needs_decoding() || self.metadata.any()
+    }
+
+    /// Whether the CLI's output has to be decoded, rather than only having
+    /// metadata added to it.
+    fn needs_decoding(&self) -> bool {
+        self.resizes() || !self.encoding.is_native()
+    }
+
+    /// Reads the CLI's output from `upscaled` and writes the final image to
+    /// `output`, with the metadata of `input` if requested.
+    pub fn run(&self, input: &Path, upscaled: &Path, output: &Path) -> Result<(), String> {
+        // Neither the CLI nor the decoder here heed the EXIF orientation, so
+        // the image is turned upright before it is fitted, when it is decoded
+        // anyway.
+        let orientation = metadata::orientation(input).filter(|_| self.turns_upright());
+
+        let (mut encoded, dimensions) = if self.needs_decoding() {
+            let image = self.process(upscaled, orientation)?;
+            (
+                self.encoding.encode(&image)?,
+                (image.width(), image.height()),
+            )
+        } else {
+            let encoded = fs::read(upscaled).map_err(|e| e.to_string())?;
+            let dimensions = image::io::Reader::new(Cursor::new(&encoded))
+                .with_guessed_format()
+                .map_err(|e| e.to_string())?
+                .into_dimensions()
+                .map_err(|e| e.to_string())?;
+            (encoded, dimensions)
+        };
+
+        if self.metadata.any() {
+            encoded = metadata::carry_over(
+                input,
+                encoded,
+                dimensions,
+                orientation.is_some(),
+                self.metadata,
+            )?;
+        }
+
+        fs::write(output, encoded).map_err(|e| e.to_string())
+    }
+
+    /// Whether a still image is turned upright for its EXIF orientation.
+    pub fn turns_upright(&self) -> bool {
+        self.needs_decoding()
+    }
+
+    /// Whether the upscaled image is resized at all.
+    pub fn resizes(&self) -> bool {
+        self.output_size.is_some() || self.fit.is_some()
+    }
+
+    /// Resizes the upscaled page of an archive and encodes it, without any
+    /// metadata.
+    pub fn render(&self, upscaled: &Path) -> Result<Vec<u8>, String> {
+        self.encoding.encode(&self.process(upscaled, None)?)
+    }
+
+    /// Resizes the upscaled frame of a video, which is written as a PNG for
+    /// ffmpeg to encode.
+    pub fn run_frame(&self, upscaled: &Path, output: &Path) -> Result<(), String> {
+        self.process(upscaled, None)?
+            .save_with_format(output, image::ImageFormat::Png)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Reads the upscaled frames of `animation` from `frames`, in order, and
+    /// writes the reassembled animation to `output`.
+    pub fn run_animation(
+        &self,
+        input: &Path,
+        animation: &Animation,
+        frames: &[PathBuf],
+        output: &Path,
+    ) -> Result<(), String> {
+        let mut dimensions = (0, 0);
+
+        let frames = frames.iter().map(|frame| {
+            let image = self.process(frame, None)?.to_rgba8();
+            dimensions = image.dimensions();
+            Ok(image)
+        });
+
+        let mut encoded = animation.encode(frames, self.encoding)?;
+
+        if self.metadata.any() {
+            encoded = metadata::carry_over(input, encoded, dimensions, false, self.metadata)?;
+        }
+
+        fs::write(output, encoded).map_err(|e| e.to_string())
+    }
+
+    /// Resizes the image at `upscaled`, turns it upright for an EXIF
+    /// `orientation`, and then fits it.
+    fn process(&self, upscaled: &Path, orientation: Option<u16>) -> Result<DynamicImage, String> {
+        let mut image = image::open(upscaled).map_err(|e| e.to_string())?;
+
+        if let Some((width, height)) = self.output_size {
+            if (image.width(), image.height()) != (width, height) {
+                image = image.resize_exact(width, height, FilterType::Lanczos3);
+            }
+        }
+
+        if let Some(orientation) = orientation {
+            image = orient(image, orientation);
+        }
+
+        if let Some(fit) = self.fit {
+            image = fit.apply(image);
+        }
+
+        Ok(image)
+    }
+}
+
+/// Turns `image` upright for an EXIF `orientation`.
+pub fn orient(image: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sized(fit: Fit, width: u32, height: u32) -> (u32, u32) {
+        let image = DynamicImage::new_rgba8(width, height);
+        let fitted = fit.apply(image);
+        (fitted.width(), fitted.height())
+    }
+
+    #[test]
+    fn fits_images_to_the_size_set() {
+        assert_eq!(sized(Fit::Box(100, 100), 400, 200), (100, 50));
+        assert_eq!(sized(Fit::Box(100, 100), 100, 400), (25, 100));
+        assert_eq!(sized(Fit::Box(800, 800), 400, 200), (800, 400));
+        assert_eq!(sized(Fit::Cover(100, 100), 400, 200), (100, 100));
+        assert_eq!(sized(Fit::Cover(30, 60), 400, 200), (30, 60));
+        assert_eq!(sized(Fit::Width(100), 400, 200), (100, 50));
+        assert_eq!(sized(Fit::Width(100), 1000, 1), (100, 1));
+        assert_eq!(sized(Fit::Height(100), 400, 200), (200, 100));
+    }
+
+    #[test]
+    fn pads_images_to_the_aspect_ratio_around_the_centre() {
+        const RED: [u8; 4] = [255, 0, 0, 255];
+        let pad = |width, height, aspect| {
+            let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba(RED)));
+            Fit::PadToAspect {
+                aspect,
+                color: [0, 0, 0, 0],
+            }
+            .apply(image)
+            .to_rgba8()
+        };
+
+        let wide = pad(40, 10, (1, 1));
+        assert_eq!(wide.dimensions(), (40, 40));
+        assert_eq!(wide[(0, 14)].0, [0; 4]);
+        assert_eq!(wide[(0, 15)].0, RED);
+        assert_eq!(wide[(39, 24)].0, RED);
+        assert_eq!(wide[(39, 25)].0, [0; 4]);
+
+        let tall = pad(10, 10, (16, 9));
+        assert_eq!(tall.dimensions(), (17, 10));
+        assert_eq!(tall[(2, 0)].0, [0; 4]);
+        assert_eq!(tall[(3, 0)].0, RED);
+        assert_eq!(tall[(12, 9)].0, RED);
+        assert_eq!(tall[(13, 9)].0, [0; 4]);
+
+        // Images that have the ratio already are left as they are.
+        assert_eq!(pad(32, 18, (16, 9)).dimensions(), (32, 18));
+    }
+
+    #[test]
+    fn parses_colours() {
+        assert_eq!(parse_color("#ff8000"), Ok([255, 128, 0, 255]));
+        assert_eq!(parse_color(" FF800080 "), Ok([255, 128, 0, 128]));
+
+        for color in [
+            "",
+            "#",
+            "#12345",
+            "#1234567",
+            "#12345g",
+            "+12345",
+            "#ff80\u{e9}",
+        ] {
+            assert!(parse_color(color).is_err(), "{}", color);
+        }
+    }
+}
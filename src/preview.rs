@@ -0,0 +1,382 @@
+//! The before/after comparison of a finished job. Both images are drawn in
+//! Rust into one picture the size of the widget showing it, so that they
+//! zoom and pan together and zoomed in pixels stay sharp.
+
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use iced::widget::image::Handle;
+use iced::{Element, Length, Point, Rectangle};
+use iced_native::layout::{self, Layout};
+use iced_native::renderer;
+use iced_native::widget::tree::{self, Tree};
+use iced_native::widget::Widget;
+use iced_native::{event, image as native_image, mouse, window, Clipboard, Event, Shell};
+use image::RgbaImage;
+
+use crate::crop::Region;
+use crate::input;
+use crate::job::JobId;
+
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 32.0;
+
+/// Shown around the image when it does not fill the viewport.
+const BACKDROP: [u8; 4] = [32, 32, 32, 255];
+const SPLIT_LINE: [u8; 4] = [255, 255, 255, 255];
+
+/// The input and the output of a job, decoded.
+pub struct Images {
+    before: RgbaImage,
+    after: RgbaImage,
+}
+
+impl Images {
+    /// Decodes the input and the output of a job. Animations are compared
+    /// by their first frame, and a cropped input by the region upscaled.
+    pub fn load(input: &Path, crop: Option<Region>, output: &Path) -> Result<Self, String> {
+        let open = |path: &Path, crop| {
+            input::decode(path, crop)
+                .map(|image| image.to_rgba8())
+                .map_err(|e| format!("{}: {}", path.to_string_lossy(), e))
+        };
+
+        Ok(Self {
+            before: open(input, crop)?,
+            after: open(output, None)?,
+        })
+    }
+}
+
+impl fmt::Debug for Images {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Images")
+            .field("before", &self.before.dimensions())
+            .field("after", &self.after.dimensions())
+            .finish()
+    }
+}
+
+/// What was done to the comparison with the mouse.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interaction {
+    /// The comparison was laid out at a new size, in pixels.
+    Resized(u32, u32),
+    /// The split line was moved, to a fraction of the width.
+    Split(f32),
+    /// The view was dragged by this many pixels.
+    Pan(f32, f32),
+    Zoom(f32),
+}
+
+#[derive(Debug)]
+pub struct Preview {
+    pub job_id: JobId,
+    images: Arc<Images>,
+    /// Where the split line is, from 0 at the left edge to 1 at the right.
+    /// The input is shown left of it and the output right of it.
+    split: f32,
+    /// Viewport pixels per output pixel.
+    zoom: f32,
+    /// The output pixel at the centre of the viewport.
+    center: (f32, f32),
+    /// The size of the widget showing the comparison, unknown until it has
+    /// been laid out.
+    viewport: (u32, u32),
+    handle: Handle,
+}
+
+impl Preview {
+    pub fn new(job_id: JobId, images: Arc<Images>) -> Self {
+        let (width, height) = images.after.dimensions();
+
+        Self {
+            job_id,
+            images,
+            split: 0.5,
+            zoom: 1.0,
+            center: (width as f32 / 2.0, height as f32 / 2.0),
+            viewport: (0, 0),
+            handle: Handle::from_pixels(0, 0, Vec::new()),
+        }
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// The sizes of the input and the output.
+    pub fn dimensions(&self) -> ((u32, u32), (u32, u32)) {
+        (
+            self.images.before.dimensions(),
+            self.images.after.dimensions(),
+        )
+    }
+
+    /// Zooms so that the whole output is in view.
+    pub fn fit(&mut self) {
+        let (width, height) = self.images.after.dimensions();
+        let (view_width, view_height) = self.viewport;
+
+        self.zoom = (view_width as f32 / width as f32)
+            .min(view_height as f32 / height as f32)
+            .clamp(MIN_ZOOM, MAX_ZOOM);
+        self.center = (width as f32 / 2.0, height as f32 / 2.0);
+        self.redraw();
+    }
+
+    /// Shows one output pixel per screen pixel, around the same centre.
+    pub fn actual_size(&mut self) {
+        self.zoom = 1.0;
+        self.redraw();
+    }
+
+    pub fn interact(&mut self, interaction: Interaction) {
+        match interaction {
+            Interaction::Resized(width, height) => {
+                let first = self.viewport == (0, 0);
+                self.viewport = (width, height);
+
+                // Shown whole at first, now that the room for it is known.
+                if first {
+                    return self.fit();
+                }
+            }
+            Interaction::Split(split) => self.split = split.clamp(0.0, 1.0),
+            Interaction::Pan(dx, dy) => {
+                let (width, height) = self.images.after.dimensions();
+                self.center = (
+                    (self.center.0 - dx / self.zoom).clamp(0.0, width as f32),
+                    (self.center.1 - dy / self.zoom).clamp(0.0, height as f32),
+                );
+            }
+            Interaction::Zoom(factor) => {
+                self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+            }
+        }
+
+        self.redraw();
+    }
+
+    fn redraw(&mut self) {
+        let (width, height) = self.viewport;
+        let (before, after) = (&self.images.before, &self.images.after);
+
+        // The input is stretched to the size of the output, sampling the
+        // nearest pixel so that its own pixels can be told apart.
+        let scale_x = before.width() as f32 / after.width() as f32;
+        let scale_y = before.height() as f32 / after.height() as f32;
+        let split_x = (self.split * width as f32).round() as u32;
+
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+
+        for y in 0..height {
+            let ay = self.center.1 + (y as f32 + 0.5 - height as f32 / 2.0) / self.zoom;
+
+            for x in 0..width {
+                let ax = self.center.0 + (x as f32 + 0.5 - width as f32 / 2.0) / self.zoom;
+
+                let outside = ax < 0.0
+                    || ay < 0.0
+                    || ax >= after.width() as f32
+                    || ay >= after.height() as f32;
+
+                let pixel = if x == split_x || x + 1 == split_x {
+                    SPLIT_LINE
+                } else if outside {
+                    BACKDROP
+                } else if x < split_x {
+                    let bx = ((ax * scale_x) as u32).min(before.width() - 1);
+                    let by = ((ay * scale_y) as u32).min(before.height() - 1);
+                    before.get_pixel(bx, by).0
+                } else {
+                    after.get_pixel(ax as u32, ay as u32).0
+                };
+
+                pixels.extend_from_slice(&pixel);
+            }
+        }
+
+        self.handle = Handle::from_pixels(width, height, pixels);
+    }
+}
+
+/// Shows a comparison at its actual size, following the mouse over it. iced
+/// has no widget telling its size or where the cursor is on it, so this one
+/// is built on iced_native directly.
+pub struct Viewer<'a, Message> {
+    preview: &'a Preview,
+    on_interaction: Box<dyn Fn(Interaction) -> Message + 'a>,
+}
+
+impl<'a, Message> Viewer<'a, Message> {
+    pub fn new(preview: &'a Preview, on_interaction: impl Fn(Interaction) -> Message + 'a) -> Self {
+        Self {
+            preview,
+            on_interaction: Box::new(on_interaction),
+        }
+    }
+}
+
+/// What dragging over the comparison does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Drag {
+    Split,
+    Pan,
+}
+
+#[derive(Default)]
+struct State {
+    drag: Option<Drag>,
+    cursor: Point,
+}
+
+impl<'a, Message, Renderer> Widget<Message, Renderer> for Viewer<'a, Message>
+where
+    Renderer: native_image::Renderer<Handle = Handle>,
+{
+    fn width(&self) -> Length {
+        Length::Fill
+    }
+
+    fn height(&self) -> Length {
+        Length::Fill
+    }
+
+    fn layout(&self, _renderer: &Renderer, limits: &layout::Limits) -> layout::Node {
+        layout::Node::new(limits.width(Length::Fill).height(Length::Fill).max())
+    }
+
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        _renderer: &Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+    ) -> event::Status {
+        let state = tree.state.downcast_mut::<State>();
+        let bounds = layout.bounds();
+        let split = |x: f32| Interaction::Split((x - bounds.x) / bounds.width);
+
+        match event {
+            Event::Window(window::Event::RedrawRequested(_)) => {
+                let size = (bounds.width.round() as u32, bounds.height.round() as u32);
+
+                if size != self.preview.viewport && size.0 > 0 && size.1 > 0 {
+                    shell.publish((self.on_interaction)(Interaction::Resized(size.0, size.1)));
+                }
+
+                event::Status::Ignored
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(button))
+                if bounds.contains(cursor_position) =>
+            {
+                state.cursor = cursor_position;
+
+                match button {
+                    mouse::Button::Left => {
+                        state.drag = Some(Drag::Split);
+                        shell.publish((self.on_interaction)(split(cursor_position.x)));
+                    }
+                    mouse::Button::Right => state.drag = Some(Drag::Pan),
+                    _ => return event::Status::Ignored,
+                }
+
+                event::Status::Captured
+            }
+            // Dragging goes on when the cursor leaves the comparison.
+            Event::Mouse(mouse::Event::CursorMoved { position }) => {
+                let previous = std::mem::replace(&mut state.cursor, position);
+
+                let interaction = match state.drag {
+                    Some(Drag::Split) => split(position.x),
+                    Some(Drag::Pan) => {
+                        Interaction::Pan(position.x - previous.x, position.y - previous.y)
+                    }
+                    None => return event::Status::Ignored,
+                };
+
+                shell.publish((self.on_interaction)(interaction));
+                event::Status::Captured
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(_)) if state.drag.is_some() => {
+                state.drag = None;
+                event::Status::Captured
+            }
+            Event::Mouse(mouse::Event::WheelScrolled { delta })
+                if bounds.contains(cursor_position) =>
+            {
+                let y = match delta {
+                    mouse::ScrollDelta::Lines { y, .. } => y,
+                    mouse::ScrollDelta::Pixels { y, .. } => y / 40.0,
+                };
+
+                shell.publish((self.on_interaction)(Interaction::Zoom(1.25_f32.powf(y))));
+                event::Status::Captured
+            }
+            _ => event::Status::Ignored,
+        }
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        _viewport: &Rectangle,
+        _renderer: &Renderer,
+    ) -> mouse::Interaction {
+        match tree.state.downcast_ref::<State>().drag {
+            Some(Drag::Split) => mouse::Interaction::ResizingHorizontally,
+            Some(Drag::Pan) => mouse::Interaction::Grabbing,
+            None if layout.bounds().contains(cursor_position) => mouse::Interaction::Crosshair,
+            None => mouse::Interaction::Idle,
+        }
+    }
+
+    fn draw(
+        &self,
+        _tree: &Tree,
+        renderer: &mut Renderer,
+        _theme: &Renderer::Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor_position: Point,
+        _viewport: &Rectangle,
+    ) {
+        let bounds = layout.bounds();
+        let (width, height) = self.preview.viewport;
+
+        // Drawn unscaled, one pixel per pixel, and cut off while a new size
+        // is being drawn.
+        renderer.with_layer(bounds, |renderer| {
+            renderer.draw(
+                self.preview.handle.clone(),
+                Rectangle {
+                    x: bounds.x,
+                    y: bounds.y,
+                    width: width as f32,
+                    height: height as f32,
+                },
+            );
+        });
+    }
+}
+
+impl<'a, Message: 'a> From<Viewer<'a, Message>> for Element<'a, Message> {
+    fn from(viewer: Viewer<'a, Message>) -> Self {
+        Element::new(viewer)
+    }
+}
@@ -0,0 +1,352 @@
+//! Objective measures of how well an output matches its input, taken by
+//! shrinking the output back to the size of the input and comparing the two.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use image::imageops::{self, FilterType};
+use image::{GrayImage, RgbImage};
+
+use crate::crop::Region;
+use crate::input;
+use crate::job::{Job, JobStatus};
+use crate::metadata;
+use crate::postprocess;
+
+/// The side of the square windows SSIM is computed over.
+const SSIM_WINDOW: u32 = 8;
+/// How far apart the windows are, so that they overlap by half.
+const SSIM_STEP: u32 = 4;
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// How far the aspect ratio of the output may be from the input's for it to
+/// still be compared.
+const ASPECT_TOLERANCE: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quality {
+    /// In dB, infinite if the images are identical.
+    pub psnr: f64,
+    /// From -1 to 1, where 1 means structurally identical.
+    pub ssim: f64,
+    /// The variance of the Laplacian of the shrunk output over the input's,
+    /// so that more than 1 means more fine detail than the input.
+    pub sharpness: f64,
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PSNR {} dB, SSIM {:.4}, sharpness {:.2}x",
+            format_psnr(self.psnr),
+            self.ssim,
+            self.sharpness
+        )
+    }
+}
+
+fn format_psnr(psnr: f64) -> String {
+    if psnr.is_finite() {
+        format!("{:.2}", psnr)
+    } else {
+        String::from("inf")
+    }
+}
+
+/// Where measuring a finished job is at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Measurement {
+    Running,
+    Done(Quality),
+    Failed(String),
+}
+
+/// Compares `output` shrunk to the size of `input`, or of `crop` of it, with
+/// the input, which is turned `upright` first if the output was.
+pub fn measure(
+    input: &Path,
+    crop: Option<Region>,
+    upright: bool,
+    output: &Path,
+) -> Result<Quality, String> {
+    let open = |path: &Path, crop| {
+        input::decode(path, crop).map_err(|e| format!("{}: {}", path.to_string_lossy(), e))
+    };
+
+    let mut original = open(input, crop)?;
+
+    if let Some(orientation) = metadata::orientation(input).filter(|_| upright) {
+        original = postprocess::orient(original, orientation);
+    }
+
+    let original = original.to_rgb8();
+    let upscaled = open(output, None)?.to_rgb8();
+    let (width, height) = original.dimensions();
+    let aspect = |(w, h): (u32, u32)| w as f64 / h as f64;
+
+    if (aspect(upscaled.dimensions()) / aspect((width, height)) - 1.0).abs() > ASPECT_TOLERANCE {
+        return Err(String::from(
+            "the output does not have the shape of the input",
+        ));
+    }
+
+    // Bicubic, as is usual when measuring upscalers this way.
+    let shrunk = imageops::resize(&upscaled, width, height, FilterType::CatmullRom);
+
+    let (original_luma, shrunk_luma) = (luma(&original), luma(&shrunk));
+
+    Ok(Quality {
+        psnr: psnr(&original, &shrunk),
+        ssim: ssim(&original_luma, &shrunk_luma),
+        // Flat images have no detail to compare, and come out as equal.
+        sharpness: (laplacian_variance(&shrunk_luma) + f64::EPSILON)
+            / (laplacian_variance(&original_luma) + f64::EPSILON),
+    })
+}
+
+fn luma(image: &RgbImage) -> GrayImage {
+    imageops::grayscale(image)
+}
+
+fn psnr(a: &RgbImage, b: &RgbImage) -> f64 {
+    let squared_error = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+        .sum::<f64>();
+    let mse = squared_error / a.as_raw().len() as f64;
+
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+}
+
+/// The mean SSIM over overlapping square windows. Images smaller than a
+/// window are compared as one.
+fn ssim(a: &GrayImage, b: &GrayImage) -> f64 {
+    let (width, height) = a.dimensions();
+    let window = (SSIM_WINDOW.min(width), SSIM_WINDOW.min(height));
+
+    let mut total = 0.0;
+    let mut count = 0;
+
+    for y in (0..=height - window.1).step_by(SSIM_STEP as usize) {
+        for x in (0..=width - window.0).step_by(SSIM_STEP as usize) {
+            let pixels = || {
+                (y..y + window.1).flat_map(move |y| {
+                    (x..x + window.0).map(move |x| {
+                        (a.get_pixel(x, y).0[0] as f64, b.get_pixel(x, y).0[0] as f64)
+                    })
+                })
+            };
+
+            let n = (window.0 * window.1) as f64;
+            let (mean_a, mean_b) = pixels().fold((0.0, 0.0), |(sa, sb), (a, b)| (sa + a, sb + b));
+            let (mean_a, mean_b) = (mean_a / n, mean_b / n);
+
+            let (var_a, var_b, covariance) =
+                pixels().fold((0.0, 0.0, 0.0), |(va, vb, cov), (a, b)| {
+                    let (da, db) = (a - mean_a, b - mean_b);
+                    (va + da * da, vb + db * db, cov + da * db)
+                });
+            let (var_a, var_b, covariance) = (var_a / n, var_b / n, covariance / n);
+
+            total += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2));
+            count += 1;
+        }
+    }
+
+    total / count as f64
+}
+
+/// How much fine detail an image has, as the variance of its Laplacian.
+fn laplacian_variance(image: &GrayImage) -> f64 {
+    let (width, height) = image.dimensions();
+
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let at = |x: u32, y: u32| image.get_pixel(x, y).0[0] as f64;
+    let values = (1..height - 1)
+        .flat_map(|y| (1..width - 1).map(move |x| (x, y)))
+        .map(|(x, y)| at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4.0 * at(x, y))
+        .collect::<Vec<_>>();
+
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+}
+
+/// The queue as CSV, one row per job, with the quality of those measured.
+pub fn report(jobs: &[Job]) -> String {
+    let field = |value: String| {
+        if value.contains([',', '"', '\n']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value
+        }
+    };
+
+    let mut csv = String::from(
+        "input,output,model,tta,status,seconds,input size,psnr,ssim,sharpness,quality error\n",
+    );
+
+    for job in jobs {
+        let (width, height) = job
+            .crop
+            .map_or(job.input.dimensions, |crop| crop.dimensions());
+        let (quality, error) = match &job.quality {
+            Some(Measurement::Done(quality)) => (
+                [
+                    format_psnr(quality.psnr),
+                    format!("{:.4}", quality.ssim),
+                    format!("{:.4}", quality.sharpness),
+                ],
+                String::new(),
+            ),
+            Some(Measurement::Failed(e)) => (Default::default(), e.clone()),
+            Some(Measurement::Running) | None => (Default::default(), String::new()),
+        };
+
+        let status = match &job.status {
+            JobStatus::Failed(reason) | JobStatus::TimedOut(reason) => {
+                format!("{}: {}", job.status, reason)
+            }
+            status => status.to_string(),
+        };
+
+        let row = [
+            PathBuf::from(&job.input_path).to_string_lossy().to_string(),
+            PathBuf::from(&job.output_path)
+                .to_string_lossy()
+                .to_string(),
+            if job.settings.model_name.is_empty() {
+                String::from(crate::DEFAULT_MODEL)
+            } else {
+                job.settings.model_name.clone()
+            },
+            job.settings.tta_mode.to_string(),
+            status,
+            job.elapsed()
+                .map(|d| d.as_secs().to_string())
+                .unwrap_or_default(),
+            format!("{}x{}", width, height),
+        ]
+        .into_iter()
+        .chain(quality)
+        .chain(Some(error))
+        .map(field)
+        .collect::<Vec<_>>();
+
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use image::{Luma, Rgb};
+
+    /// A pattern with detail at every scale, so that changes to it show.
+    fn pattern(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| {
+            Rgb([(x * 16) as u8, (y * 16) as u8, ((x ^ y) * 32) as u8])
+        })
+    }
+
+    #[test]
+    fn psnr_follows_the_mean_squared_error() {
+        let black = RgbImage::new(4, 4);
+        let grey = RgbImage::from_pixel(4, 4, Rgb([10, 10, 10]));
+
+        assert_eq!(psnr(&black, &black), f64::INFINITY);
+        // An error of 10 everywhere is an MSE of 100.
+        assert!((psnr(&black, &grey) - 10.0 * (255.0f64 * 255.0 / 100.0).log10()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ssim_is_one_only_for_identical_images() {
+        let image = luma(&pattern(16, 16));
+        let inverted = GrayImage::from_fn(16, 16, |x, y| Luma([255 - image.get_pixel(x, y).0[0]]));
+
+        assert!((ssim(&image, &image) - 1.0).abs() < 1e-9);
+        assert!(ssim(&image, &inverted) < 0.0);
+
+        // Images smaller than a window are compared as one.
+        let small = luma(&pattern(3, 5));
+        assert!((ssim(&small, &small) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn laplacian_variance_measures_detail() {
+        assert_eq!(
+            laplacian_variance(&GrayImage::from_pixel(8, 8, Luma([128]))),
+            0.0
+        );
+        assert_eq!(laplacian_variance(&GrayImage::new(2, 8)), 0.0);
+
+        let checkerboard =
+            GrayImage::from_fn(8, 8, |x, y| Luma([if (x + y) % 2 == 0 { 255 } else { 0 }]));
+        assert!(laplacian_variance(&checkerboard) > 0.0);
+    }
+
+    #[test]
+    fn measures_outputs_against_their_inputs() {
+        let dir = std::env::temp_dir().join(format!("upscaler-quality-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (input, output, stretched) = (
+            dir.join("in.png"),
+            dir.join("out.png"),
+            dir.join("wide.png"),
+        );
+
+        let original = pattern(16, 8);
+        original.save(&input).unwrap();
+        imageops::resize(&original, 32, 16, FilterType::Nearest)
+            .save(&output)
+            .unwrap();
+        imageops::resize(&original, 48, 16, FilterType::Nearest)
+            .save(&stretched)
+            .unwrap();
+
+        let whole = measure(&input, None, false, &output);
+        let cropped = measure(
+            &input,
+            Some(Region {
+                x: 0,
+                y: 0,
+                width: 8,
+                height: 4,
+            }),
+            false,
+            &output,
+        );
+        let wrong_shape = measure(&input, None, false, &stretched);
+        let missing = measure(&dir.join("missing.png"), None, false, &output);
+        let _ = fs::remove_dir_all(&dir);
+
+        let whole = whole.unwrap();
+        assert!(whole.psnr > 20.0, "{}", whole);
+        assert!(whole.ssim > 0.5, "{}", whole);
+
+        // The output of the whole input is compared with only a corner of it.
+        let cropped = cropped.unwrap();
+        assert!(cropped.psnr < whole.psnr, "{} against {}", cropped, whole);
+
+        assert!(wrong_shape.is_err());
+        assert!(missing.is_err());
+    }
+}
@@ -0,0 +1,339 @@
+use std::fmt;
+
+/// The largest number of realesrgan passes a single job may be chained into.
+const MAX_PASSES: u32 = 4;
+
+/// How the requested size of the output is given.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScaleMode {
+    #[default]
+    Factor,
+    LongestEdge,
+    Width,
+    Height,
+}
+
+impl ScaleMode {
+    pub const ALL: [ScaleMode; 4] = [
+        ScaleMode::Factor,
+        ScaleMode::LongestEdge,
+        ScaleMode::Width,
+        ScaleMode::Height,
+    ];
+
+    /// Parses the value typed next to the mode into a target.
+    pub fn parse(self, value: &str) -> Result<ScaleTarget, String> {
+        let value = value.trim();
+        let invalid = || {
+            let mode = self.to_string().to_lowercase();
+            format!("\"{}\" is not a valid {}.", value, mode)
+        };
+
+        if self == ScaleMode::Factor {
+            return match value.parse::<f64>() {
+                Ok(f) if f.is_finite() && f > 0.0 => Ok(ScaleTarget::Factor(f)),
+                _ => Err(invalid()),
+            };
+        }
+
+        let length = value
+            .parse::<u32>()
+            .ok()
+            .filter(|&length| length > 0)
+            .ok_or_else(invalid)?;
+
+        Ok(match self {
+            ScaleMode::Factor => unreachable!(),
+            ScaleMode::LongestEdge => ScaleTarget::LongestEdge(length),
+            ScaleMode::Width => ScaleTarget::Width(length),
+            ScaleMode::Height => ScaleTarget::Height(length),
+        })
+    }
+}
+
+/// Parses a target given on its own, as a factor like "2" or "2.5x", or as a
+/// length like "1920w", "1080h" or "2048px" for the longest edge.
+pub fn parse_target(value: &str) -> Result<ScaleTarget, String> {
+    let value = value.trim();
+    let (mode, number) = if let Some(width) = value.strip_suffix('w') {
+        (ScaleMode::Width, width)
+    } else if let Some(height) = value.strip_suffix('h') {
+        (ScaleMode::Height, height)
+    } else if let Some(edge) = value.strip_suffix("px") {
+        (ScaleMode::LongestEdge, edge)
+    } else {
+        (ScaleMode::Factor, value.strip_suffix('x').unwrap_or(value))
+    };
+
+    mode.parse(number)
+        .map_err(|_| format!("\"{}\" is not a valid scale.", value))
+}
+
+impl fmt::Display for ScaleMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ScaleMode::Factor => "Factor",
+            ScaleMode::LongestEdge => "Longest edge",
+            ScaleMode::Width => "Width",
+            ScaleMode::Height => "Height",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScaleTarget {
+    Factor(f64),
+    LongestEdge(u32),
+    Width(u32),
+    Height(u32),
+}
+
+impl ScaleTarget {
+    /// The mode and the value that stand for this target in the window.
+    pub fn mode_and_value(self) -> (ScaleMode, String) {
+        match self {
+            ScaleTarget::Factor(factor) => (ScaleMode::Factor, factor.to_string()),
+            ScaleTarget::LongestEdge(edge) => (ScaleMode::LongestEdge, edge.to_string()),
+            ScaleTarget::Width(width) => (ScaleMode::Width, width.to_string()),
+            ScaleTarget::Height(height) => (ScaleMode::Height, height.to_string()),
+        }
+    }
+}
+
+/// Written the way `parse_target` reads it.
+impl fmt::Display for ScaleTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScaleTarget::Factor(factor) => write!(f, "{}x", factor),
+            ScaleTarget::LongestEdge(edge) => write!(f, "{}px", edge),
+            ScaleTarget::Width(width) => write!(f, "{}w", width),
+            ScaleTarget::Height(height) => write!(f, "{}h", height),
+        }
+    }
+}
+
+/// How a single input is brought to the requested size: the CLI is run
+/// `passes` times at its native scale, and the result is then resampled to
+/// `output_size` if that alone does not land on the requested size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScalePlan {
+    pub factor: f64,
+    pub passes: u32,
+    pub output_size: Option<(u32, u32)>,
+}
+
+impl ScalePlan {
+    pub fn new(
+        target: ScaleTarget,
+        model_scale: u32,
+        dimensions: Option<(u32, u32)>,
+    ) -> Result<Self, String> {
+        let dimensions_required = || {
+            dimensions.ok_or_else(|| String::from("Unable to read the dimensions of the input."))
+        };
+
+        let (factor, exact_size) = match target {
+            ScaleTarget::Factor(f) => (f, None),
+            ScaleTarget::LongestEdge(edge) => {
+                let (w, h) = dimensions_required()?;
+                let factor = edge as f64 / w.max(h) as f64;
+
+                let size = if w >= h {
+                    (edge, scaled(h, factor))
+                } else {
+                    (scaled(w, factor), edge)
+                };
+
+                (factor, Some(size))
+            }
+            ScaleTarget::Width(width) => {
+                let (w, h) = dimensions_required()?;
+                let factor = width as f64 / w as f64;
+                (factor, Some((width, scaled(h, factor))))
+            }
+            ScaleTarget::Height(height) => {
+                let (w, h) = dimensions_required()?;
+                let factor = height as f64 / h as f64;
+                (factor, Some((scaled(w, factor), height)))
+            }
+        };
+
+        let mut passes = 1;
+        let mut reached = model_scale as f64;
+
+        while reached < factor - f64::EPSILON {
+            passes += 1;
+            reached *= model_scale as f64;
+
+            if passes > MAX_PASSES {
+                return Err(format!(
+                    "An upscale ratio of {} would take more than {} passes of the model.",
+                    format_factor(factor),
+                    MAX_PASSES
+                ));
+            }
+        }
+
+        let output_size = match exact_size {
+            Some((w, h)) => {
+                let (in_w, in_h) = dimensions_required()?;
+                let natural = (scaled(in_w, reached), scaled(in_h, reached));
+                Some((w, h)).filter(|&size| size != natural)
+            }
+            None if (reached - factor).abs() <= f64::EPSILON => None,
+            None => {
+                let (w, h) = dimensions_required()?;
+                Some((scaled(w, factor), scaled(h, factor)))
+            }
+        };
+
+        Ok(Self {
+            factor,
+            passes,
+            output_size,
+        })
+    }
+}
+
+pub fn scaled(length: u32, factor: f64) -> u32 {
+    ((length as f64 * factor).round() as u32).max(1)
+}
+
+/// Formats a factor for display and for `{scale}` in filenames, using at most
+/// two decimals.
+pub fn format_factor(factor: f64) -> String {
+    let formatted = format!("{:.2}", factor);
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+
+    formatted.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_value_typed_next_to_the_mode() {
+        let targets = [
+            (ScaleMode::Factor, "2.5", ScaleTarget::Factor(2.5)),
+            (ScaleMode::Width, " 1920 ", ScaleTarget::Width(1920)),
+            (ScaleMode::Height, "1080", ScaleTarget::Height(1080)),
+            (
+                ScaleMode::LongestEdge,
+                "2048",
+                ScaleTarget::LongestEdge(2048),
+            ),
+        ];
+
+        for (mode, value, target) in targets {
+            assert_eq!(mode.parse(value), Ok(target));
+        }
+
+        for value in ["", "0", "-2", "x", "inf"] {
+            assert!(
+                ScaleMode::Factor.parse(value).is_err(),
+                "{:?} was accepted",
+                value
+            );
+        }
+
+        for value in ["0", "1.5", "1920w"] {
+            assert!(
+                ScaleMode::Width.parse(value).is_err(),
+                "{:?} was accepted",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn parses_targets_and_writes_them_back() {
+        let targets = [
+            ("2", ScaleTarget::Factor(2.0)),
+            ("2.5x", ScaleTarget::Factor(2.5)),
+            (" 1920w ", ScaleTarget::Width(1920)),
+            ("1080h", ScaleTarget::Height(1080)),
+            ("2048px", ScaleTarget::LongestEdge(2048)),
+        ];
+
+        for (value, target) in targets {
+            assert_eq!(parse_target(value), Ok(target));
+            assert_eq!(parse_target(&target.to_string()), Ok(target));
+        }
+
+        for value in ["", "0", "-2", "0w", "1.5h", "x", "inf", "2y"] {
+            assert!(parse_target(value).is_err(), "{:?} was accepted", value);
+        }
+    }
+
+    #[test]
+    fn plans_passes_and_resampling() {
+        let plan = |target, dimensions| ScalePlan::new(target, 4, dimensions);
+
+        // The native scale of the model needs nothing else.
+        assert_eq!(
+            plan(ScaleTarget::Factor(4.0), None),
+            Ok(ScalePlan {
+                factor: 4.0,
+                passes: 1,
+                output_size: None,
+            })
+        );
+
+        // Other factors are resampled from the next pass above them.
+        assert_eq!(
+            plan(ScaleTarget::Factor(2.0), Some((100, 50))),
+            Ok(ScalePlan {
+                factor: 2.0,
+                passes: 1,
+                output_size: Some((200, 100)),
+            })
+        );
+        assert_eq!(
+            plan(ScaleTarget::Factor(16.0), None),
+            Ok(ScalePlan {
+                factor: 16.0,
+                passes: 2,
+                output_size: None,
+            })
+        );
+
+        // Lengths keep the aspect ratio, and are only resampled to if the
+        // passes do not land on them.
+        assert_eq!(
+            plan(ScaleTarget::Width(1000), Some((100, 50))),
+            Ok(ScalePlan {
+                factor: 10.0,
+                passes: 2,
+                output_size: Some((1000, 500)),
+            })
+        );
+        assert_eq!(
+            plan(ScaleTarget::LongestEdge(200), Some((25, 50))),
+            Ok(ScalePlan {
+                factor: 4.0,
+                passes: 1,
+                output_size: None,
+            })
+        );
+        assert_eq!(
+            plan(ScaleTarget::Height(75), Some((100, 50))).map(|plan| plan.output_size),
+            Ok(Some((150, 75)))
+        );
+    }
+
+    #[test]
+    fn rejects_plans_it_cannot_make() {
+        assert!(ScalePlan::new(ScaleTarget::Factor(2.0), 4, None).is_err());
+        assert!(ScalePlan::new(ScaleTarget::Width(1000), 4, None).is_err());
+        assert!(ScalePlan::new(ScaleTarget::Factor(256.0), 4, None).is_ok());
+        assert!(ScalePlan::new(ScaleTarget::Factor(300.0), 4, None).is_err());
+    }
+
+    #[test]
+    fn formats_factors_with_two_decimals_at_most() {
+        assert_eq!(format_factor(2.0), "2");
+        assert_eq!(format_factor(2.5), "2.5");
+        assert_eq!(format_factor(4.0 / 3.0), "1.33");
+    }
+}
@@ -0,0 +1,230 @@
+//! An image that a rectangle can be drawn on with the mouse, to pick the
+//! region of it to crop to. The rectangle is kept in pixels of the image,
+//! which takes knowing where the image is laid out and how far it is
+//! scaled down to fit.
+
+use iced::widget::image::Handle;
+use iced::{Color, ContentFit, Element, Length, Point, Rectangle, Size, Vector};
+use iced_native::layout::{self, Layout};
+use iced_native::renderer::{self, Quad};
+use iced_native::widget::tree::{self, Tree};
+use iced_native::widget::{self, Widget};
+use iced_native::{event, image, mouse, Clipboard, Event, Shell};
+
+const BORDER: Color = Color::from_rgb(1.0, 0.8, 0.0);
+const FILL: Color = Color::from_rgba(1.0, 0.8, 0.0, 0.2);
+
+/// Shows `handle` fitted to its bounds, with `selection` drawn over it. The
+/// selection is in fractions of the size of the image, from 0 to 1.
+pub struct Selection<Message> {
+    handle: Handle,
+    selection: Option<Rectangle>,
+    on_select: Box<dyn Fn(Rectangle) -> Message>,
+    width: Length,
+    height: Length,
+}
+
+impl<Message> Selection<Message> {
+    pub fn new(
+        handle: Handle,
+        selection: Option<Rectangle>,
+        on_select: impl Fn(Rectangle) -> Message + 'static,
+    ) -> Self {
+        Self {
+            handle,
+            selection,
+            on_select: Box::new(on_select),
+            width: Length::Fill,
+            height: Length::Fill,
+        }
+    }
+
+    pub fn width(mut self, width: Length) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn height(mut self, height: Length) -> Self {
+        self.height = height;
+        self
+    }
+
+    /// Where the image is drawn within `bounds`.
+    fn image_bounds<Renderer>(&self, renderer: &Renderer, bounds: Rectangle) -> Rectangle
+    where
+        Renderer: image::Renderer<Handle = Handle>,
+    {
+        let dimensions = renderer.dimensions(&self.handle);
+        let fitted = ContentFit::Contain.fit(
+            Size::new(dimensions.width as f32, dimensions.height as f32),
+            bounds.size(),
+        );
+
+        Rectangle {
+            x: bounds.x + (bounds.width - fitted.width).max(0.0) / 2.0,
+            y: bounds.y + (bounds.height - fitted.height).max(0.0) / 2.0,
+            width: fitted.width,
+            height: fitted.height,
+        }
+    }
+}
+
+/// Where the drag started, in fractions of the size of the image.
+#[derive(Default)]
+struct State {
+    anchor: Option<Point>,
+}
+
+/// The position of `cursor` within `image`, kept inside it.
+fn relative(image: Rectangle, cursor: Point) -> Point {
+    Point::new(
+        ((cursor.x - image.x) / image.width).clamp(0.0, 1.0),
+        ((cursor.y - image.y) / image.height).clamp(0.0, 1.0),
+    )
+}
+
+impl<Message, Renderer> Widget<Message, Renderer> for Selection<Message>
+where
+    Renderer: image::Renderer<Handle = Handle>,
+{
+    fn width(&self) -> Length {
+        self.width
+    }
+
+    fn height(&self) -> Length {
+        self.height
+    }
+
+    fn layout(&self, renderer: &Renderer, limits: &layout::Limits) -> layout::Node {
+        widget::image::layout(
+            renderer,
+            limits,
+            &self.handle,
+            self.width,
+            self.height,
+            ContentFit::Contain,
+        )
+    }
+
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        renderer: &Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+    ) -> event::Status {
+        let state = tree.state.downcast_mut::<State>();
+        let image = self.image_bounds(renderer, layout.bounds());
+
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left))
+                if image.contains(cursor_position) =>
+            {
+                state.anchor = Some(relative(image, cursor_position));
+                event::Status::Captured
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => {
+                let Some(anchor) = state.anchor else {
+                    return event::Status::Ignored;
+                };
+
+                let cursor = relative(image, cursor_position);
+                let (x, y) = (anchor.x.min(cursor.x), anchor.y.min(cursor.y));
+
+                shell.publish((self.on_select)(Rectangle {
+                    x,
+                    y,
+                    width: anchor.x.max(cursor.x) - x,
+                    height: anchor.y.max(cursor.y) - y,
+                }));
+
+                event::Status::Captured
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left))
+                if state.anchor.is_some() =>
+            {
+                state.anchor = None;
+                event::Status::Captured
+            }
+            _ => event::Status::Ignored,
+        }
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        _viewport: &Rectangle,
+        renderer: &Renderer,
+    ) -> mouse::Interaction {
+        let dragging = tree.state.downcast_ref::<State>().anchor.is_some();
+
+        if dragging
+            || self
+                .image_bounds(renderer, layout.bounds())
+                .contains(cursor_position)
+        {
+            mouse::Interaction::Crosshair
+        } else {
+            mouse::Interaction::Idle
+        }
+    }
+
+    fn draw(
+        &self,
+        _tree: &Tree,
+        renderer: &mut Renderer,
+        _theme: &Renderer::Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor_position: Point,
+        _viewport: &Rectangle,
+    ) {
+        widget::image::draw(renderer, layout, &self.handle, ContentFit::Contain);
+
+        let Some(selection) = self.selection else {
+            return;
+        };
+
+        let image = self.image_bounds(renderer, layout.bounds());
+        let bounds = Rectangle {
+            width: selection.width * image.width,
+            height: selection.height * image.height,
+            ..Rectangle::new(Point::ORIGIN, Size::ZERO)
+        } + Vector::new(
+            image.x + selection.x * image.width,
+            image.y + selection.y * image.height,
+        );
+
+        // In its own layer, so that it is drawn over the image.
+        renderer.with_layer(layout.bounds(), |renderer| {
+            renderer.fill_quad(
+                Quad {
+                    bounds,
+                    border_radius: 0.0.into(),
+                    border_width: 2.0,
+                    border_color: BORDER,
+                },
+                FILL,
+            );
+        });
+    }
+}
+
+impl<'a, Message: 'a> From<Selection<Message>> for Element<'a, Message> {
+    fn from(selection: Selection<Message>) -> Self {
+        Element::new(selection)
+    }
+}
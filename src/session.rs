@@ -0,0 +1,40 @@
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::manifest::Item;
+
+/// Where the unfinished jobs are kept when the application is closed while
+/// processing. Like the CLI, this lives in the working directory.
+const QUEUE_FILE: &str = "realesrgan-ncnn-vulkan-gui.queue.json";
+
+/// The jobs left over from a cancelled session, so that they can be resumed
+/// on the next launch with the settings they were queued with.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SavedQueue {
+    pub output_dir: PathBuf,
+    pub inputs: Vec<Item>,
+}
+
+impl SavedQueue {
+    pub fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(QUEUE_FILE, json).map_err(|e| e.to_string())
+    }
+
+    /// Returns `None` if there is no saved queue.
+    pub fn load() -> Option<Result<Self, String>> {
+        let json = match fs::read_to_string(QUEUE_FILE) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => return Some(Err(e.to_string())),
+        };
+
+        Some(serde_json::from_str(&json).map_err(|e| e.to_string()))
+    }
+
+    pub fn discard() {
+        let _ = fs::remove_file(QUEUE_FILE);
+    }
+}
@@ -0,0 +1,138 @@
+//! Thumbnails of the selected inputs, made in the background a few at a
+//! time and kept for as long as the file is unchanged.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use iced::widget::image::Handle;
+
+use crate::archive;
+use crate::input;
+use crate::video;
+
+/// The longest side of a thumbnail.
+pub const SIZE: u32 = 128;
+
+/// The longest side of the image the crop region is drawn on.
+pub const CROP_EDITOR_SIZE: u32 = 1024;
+
+/// How many thumbnails are made at the same time.
+const MAX_RUNNING: usize = 4;
+
+#[derive(Clone, Debug)]
+pub struct Thumbnail {
+    /// Videos have no thumbnail, as that would take running ffmpeg.
+    pub handle: Option<Handle>,
+    pub dimensions: Option<(u32, u32)>,
+}
+
+#[derive(Debug)]
+enum Entry {
+    Queued,
+    Running,
+    Ready(Thumbnail, Option<SystemTime>),
+    Failed(String),
+}
+
+#[derive(Debug, Default)]
+pub struct ThumbnailCache {
+    entries: HashMap<PathBuf, Entry>,
+    queue: VecDeque<PathBuf>,
+    running: usize,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+impl ThumbnailCache {
+    /// Queues a thumbnail for each of `paths` that has none yet or has
+    /// changed since. Whatever is still queued for other paths is dropped.
+    pub fn request(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        for path in self.queue.drain(..) {
+            self.entries.remove(&path);
+        }
+
+        for path in paths {
+            let cached = match self.entries.get(&path) {
+                Some(Entry::Ready(_, at)) => *at == modified(&path),
+                Some(Entry::Queued | Entry::Running) => true,
+                Some(Entry::Failed(_)) | None => false,
+            };
+
+            if !cached {
+                self.entries.insert(path.clone(), Entry::Queued);
+                self.queue.push_back(path);
+            }
+        }
+    }
+
+    /// Takes as many queued paths as can be worked on now.
+    pub fn start(&mut self) -> Vec<PathBuf> {
+        let mut started = Vec::new();
+
+        while self.running < MAX_RUNNING {
+            let Some(path) = self.queue.pop_front() else {
+                break;
+            };
+
+            self.entries.insert(path.clone(), Entry::Running);
+            self.running += 1;
+            started.push(path);
+        }
+
+        started
+    }
+
+    pub fn finish(&mut self, path: PathBuf, result: Result<Thumbnail, String>) {
+        self.running = self.running.saturating_sub(1);
+
+        let entry = match result {
+            Ok(thumbnail) => Entry::Ready(thumbnail, modified(&path)),
+            Err(e) => Entry::Failed(e),
+        };
+
+        self.entries.insert(path, entry);
+    }
+
+    /// Returns `None` while the thumbnail of `path` is being made.
+    pub fn get(&self, path: &Path) -> Option<Result<&Thumbnail, &str>> {
+        match self.entries.get(path)? {
+            Entry::Ready(thumbnail, _) => Some(Ok(thumbnail)),
+            Entry::Failed(e) => Some(Err(e)),
+            Entry::Queued | Entry::Running => None,
+        }
+    }
+}
+
+/// Decodes `path`, or the first page of an archive, and shrinks it to fit
+/// in a `size` square.
+pub fn generate(path: &Path, size: u32) -> Result<Thumbnail, String> {
+    if video::is_video(path) {
+        return Ok(Thumbnail {
+            handle: None,
+            dimensions: None,
+        });
+    }
+
+    let image = if archive::is_archive(path) {
+        archive::cover(path)?
+    } else {
+        input::decode(path, None)?
+    };
+
+    let thumbnail = image.thumbnail(size, size).to_rgba8();
+
+    Ok(Thumbnail {
+        handle: Some(Handle::from_pixels(
+            thumbnail.width(),
+            thumbnail.height(),
+            thumbnail.into_raw(),
+        )),
+        dimensions: Some((image.width(), image.height())),
+    })
+}
@@ -0,0 +1,427 @@
+//! Video inputs, read and written through a locally installed ffmpeg. The
+//! frames of a video are extracted to PNGs, upscaled one by one like the
+//! frames of an animation, and encoded again at the original frame rate,
+//! with the audio of the input copied over as is.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+use async_std::io::ReadExt;
+use async_std::process as async_process;
+use async_std::task::JoinHandle;
+use serde_json::Value;
+
+use crate::animation;
+use crate::checker;
+use crate::input::{InputFormat, InputInfo};
+use crate::postprocess::PostProcess;
+
+/// The containers offered in the input file dialog. Videos are recognised
+/// by their extension, as ffmpeg reads far more than is worth sniffing for.
+pub const EXTENSIONS: &[&str] = &["mp4", "mkv", "webm", "mov", "avi", "m4v"];
+
+/// How videos are encoded again, as set on the Output page.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VideoSettings {
+    pub ffmpeg_path: String,
+    pub codec: String,
+    pub crf: u8,
+}
+
+impl VideoSettings {
+    pub fn parse(ffmpeg_path: &str, codec: &str, crf: &str) -> Result<Self, String> {
+        let crf = crf
+            .trim()
+            .parse::<u8>()
+            .ok()
+            .filter(|crf| *crf <= 63)
+            .ok_or_else(|| format!("\"{}\" is not a valid CRF, use 0 to 63.", crf))?;
+
+        let ffmpeg_path = match ffmpeg_path.trim() {
+            "" => "ffmpeg",
+            path => path,
+        };
+
+        let codec = match codec.trim() {
+            "" => "libx264",
+            codec => codec,
+        };
+
+        Ok(Self {
+            ffmpeg_path: ffmpeg_path.to_owned(),
+            codec: codec.to_owned(),
+            crf,
+        })
+    }
+}
+
+/// A video input, with how it is to be encoded again.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Video {
+    pub info: VideoInfo,
+    pub settings: VideoSettings,
+}
+
+/// What ffprobe has to say about the video stream of an input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VideoInfo {
+    pub codec: String,
+    /// As given by ffprobe, e.g. "30000/1001", to be handed back to ffmpeg.
+    pub frame_rate: String,
+    pub has_audio: bool,
+}
+
+pub fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// ffprobe is expected to sit next to ffmpeg, as it does in every build.
+fn ffprobe_path(ffmpeg_path: &str) -> PathBuf {
+    let ffmpeg_path = Path::new(ffmpeg_path);
+    let mut file_name = OsString::from("ffprobe");
+
+    if let Some(ext) = ffmpeg_path.extension() {
+        file_name.push(".");
+        file_name.push(ext);
+    }
+
+    ffmpeg_path.with_file_name(file_name)
+}
+
+/// An ffmpeg run in the background, which can be killed.
+#[derive(Debug)]
+pub struct Ffmpeg {
+    child: async_process::Child,
+    /// Collects the error output, to tell why ffmpeg failed.
+    stderr: Option<JoinHandle<String>>,
+}
+
+impl Ffmpeg {
+    fn spawn(command: &mut async_process::Command) -> io::Result<Self> {
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let stderr = child.stderr.take().map(|mut stderr| {
+            async_std::task::spawn(async move {
+                let mut output = String::new();
+                let _ = stderr.read_to_string(&mut output).await;
+                output
+            })
+        });
+
+        Ok(Self { child, stderr })
+    }
+
+    /// How ffmpeg went, once it has exited.
+    pub async fn try_finish(&mut self) -> Option<Result<(), String>> {
+        let status = match self.child.try_status() {
+            Ok(None) => return None,
+            Ok(Some(status)) => status,
+            Err(e) => return Some(Err(e.to_string())),
+        };
+
+        let stderr = match self.stderr.take() {
+            Some(stderr) => stderr.await,
+            None => String::new(),
+        };
+
+        Some(check(status, &stderr, "ffmpeg"))
+    }
+
+    pub async fn kill(&mut self) -> io::Result<()> {
+        checker::kill(&mut self.child).await
+    }
+}
+
+/// Returns the last line of the error output of `name` if it failed.
+fn check(status: ExitStatus, stderr: &str, name: &str) -> Result<(), String> {
+    if status.success() {
+        return Ok(());
+    }
+
+    let reason = stderr
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or("no error output");
+
+    Err(format!(
+        "{} returned {}: {}",
+        name,
+        status.code().unwrap_or(-1),
+        reason.trim()
+    ))
+}
+
+/// Runs `command` and returns what it printed, or the last line of its
+/// error output if it failed.
+fn run(command: &mut Command, name: &str) -> Result<Vec<u8>, String> {
+    let output = command
+        .output()
+        .map_err(|e| format!("unable to run {}: {}", name, e))?;
+
+    check(
+        output.status,
+        &String::from_utf8_lossy(&output.stderr),
+        name,
+    )?;
+
+    Ok(output.stdout)
+}
+
+/// Reads the streams of `path` with ffprobe. The packets of the video
+/// stream are counted, as containers do not always store a frame count.
+pub fn probe(path: &Path, ffmpeg_path: &str) -> Result<InputInfo, String> {
+    let output = run(
+        Command::new(ffprobe_path(ffmpeg_path))
+            .args([
+                "-v",
+                "error",
+                "-count_packets",
+                "-of",
+                "json",
+                "-show_entries",
+            ])
+            .arg("stream=codec_type,codec_name,width,height,r_frame_rate,nb_read_packets,pix_fmt")
+            .arg(path),
+        "ffprobe",
+    )?;
+
+    let json: Value = serde_json::from_slice(&output).map_err(|e| e.to_string())?;
+    let streams = json["streams"].as_array().cloned().unwrap_or_default();
+
+    let video = streams
+        .iter()
+        .find(|stream| stream["codec_type"] == "video")
+        .ok_or_else(|| String::from("the file has no video stream"))?;
+
+    let dimension = |key: &str| video[key].as_u64().and_then(|n| u32::try_from(n).ok());
+    let (width, height) = match (dimension("width"), dimension("height")) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => (width, height),
+        _ => return Err(String::from("unable to read the size of the video")),
+    };
+
+    let frames = video["nb_read_packets"]
+        .as_str()
+        .and_then(|n| n.parse::<u32>().ok())
+        .filter(|n| *n > 0)
+        .ok_or_else(|| String::from("the video has no frames"))?;
+
+    let frame_rate = video["r_frame_rate"]
+        .as_str()
+        .filter(|rate| !rate.starts_with('0'))
+        .ok_or_else(|| String::from("unable to read the frame rate of the video"))?;
+
+    let pix_fmt = video["pix_fmt"].as_str().unwrap_or_default();
+    let bit_depth = ["16", "12", "10"]
+        .iter()
+        .find(|depth| pix_fmt.contains(*depth))
+        .map_or(8, |depth| depth.parse().unwrap_or(8));
+
+    Ok(InputInfo {
+        format: InputFormat::Video(VideoInfo {
+            codec: video["codec_name"].as_str().unwrap_or("video").to_owned(),
+            frame_rate: frame_rate.to_owned(),
+            has_audio: streams.iter().any(|stream| stream["codec_type"] == "audio"),
+        }),
+        dimensions: (width, height),
+        bit_depth,
+        has_alpha: false,
+        extension_mismatch: false,
+        frames,
+    })
+}
+
+/// Reads the size of the video written to `path`, to check that it is one.
+pub fn dimensions(path: &Path, ffmpeg_path: &str) -> Result<(u32, u32), String> {
+    let output = run(
+        Command::new(ffprobe_path(ffmpeg_path))
+            .args(["-v", "error", "-select_streams", "v:0", "-of", "csv=p=0"])
+            .args(["-show_entries", "stream=width,height"])
+            .arg(path),
+        "ffprobe",
+    )?;
+
+    let output = String::from_utf8_lossy(&output);
+    let mut size = output.trim().split(',').map(|n| n.parse::<u32>().ok());
+
+    match (size.next().flatten(), size.next().flatten()) {
+        (Some(width), Some(height)) => Ok((width, height)),
+        _ => Err(String::from("the output has no video stream")),
+    }
+}
+
+/// The image2 pattern ffmpeg reads or writes the frames in `dir` with, for
+/// a file name like "frame-%05d.png".
+fn frame_pattern(dir: &Path, file_name: &str) -> OsString {
+    // A literal '%' in the directory would be read as part of the pattern.
+    let mut pattern = OsString::from(dir.to_string_lossy().replace('%', "%%"));
+    pattern.push(std::path::MAIN_SEPARATOR_STR);
+    pattern.push(file_name);
+    pattern
+}
+
+/// Starts extracting every frame of `input` to `dir`, as named by
+/// `animation::frame_path`.
+pub fn extract(input: &Path, dir: &Path, video: &Video) -> io::Result<Ffmpeg> {
+    let frames_dir = animation::step_dir(dir, 0);
+    fs::create_dir_all(&frames_dir)?;
+
+    Ffmpeg::spawn(
+        async_process::Command::new(&video.settings.ffmpeg_path)
+            .args(["-nostdin", "-v", "error", "-i"])
+            .arg(input)
+            .args(["-map", "0:v:0", "-start_number", "0"])
+            .arg(frame_pattern(&frames_dir, animation::FRAME_PATTERN)),
+    )
+}
+
+/// Counts the frames `extract` has written to `dir`.
+pub fn count_frames(dir: &Path) -> Result<u32, String> {
+    let mut frames = 0;
+
+    while animation::frame_path(dir, frames).exists() {
+        frames += 1;
+    }
+
+    match frames {
+        0 => Err(String::from("ffmpeg did not extract any frames")),
+        frames => Ok(frames),
+    }
+}
+
+/// Resizes the `count` upscaled frames in `from` into `to`, as
+/// `post_process` asks.
+pub fn resize_frames(
+    from: &Path,
+    to: &Path,
+    count: u32,
+    post_process: &PostProcess,
+) -> Result<(), String> {
+    fs::create_dir_all(to).map_err(|e| e.to_string())?;
+
+    for frame in 0..count {
+        let file_name = animation::frame_file_name(frame);
+        post_process.run_frame(&from.join(&file_name), &to.join(&file_name))?;
+    }
+
+    Ok(())
+}
+
+/// Starts encoding the upscaled frames of `input`, kept in `frames`, into
+/// `output`.
+pub fn encode(input: &Path, video: &Video, frames: &Path, output: &Path) -> io::Result<Ffmpeg> {
+    let (info, settings) = (&video.info, &video.settings);
+
+    let mut command = async_process::Command::new(&settings.ffmpeg_path);
+    command
+        .args(["-nostdin", "-y", "-v", "error"])
+        .args(["-framerate", &info.frame_rate, "-start_number", "0", "-i"])
+        .arg(frame_pattern(frames, animation::FRAME_PATTERN))
+        .arg("-i")
+        .arg(input)
+        .args(["-map", "0:v:0"]);
+
+    if info.has_audio {
+        command.args(["-map", "1:a", "-c:a", "copy", "-shortest"]);
+    }
+
+    // Most encoders only take even sizes with 4:2:0 chroma subsampling.
+    command
+        .args(["-c:v", &settings.codec, "-crf", &settings.crf.to_string()])
+        .args([
+            "-pix_fmt",
+            "yuv420p",
+            "-vf",
+            "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+        ])
+        .arg(output);
+
+    Ffmpeg::spawn(&mut command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wait(mut ffmpeg: Ffmpeg) -> Result<(), String> {
+        async_std::task::block_on(async {
+            loop {
+                if let Some(result) = ffmpeg.try_finish().await {
+                    return result;
+                }
+
+                async_std::task::sleep(std::time::Duration::from_millis(20)).await;
+            }
+        })
+    }
+
+    /// Extracts the frames of a generated clip and encodes them again, with
+    /// the frames copied from one step to the next in place of realesrgan.
+    #[test]
+    #[ignore = "needs ffmpeg and ffprobe on the PATH"]
+    fn frames_round_trip() {
+        let dir = std::env::temp_dir().join(format!("upscaler-video-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.mp4");
+
+        run(
+            Command::new("ffmpeg")
+                .args(["-v", "error", "-y", "-f", "lavfi"])
+                .args(["-i", "testsrc=size=64x48:rate=25:duration=1"])
+                .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+                .arg(&input),
+            "ffmpeg",
+        )
+        .unwrap();
+
+        let info = probe(&input, "ffmpeg").unwrap();
+        let InputFormat::Video(video_info) = info.format.clone() else {
+            panic!("not probed as a video");
+        };
+        assert_eq!(info.frames, 25);
+        assert_eq!(video_info.frame_rate, "25/1");
+
+        let video = Video {
+            info: video_info,
+            settings: VideoSettings::parse("", "", "23").unwrap(),
+        };
+        let frames_dir = dir.join("frames");
+
+        wait(extract(&input, &frames_dir, &video).unwrap()).unwrap();
+        assert_eq!(count_frames(&frames_dir), Ok(25));
+
+        let upscaled = animation::step_dir(&frames_dir, 1);
+        fs::create_dir_all(&upscaled).unwrap();
+        for frame in 0..25 {
+            let file_name = animation::frame_file_name(frame);
+            fs::copy(
+                animation::frame_path(&frames_dir, frame),
+                upscaled.join(file_name),
+            )
+            .unwrap();
+        }
+
+        let output = dir.join("output.mp4");
+        wait(encode(&input, &video, &upscaled, &output).unwrap()).unwrap();
+
+        let encoded = probe(&output, "ffmpeg").unwrap();
+        let result = (encoded.frames, encoded.dimensions, encoded.format);
+        let _ = fs::remove_dir_all(&dir);
+
+        let (frames, dimensions, InputFormat::Video(encoded_info)) = result else {
+            panic!("not encoded as a video");
+        };
+        assert_eq!(frames, 25);
+        assert_eq!(dimensions, (64, 48));
+        assert_eq!(encoded_info.frame_rate, "25/1");
+    }
+}
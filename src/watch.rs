@@ -0,0 +1,188 @@
+//! Watching a folder for new inputs, so that they can be queued as soon as
+//! whatever is writing them is done.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use iced::futures::channel::mpsc;
+use iced::futures::SinkExt;
+use iced::Subscription;
+use notify::event::{EventKind, ModifyKind, RenameMode};
+use notify::{RecursiveMode, Watcher};
+
+use crate::input;
+use crate::partial;
+use crate::Message;
+
+/// How often the files waiting to be written are looked at.
+const TICK: Duration = Duration::from_millis(500);
+/// How long a file has to keep its size and modification time before it is
+/// taken to be fully written.
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+/// Notifications are not delivered for every file system, network shares
+/// especially, so the folder is also scanned now and then.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A file that was seen being written, and how it looked last time.
+struct Candidate {
+    len: u64,
+    modified: Option<SystemTime>,
+    unchanged_since: Instant,
+}
+
+/// Reports each supported file in `dir`, and its subfolders if `recursive`,
+/// once it has stopped changing. Files already there when watching starts
+/// are reported too.
+pub fn watch(dir: PathBuf, recursive: bool) -> Subscription<Message> {
+    struct Watch;
+
+    iced::subscription::channel(
+        (std::any::TypeId::of::<Watch>(), dir.clone(), recursive),
+        100,
+        move |mut output| {
+            let dir = dir.clone();
+
+            async move {
+                let (sender, mut events) = mpsc::unbounded();
+                let mode = if recursive {
+                    RecursiveMode::Recursive
+                } else {
+                    RecursiveMode::NonRecursive
+                };
+
+                // Kept alive for as long as the folder is watched. Without
+                // it, new files are only found by scanning.
+                let watcher =
+                    notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                        if let Ok(event) = event {
+                            // Renames give the old path first, if both.
+                            let removed = |i| match event.kind {
+                                EventKind::Remove(_)
+                                | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => true,
+                                EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => i == 0,
+                                _ => false,
+                            };
+
+                            for (i, path) in event.paths.iter().enumerate() {
+                                let _ = sender.unbounded_send((path.clone(), removed(i)));
+                            }
+                        }
+                    })
+                    .and_then(|mut watcher| watcher.watch(&dir, mode).map(|()| watcher));
+
+                if let Err(e) = &watcher {
+                    let message = format!("falling back to scanning it every few seconds: {}", e);
+                    let _ = output.send(Message::WatchFailed(message)).await;
+                }
+
+                let mut candidates = HashMap::new();
+                let mut seen = HashSet::new();
+                let mut last_scan = None::<Instant>;
+
+                loop {
+                    if last_scan.is_none_or(|at| at.elapsed() >= RESCAN_INTERVAL) {
+                        let mut files = Vec::new();
+
+                        if let Err(e) = scan(&dir, recursive, &mut files) {
+                            let message =
+                                format!("unable to read {}: {}", dir.to_string_lossy(), e);
+                            let _ = output.send(Message::WatchFailed(message)).await;
+                        }
+
+                        // Files that are gone are new again if they come back.
+                        let files = files.into_iter().collect::<HashSet<_>>();
+                        seen.retain(|path| files.contains(path));
+
+                        for file in files {
+                            if !seen.contains(&file) {
+                                candidates.entry(file).or_insert(None);
+                            }
+                        }
+
+                        last_scan = Some(Instant::now());
+                    }
+
+                    while let Ok(Some((path, removed))) = events.try_next() {
+                        if removed {
+                            seen.remove(&path);
+                        } else if !seen.contains(&path) {
+                            candidates.entry(path).or_insert(None);
+                        }
+                    }
+
+                    let mut ready = Vec::new();
+
+                    candidates.retain(|path: &PathBuf, candidate: &mut Option<Candidate>| {
+                        let Ok(metadata) = fs::metadata(path) else {
+                            // Deleted or renamed before it was done.
+                            return false;
+                        };
+
+                        if !metadata.is_file() {
+                            return false;
+                        }
+
+                        let (len, modified) = (metadata.len(), metadata.modified().ok());
+
+                        match candidate {
+                            Some(c) if c.len == len && c.modified == modified => {
+                                // Writers may still hold the file open
+                                // exclusively, on Windows in particular.
+                                if c.unchanged_since.elapsed() >= SETTLE_TIME
+                                    && File::open(path).is_ok()
+                                {
+                                    ready.push(path.clone());
+                                    return false;
+                                }
+                            }
+                            _ => {
+                                *candidate = Some(Candidate {
+                                    len,
+                                    modified,
+                                    unchanged_since: Instant::now(),
+                                })
+                            }
+                        }
+
+                        true
+                    });
+
+                    for path in ready {
+                        seen.insert(path.clone());
+
+                        if !partial::is_temporary(&path) && input::is_supported(&path) {
+                            let _ = output.send(Message::WatchFound(path)).await;
+                        }
+                    }
+
+                    async_std::task::sleep(TICK).await;
+                }
+            }
+        },
+    )
+}
+
+/// Lists the files in `dir`, and in its subfolders if `recursive`, leaving
+/// out those jobs are still writing.
+fn scan(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if partial::is_temporary(&path) {
+            continue;
+        }
+
+        if path.is_dir() {
+            if recursive {
+                // A subfolder that cannot be read is skipped, not the scan.
+                let _ = scan(&path, recursive, files);
+            }
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}